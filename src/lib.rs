@@ -0,0 +1,3 @@
+pub mod protocol;
+pub mod system;
+pub mod tools;
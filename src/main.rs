@@ -1,6 +1,91 @@
-mod system;
+use rush_rs::{ protocol, system, tools };
+
+const USAGE: &str = "usage: rush-rs [--params <file>] [command]
+
+    --params <file>         evaluate with tuned weights from a tune --out file
+
+commands:
+    uci                     run the UCI protocol
+    xboard                  run the XBoard/CECP protocol
+                            (without a command the first line picks the protocol)
+    play [--fen <fen>] [--engine white|black|none] [--depth <n> | --nodes <n> | --time <ms>]
+            [--unicode] [--flip]
+                            play or analyse against the engine at the terminal
+    mate <n> [--checks] <fen>
+                            prove or refute mate in n moves
+    puzzles <file.csv> [--nodes <n> | --time <ms>] [--limit <n>]
+            [--min-rating <r>] [--max-rating <r>] [--no-ambiguity] [--verbose]
+                            solve a lichess puzzle export and report the solve rate
+    rush <file.csv> [--human] [--seed <n>] [--clock <secs>] [--strikes <n>]
+            [--nodes <n> | --time <ms>]
+                            puzzle rush with rising ratings and a strike limit
+    mine-puzzles <file.pgn>... [--out <file.csv>] [--nodes <n>] [--gap <cp>]
+            [--max-moves <n>]
+                            find blunders with a unique winning reply in PGN games
+    annotate <file.pgn> [--out <file.pgn>] [--nodes <n>]
+                            mark inaccuracies, mistakes and blunders in PGN games
+    match <engine1> <engine2> [--games <n>] [--tc <secs>+<inc>]
+            [--openings <file.epd|file.pgn>] [--pgn <out.pgn>] [--option <name>=<value>]
+            [--sprt] [--elo0 <elo>] [--elo1 <elo>] [--alpha <a>] [--beta <b>]
+            [--resign-moves <n>] [--resign-score <cp>] [--draw-after <move>]
+            [--draw-moves <n>] [--draw-score <cp>] [--max-moves <n>]
+                            play two UCI engines against each other, with Elo and SPRT
+    testsuite <file.epd>... [--nodes <n> | --time <ms>] [--json <file.json>] [--verbose]
+                            check bm/am/dm answers of EPD test suites
+    tune <file>... [--epochs <n>] [--lr <rate>] [--optimizer adam|gd]
+            [--params <file>] [--out <file>] [--limit <n>]
+                            fit evaluation weights to labelled positions (Texel tuning)
+    datagen [--games <n>] [--threads <n>] [--nodes <n>] [--random-plies <n>] [--seed <n>]
+            [--bin <file>] [--text <file>]
+                            generate labelled positions by self-play";
 
 fn main() {
-    println!("Hello world!");
-    // TODO: build engine functionality; board should be done? 
-}
\ No newline at end of file
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // must come before anything evaluates a position
+    if args.first().map(String::as_str) == Some("--params") {
+        let loaded = args.get(1)
+            .ok_or_else(|| USAGE.to_string())
+            .and_then(|path| system::EvalParams::load(path))
+            .and_then(system::EvalParams::set_global);
+        if let Err(e) = loaded {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        args.drain(..2);
+    }
+
+    let result = match args.first().map(String::as_str) {
+        None => {
+            protocol::run();
+            Ok(())
+        },
+        Some("uci") => {
+            protocol::Uci::new().run();
+            Ok(())
+        },
+        Some("xboard") => {
+            protocol::XBoard::new().run();
+            Ok(())
+        },
+        Some("play") => tools::play::run(&args[1..]),
+        Some("mate") => tools::mate::run(&args[1..]),
+        Some("puzzles") => tools::puzzles::run(&args[1..]),
+        Some("rush") => tools::rush::run(&args[1..]),
+        Some("mine-puzzles") => tools::mine::run(&args[1..]),
+        Some("annotate") => tools::annotate::run(&args[1..]),
+        Some("match") => tools::engine_match::run(&args[1..]),
+        Some("testsuite") => tools::testsuite::run(&args[1..]),
+        Some("tune") => tools::tune::run(&args[1..]),
+        Some("datagen") => tools::datagen::run(&args[1..]),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        Some(other) => Err(format!("unknown command '{}'\n{}", other, USAGE)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+//! Protocol front ends
+//!
+//! UCI and XBoard/CECP. Run without a subcommand the engine picks one from
+//! the first command it receives
+use std::io::{ self, BufRead };
+
+pub mod output;
+pub mod uci;
+pub mod xboard;
+
+pub use output::{ Output, SharedBuffer };
+pub use uci::Uci;
+pub use xboard::XBoard;
+
+/// Reads commands from stdin: `xboard` as the first command starts the
+/// XBoard front end, anything else (normally `uci`) the UCI one.
+pub fn run() {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines().map_while(Result::ok);
+    let Some(first) = lines.find(|line| !line.trim().is_empty()) else { return };
+    let lines = std::iter::once(first.clone()).chain(lines);
+    if first.trim() == "xboard" {
+        XBoard::new().run_lines(lines);
+    } else {
+        Uci::new().run_lines(lines);
+    }
+}
@@ -0,0 +1,48 @@
+//! Shared protocol output
+//! 
+//! Lines written by the command loop and by search threads go through
+//! one lock so they never interleave
+use std::fmt::Display;
+use std::io::{ self, Write };
+use std::sync::{ Arc, Mutex };
+
+#[derive(Clone)]
+pub struct Output(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl Output {
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+
+    pub fn line(&self, text: impl Display) {
+        let mut writer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        // a closed pipe means the GUI is gone; nothing useful left to do with the error
+        let _ = writeln!(writer, "{}", text);
+        let _ = writer.flush();
+    }
+}
+
+// in-memory writer whose contents can be read back, used by protocol tests
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
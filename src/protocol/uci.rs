@@ -0,0 +1,478 @@
+//! UCI protocol front end
+//! 
+//! Reads commands from stdin and answers on stdout.
+//! Searches run on a worker thread so `stop` and `isready` stay responsive
+use std::io::{ self, BufRead };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+use super::Output;
+use crate::system::{ format_score, parse_fen, try_parse_fen, Board, Color, GameClock, MateResult, MateSearch, MoveGenerator, Network, SearchLimits, Searcher, DEFAULT_HASH_MB, DEFAULT_MOVE_OVERHEAD, START_FEN };
+
+const MAX_MULTIPV: usize = 64;
+const GO_KEYWORDS: [&str; 12] = ["searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite"];
+
+pub struct Uci {
+    board: Board,
+    mg: &'static MoveGenerator,
+    out: Output,
+    // owned by the worker thread while a search runs
+    searcher: Option<Searcher>,
+    worker: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    // cleared by ponderhit
+    ponder: Arc<AtomicBool>,
+    // lines to report per search (MultiPV option)
+    multipv: usize,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Self::with_output(Output::stdout())
+    }
+
+    pub fn with_output(out: Output) -> Self {
+        let searcher = Searcher::new(DEFAULT_HASH_MB);
+        Self {
+            board: parse_fen(START_FEN).into(),
+            mg: MoveGenerator::global(),
+            out,
+            stop: searcher.stop_handle(),
+            ponder: searcher.ponder_handle(),
+            searcher: Some(searcher),
+            worker: None,
+            multipv: 1,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        self.run_lines(stdin.lock().lines().map_while(Result::ok));
+    }
+
+    pub fn run_lines(&mut self, lines: impl Iterator<Item = String>) {
+        for line in lines {
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.abort();
+    }
+
+    // handles one command line; returns false once the GUI asks us to quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = tokens.first() else { return true };
+
+        match command {
+            "uci" => {
+                self.out.line(format!("id name rush-rs {}", env!("CARGO_PKG_VERSION")));
+                self.out.line("id author BoostedJ");
+                self.out.line(format!("option name Hash type spin default {} min 1 max 4096", DEFAULT_HASH_MB));
+                self.out.line("option name Threads type spin default 1 min 1 max 256");
+                self.out.line(format!("option name Move Overhead type spin default {} min 0 max 5000", DEFAULT_MOVE_OVERHEAD.as_millis()));
+                self.out.line(format!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV));
+                self.out.line("option name Ponder type check default false");
+                self.out.line("option name Clear Hash type button");
+                self.out.line("option name EvalFile type string default <empty>");
+                self.out.line("uciok");
+            },
+            "isready" => self.out.line("readyok"),
+            "setoption" => self.set_option(&tokens[1..]),
+            "ucinewgame" => {
+                self.abort();
+                self.board.reset();
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.clear();
+                }
+            },
+            "position" => {
+                self.abort();
+                if let Err(e) = self.position(&tokens[1..]) {
+                    self.out.line(format!("info string {}", e));
+                }
+            },
+            "go" => self.go(&tokens[1..]),
+            // the predicted move was played: the running search now counts our time
+            "ponderhit" => self.ponder.store(false, Ordering::Relaxed),
+            "stop" => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.wait();
+            },
+            "quit" => {
+                self.abort();
+                return false;
+            },
+            _ => self.out.line(format!("info string unknown command '{}'", command)),
+        }
+        true
+    }
+
+    // blocks until the running search (if any) has printed its bestmove
+    pub fn wait(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.searcher = Some(worker.join().expect("search thread panicked"));
+        }
+    }
+
+    fn abort(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        // setoption name <id...> [value <x...>]
+        let value_at = args.iter().position(|&t| t == "value").unwrap_or(args.len());
+        let name = args.get(1..value_at).unwrap_or_default().join(" ").to_ascii_lowercase();
+        let value = args.get(value_at + 1..).unwrap_or_default().join(" ");
+
+        self.abort();
+        let Some(searcher) = self.searcher.as_mut() else { return };
+        match name.as_str() {
+            "multipv" => match value.parse::<usize>() {
+                Ok(lines) => self.multipv = lines.clamp(1, MAX_MULTIPV),
+                Err(_) => self.out.line(format!("info string invalid MultiPV value '{}'", value)),
+            },
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => searcher.resize_hash(mb.clamp(1, 4096)),
+                Err(_) => self.out.line(format!("info string invalid Hash value '{}'", value)),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => searcher.set_threads(threads.clamp(1, 256)),
+                Err(_) => self.out.line(format!("info string invalid Threads value '{}'", value)),
+            },
+            "move overhead" => match value.parse::<u64>() {
+                Ok(ms) => searcher.set_move_overhead(Duration::from_millis(ms.min(5000))),
+                Err(_) => self.out.line(format!("info string invalid Move Overhead value '{}'", value)),
+            },
+            "clear hash" => searcher.clear(),
+            // the GUI only tells us whether pondering is allowed; it still sends go ponder
+            "ponder" => {},
+            // an empty value goes back to the hand-crafted evaluation
+            "evalfile" if value.is_empty() || value == "<empty>" => searcher.set_network(None),
+            "evalfile" => match Network::load(&value) {
+                Ok(network) => {
+                    self.out.line(format!("info string loaded network {} with {} hidden neurons", value, network.hidden()));
+                    searcher.set_network(Some(Arc::new(network)));
+                },
+                Err(e) => self.out.line(format!("info string {}", e)),
+            },
+            _ => self.out.line(format!("info string unknown option '{}'", name)),
+        }
+    }
+
+    fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        let moves_at = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+        // built on the side so a bad FEN or move leaves the previous position in place
+        let mut board: Board = match args.first() {
+            Some(&"startpos") => parse_fen(START_FEN).into(),
+            Some(&"fen") => try_parse_fen(&args[1..moves_at].join(" "))?.into(),
+            _ => return Err("expected 'startpos' or 'fen'".to_string()),
+        };
+
+        for text in args.iter().skip(moves_at + 1) {
+            let mv = self.mg.parse_move(&mut board, text)
+                .ok_or_else(|| format!("illegal move '{}'", text))?;
+            board.make_move(mv);
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.abort();
+        let value = |name: &str| -> Option<u64> {
+            args.iter()
+                .position(|&t| t == name)
+                .and_then(|i| args.get(i + 1))
+                .and_then(|v| v.parse().ok())
+        };
+
+        if let Some(max_moves) = value("mate") {
+            self.spawn(false, move |searcher, board, out| mate_job(board, max_moves as u32, searcher.stop_handle(), out));
+            return;
+        }
+
+        let mut limits = SearchLimits {
+            depth: value("depth").map(|d| d as u32),
+            nodes: value("nodes"),
+            movetime: value("movetime").map(Duration::from_millis),
+            multipv: self.multipv,
+            ..SearchLimits::default()
+        };
+        // some GUIs send negative times once the clock has run out
+        let millis = |name: &str| -> Option<Duration> {
+            args.iter()
+                .position(|&t| t == name)
+                .and_then(|i| args.get(i + 1))
+                .and_then(|v| v.parse::<i64>().ok())
+                .map(|ms| Duration::from_millis(ms.max(0) as u64))
+        };
+        let (time, inc) = match self.board.side_to_move {
+            Color::White => (millis("wtime"), millis("winc")),
+            Color::Black => (millis("btime"), millis("binc")),
+        };
+        limits.clock = time.map(|remaining| GameClock {
+            remaining,
+            increment: inc.unwrap_or_default(),
+            moves_to_go: value("movestogo").map(|n| n as u32),
+        });
+
+        // searchmoves runs up to the next keyword
+        if let Some(at) = args.iter().position(|&t| t == "searchmoves") {
+            for text in args[at + 1..].iter().take_while(|t| !GO_KEYWORDS.contains(t)) {
+                match self.mg.parse_move(&mut self.board, text) {
+                    Some(mv) => limits.searchmoves.push(mv),
+                    None => self.out.line(format!("info string illegal searchmove '{}'", text)),
+                }
+            }
+        }
+        let ponder = args.contains(&"ponder");
+
+        self.spawn(ponder, move |searcher, board, out| {
+            let result = searcher.search_with(board, limits, |info| {
+                let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+                out.line(format!("info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+                    info.depth, info.seldepth, info.multipv, format_score(info.score), info.nodes, info.nps(),
+                    info.hashfull, info.time.as_millis(), pv.join(" ")));
+            });
+            // the reply we expect is the one to think about on the opponent's time
+            match (result.best_move, result.pv.get(1)) {
+                (Some(mv), Some(reply)) => out.line(format!("bestmove {} ponder {}", mv, reply)),
+                (Some(mv), None) => out.line(format!("bestmove {}", mv)),
+                (None, _) => out.line("bestmove 0000"),
+            }
+        });
+    }
+
+    fn spawn(&mut self, ponder: bool, job: impl FnOnce(&mut Searcher, &mut Board, &Output) + Send + 'static) {
+        let mut searcher = self.searcher.take().unwrap_or_default();
+        let mut board = self.board.clone();
+        let out = self.out.clone();
+        self.stop = searcher.stop_handle();
+        self.stop.store(false, Ordering::Relaxed);
+        // set before the search starts so an early ponderhit is not lost
+        self.ponder = searcher.ponder_handle();
+        self.ponder.store(ponder, Ordering::Relaxed);
+        self.worker = Some(thread::spawn(move || {
+            job(&mut searcher, &mut board, &out);
+            searcher
+        }));
+    }
+}
+
+fn mate_job(board: &mut Board, max_moves: u32, stop: Arc<AtomicBool>, out: &Output) {
+    let mg = MoveGenerator::global();
+    let mut search = MateSearch::new(mg).with_stop(stop);
+    let result = search.search(board, max_moves);
+    match &result {
+        MateResult::Mate { moves, line } => {
+            let pv: Vec<String> = line.iter().map(|mv| mv.to_string()).collect();
+            out.line(format!("info depth {} nodes {} score mate {} pv {}", line.len(), search.nodes(), moves, pv.join(" ")));
+        },
+        MateResult::NoMate { max_moves } => out.line(format!("info string no mate in {} (nodes {})", max_moves, search.nodes())),
+        MateResult::Stopped { searched } => out.line(format!("info string stopped, no mate in {} (nodes {})", searched, search.nodes())),
+    }
+    // without a proof any legal move will do
+    match result.best_move().or_else(|| mg.legal_moves(board).first().copied()) {
+        Some(mv) => out.line(format!("bestmove {}", mv)),
+        None => out.line("bestmove 0000"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+    use crate::protocol::SharedBuffer;
+
+    fn run_commands(commands: &[&str]) -> String {
+        let buffer = SharedBuffer::default();
+        let mut uci = Uci::with_output(Output::new(Box::new(buffer.clone())));
+        for command in commands {
+            uci.handle(command);
+        }
+        uci.wait();
+        buffer.contents()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let output = run_commands(&["uci", "isready"]);
+        assert!(output.contains("option name Hash"));
+        assert!(output.contains("uciok"));
+        assert!(output.ends_with("readyok\n"));
+
+        let mut uci = Uci::with_output(Output::new(Box::new(SharedBuffer::default())));
+        assert!(!uci.handle("quit"));
+    }
+
+    #[test]
+    fn test_go_mate() {
+        let output = run_commands(&[
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "go mate 2",
+        ]);
+        assert!(output.contains("score mate 1 pv a1a8"));
+        assert!(output.ends_with("bestmove a1a8\n"));
+    }
+
+    #[test]
+    fn test_stop_mate_search() {
+        let start = Instant::now();
+        let output = run_commands(&["position startpos", "go mate 20", "stop"]);
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+        assert!(output.contains("info string stopped, no mate in"), "{}", output);
+        assert!(output.ends_with("\n") && output.lines().last().unwrap().starts_with("bestmove "), "{}", output);
+    }
+
+    #[test]
+    fn test_bad_position_fen() {
+        let output = run_commands(&[
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "position fen 8/8/8 w - - 0 1",
+            "position fen 8/8/8/8/8/8/8/K7 w - - 0 1",
+            "go mate 2",
+        ]);
+        assert_eq!(output.matches("info string ").count(), 2, "{}", output);
+        assert!(output.ends_with("bestmove a1a8\n"));
+    }
+
+    #[test]
+    fn test_illegal_position_moves() {
+        let buffer = SharedBuffer::default();
+        let mut uci = Uci::with_output(Output::new(Box::new(buffer.clone())));
+        uci.handle("position startpos moves d2d4");
+        let before = uci.board.to_fen();
+
+        uci.handle("position startpos moves e2e4 e7e9");
+        assert!(buffer.contents().contains("info string illegal move 'e7e9'"));
+        assert_eq!(uci.board.to_fen(), before);
+        assert_eq!(uci.board.ply(), 1);
+    }
+
+    #[test]
+    fn test_position_with_moves() {
+        // fool's mate after the moves are applied
+        let output = run_commands(&[
+            "position startpos moves f2f3 e7e5 g2g4",
+            "go depth 3",
+        ]);
+        assert!(output.contains("score mate 1"));
+        assert!(output.ends_with("bestmove d8h4\n"));
+    }
+
+    #[test]
+    fn test_eval_file() {
+        let path = std::env::temp_dir().join(format!("rush-rs-uci-{}.nnue", std::process::id()));
+        let hidden = 16;
+        let network = Network::new(hidden, vec![1; 768 * hidden], vec![0; hidden], vec![1; 2 * hidden], 0).unwrap();
+        std::fs::write(&path, network.to_bytes()).unwrap();
+
+        let output = run_commands(&[
+            &format!("setoption name EvalFile value {}", path.display()),
+            "setoption name EvalFile value missing.nnue",
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "go depth 2",
+        ]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(output.contains("loaded network"));
+        assert!(output.contains("info string missing.nnue:"));
+        assert!(output.ends_with("bestmove a1a8\n"));
+    }
+
+    #[test]
+    fn test_multipv() {
+        // only a1a8 mates, so the other lines score below it
+        let output = run_commands(&[
+            "setoption name MultiPV value 3",
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "go depth 4",
+        ]);
+        let last: Vec<&str> = output.lines().filter(|l| l.starts_with("info depth 4 ")).collect();
+        assert_eq!(last.len(), 3);
+        for (index, line) in last.iter().enumerate() {
+            assert!(line.contains(&format!(" multipv {} ", index + 1)), "{}", line);
+        }
+        assert!(last[0].contains("score mate 1 "));
+        assert!(!last[1].contains("score mate"));
+        assert!(output.ends_with("bestmove a1a8\n"));
+    }
+
+    #[test]
+    fn test_searchmoves() {
+        // the mate is not among the allowed moves
+        let output = run_commands(&[
+            "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "go searchmoves a1a2 g1f2 depth 3",
+        ]);
+        assert!(!output.contains("score mate"));
+        assert!(output.contains("bestmove a1a2") || output.contains("bestmove g1f2"), "{}", output);
+
+        let output = run_commands(&["position startpos", "go searchmoves e2e5 e2e4 depth 2"]);
+        assert!(output.contains("info string illegal searchmove 'e2e5'"));
+        assert!(output.contains("bestmove e2e4"));
+    }
+
+    #[test]
+    fn test_ponder() {
+        let buffer = SharedBuffer::default();
+        let mut uci = Uci::with_output(Output::new(Box::new(buffer.clone())));
+        uci.handle("position startpos moves e2e4 e7e5");
+        // the clock would allow only a few milliseconds, but it doesn't run yet
+        uci.handle("go ponder wtime 300 btime 300");
+        thread::sleep(Duration::from_millis(300));
+        assert!(buffer.contents().contains("info depth"));
+        assert!(!buffer.contents().contains("bestmove"));
+
+        let hit = std::time::Instant::now();
+        uci.handle("ponderhit");
+        uci.wait();
+        assert!(hit.elapsed() < Duration::from_millis(300), "{:?}", hit.elapsed());
+        let output = buffer.contents();
+        let bestmove = output.lines().last().unwrap();
+        assert!(bestmove.starts_with("bestmove ") && bestmove.contains(" ponder "), "{}", bestmove);
+
+        // a ponder miss ends with stop
+        uci.handle("go ponder depth 1");
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(buffer.contents().matches("bestmove").count(), 1);
+        uci.handle("stop");
+        assert_eq!(buffer.contents().matches("bestmove").count(), 2);
+    }
+
+    #[test]
+    fn test_stop_infinite_search() {
+        let output = run_commands(&[
+            "setoption name Hash value 2",
+            "setoption name Threads value 3",
+            "position startpos",
+            "go infinite",
+            "stop",
+        ]);
+        assert_eq!(output.matches("bestmove").count(), 1);
+    }
+
+    #[test]
+    fn test_go_on_clock() {
+        let start = std::time::Instant::now();
+        let output = run_commands(&[
+            "setoption name Move Overhead value 30",
+            "position startpos moves e2e4",
+            "go wtime 60000 btime 100 winc 0 binc 0",
+            "position startpos",
+            "go wtime -20 btime 1000",
+        ]);
+        // with 70ms left after the overhead black must answer almost at once
+        assert!(start.elapsed() < Duration::from_millis(500), "{:?}", start.elapsed());
+        assert_eq!(output.matches("bestmove").count(), 2);
+        assert!(!output.contains("bestmove 0000"));
+    }
+}
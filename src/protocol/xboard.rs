@@ -0,0 +1,450 @@
+//! XBoard/CECP protocol front end
+//!
+//! Speaks protocol version 2 of the Chess Engine Communication Protocol.
+//! Unlike UCI the engine keeps the game itself: it follows the moves, plays
+//! its side whenever that side is to move and announces its moves and the
+//! game result. Searches run on a worker thread as in the UCI front end
+use std::io::{ self, BufRead };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread::{ self, JoinHandle };
+use std::time::Duration;
+use super::Output;
+use crate::system::{ game_over, parse_fen, try_parse_fen, Board, Color, GameClock, Move, MoveGenerator, SearchInfo, SearchLimits, Searcher, DEFAULT_HASH_MB, MATE, MATE_BOUND, START_FEN };
+
+// commands without any effect on this engine
+const IGNORED: [&str; 12] = ["xboard", "accepted", "rejected", "random", "computer", "name", "rating", "ics", "easy", "hard", "otim", "."];
+
+/// Clock settings from `level` and `st`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TimeControl {
+    // moves per session, 0 for the whole game
+    moves: u32,
+    base: Duration,
+    increment: Duration,
+    // fixed time per move (`st`), overriding the rest
+    per_move: Option<Duration>,
+}
+
+impl Default for TimeControl {
+    // xboard's own default, 40 moves in 5 minutes
+    fn default() -> Self {
+        Self { moves: 40, base: Duration::from_secs(300), increment: Duration::ZERO, per_move: None }
+    }
+}
+
+impl TimeControl {
+    // level <moves> <minutes[:seconds]> <increment seconds>
+    fn parse_level(args: &[&str]) -> Option<Self> {
+        let [moves, base, increment] = args else { return None };
+        let base = match base.split_once(':') {
+            Some((minutes, seconds)) => minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?,
+            None => base.parse::<u64>().ok()? * 60,
+        };
+        Some(Self {
+            moves: moves.parse().ok()?,
+            base: Duration::from_secs(base),
+            increment: Duration::from_secs_f64(increment.parse::<f64>().ok().filter(|i| *i >= 0.0)?),
+            per_move: None,
+        })
+    }
+}
+
+pub struct XBoard {
+    board: Board,
+    mg: &'static MoveGenerator,
+    out: Output,
+    // owned by the worker thread while a search runs; it hands back the move it played
+    searcher: Option<Searcher>,
+    worker: Option<JoinHandle<(Searcher, Option<Move>)>>,
+    stop: Arc<AtomicBool>,
+    // set under the lock when the running search must not play its move
+    cancelled: Arc<Mutex<bool>>,
+    // the side the engine plays; None in force mode
+    engine: Option<Color>,
+    analyzing: bool,
+    post: bool,
+    control: TimeControl,
+    // our clock as last reported by `time`
+    remaining: Option<Duration>,
+    depth: Option<u32>,
+}
+
+impl Default for XBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XBoard {
+    pub fn new() -> Self {
+        Self::with_output(Output::stdout())
+    }
+
+    pub fn with_output(out: Output) -> Self {
+        let searcher = Searcher::new(DEFAULT_HASH_MB);
+        Self {
+            board: parse_fen(START_FEN).into(),
+            mg: MoveGenerator::global(),
+            out,
+            stop: searcher.stop_handle(),
+            searcher: Some(searcher),
+            worker: None,
+            cancelled: Arc::new(Mutex::new(false)),
+            engine: Some(Color::Black),
+            analyzing: false,
+            post: false,
+            control: TimeControl::default(),
+            remaining: None,
+            depth: None,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        self.run_lines(stdin.lock().lines().map_while(Result::ok));
+    }
+
+    pub fn run_lines(&mut self, lines: impl Iterator<Item = String>) {
+        for line in lines {
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.cancel();
+    }
+
+    // handles one command line; returns false on quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(&command) = tokens.first() else { return true };
+        let arg = |index: usize| tokens.get(index).copied().unwrap_or("");
+
+        match command {
+            "protover" => self.out.line(format!(
+                "feature myname=\"rush-rs {}\" ping=1 setboard=1 usermove=1 time=1 draw=0 sigint=0 sigterm=0 \
+                 reuse=1 analyze=1 colors=0 variants=\"normal\" memory=1 smp=1 done=1",
+                env!("CARGO_PKG_VERSION"))),
+            "new" => {
+                self.cancel();
+                self.board.reset();
+                self.engine = Some(Color::Black);
+                self.remaining = None;
+                self.depth = None;
+                if let Some(searcher) = self.searcher.as_mut() {
+                    searcher.clear();
+                }
+                self.restart_analysis();
+            },
+            "setboard" => {
+                self.cancel();
+                match try_parse_fen(&tokens[1..].join(" ")) {
+                    Ok(fen) => self.board = fen.into(),
+                    Err(e) => self.out.line(format!("tellusererror {}", e)),
+                }
+                self.restart_analysis();
+            },
+            "usermove" => self.user_move(arg(1)),
+            "go" => {
+                self.cancel();
+                self.engine = Some(self.board.side_to_move);
+                self.think();
+            },
+            "playother" => {
+                self.cancel();
+                self.engine = Some(!self.board.side_to_move);
+            },
+            "force" => {
+                self.cancel();
+                self.engine = None;
+            },
+            // game over: wait for new or quit
+            "result" => {
+                self.cancel();
+                self.engine = None;
+            },
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "level" => match TimeControl::parse_level(&tokens[1..]) {
+                Some(control) => self.control = control,
+                None => self.out.line(format!("Error (bad time control): {}", line)),
+            },
+            "st" => match arg(1).parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => self.control.per_move = Some(Duration::from_secs_f64(seconds)),
+                _ => self.out.line(format!("Error (bad time): {}", line)),
+            },
+            "sd" => match arg(1).parse::<u32>() {
+                Ok(depth) => self.depth = Some(depth.max(1)),
+                Err(_) => self.out.line(format!("Error (bad depth): {}", line)),
+            },
+            // centiseconds
+            "time" => match arg(1).parse::<i64>() {
+                Ok(cs) => self.remaining = Some(Duration::from_millis(cs.max(0) as u64 * 10)),
+                Err(_) => self.out.line(format!("Error (bad time): {}", line)),
+            },
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "analyze" => {
+                self.cancel();
+                self.analyzing = true;
+                self.restart_analysis();
+            },
+            "exit" => {
+                self.cancel();
+                self.analyzing = false;
+            },
+            // move now
+            "?" => self.stop.store(true, Ordering::Relaxed),
+            "ping" => self.out.line(format!("pong {}", arg(1))),
+            "memory" => match arg(1).parse::<usize>() {
+                Ok(mb) => {
+                    self.cancel();
+                    if let Some(searcher) = self.searcher.as_mut() {
+                        searcher.resize_hash(mb.clamp(1, 4096));
+                    }
+                },
+                Err(_) => self.out.line(format!("Error (bad memory): {}", line)),
+            },
+            "cores" => match arg(1).parse::<usize>() {
+                Ok(threads) => {
+                    self.cancel();
+                    if let Some(searcher) = self.searcher.as_mut() {
+                        searcher.set_threads(threads.clamp(1, 256));
+                    }
+                },
+                Err(_) => self.out.line(format!("Error (bad cores): {}", line)),
+            },
+            "quit" => {
+                self.cancel();
+                return false;
+            },
+            _ if IGNORED.contains(&command) => {},
+            // without the usermove feature moves come bare
+            _ if self.mg.parse_move(&mut self.board.clone(), command).is_some() => self.user_move(command),
+            _ => self.out.line(format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    // blocks until the running search has finished, playing its move if it announced one
+    pub fn wait(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let (searcher, played) = worker.join().expect("search thread panicked");
+            self.searcher = Some(searcher);
+            if let Some(mv) = played {
+                self.board.make_move(mv);
+            }
+        }
+    }
+
+    // stops the running search; a move it has not announced yet is dropped
+    fn cancel(&mut self) {
+        *self.cancelled.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    fn user_move(&mut self, text: &str) {
+        self.cancel();
+        let mv = self.mg.parse_move(&mut self.board, text).or_else(|| self.mg.parse_san(&mut self.board, text));
+        let Some(mv) = mv else {
+            self.out.line(format!("Illegal move: {}", text));
+            return;
+        };
+        self.board.make_move(mv);
+        if self.analyzing {
+            self.restart_analysis();
+        } else if let Some((result, termination)) = game_over(&mut self.board) {
+            self.out.line(format!("{} {{{}}}", result, termination.describe()));
+        } else {
+            self.think();
+        }
+    }
+
+    fn take_back(&mut self, plies: usize) {
+        self.cancel();
+        for _ in 0..plies {
+            self.board.unmake_move();
+        }
+        self.restart_analysis();
+    }
+
+    // searches for a move if it is the engine's turn
+    fn think(&mut self) {
+        if self.analyzing || self.engine != Some(self.board.side_to_move) {
+            return;
+        }
+        let control = self.control;
+        let clock = match control.per_move {
+            Some(_) => None,
+            None => {
+                // moves left in this session, counting from the first move
+                let moves_to_go = (control.moves > 0)
+                    .then(|| control.moves - (self.board.full_move as u32 - 1) % control.moves);
+                Some(GameClock {
+                    remaining: self.remaining.unwrap_or(control.base),
+                    increment: control.increment,
+                    moves_to_go,
+                })
+            },
+        };
+        let limits = SearchLimits { depth: self.depth, movetime: control.per_move, clock, ..SearchLimits::default() };
+        self.spawn(limits, true);
+    }
+
+    // analysis follows every change of position until exit
+    fn restart_analysis(&mut self) {
+        if self.analyzing {
+            self.cancel();
+            self.spawn(SearchLimits::default(), false);
+        }
+    }
+
+    fn spawn(&mut self, limits: SearchLimits, play: bool) {
+        let mut searcher = self.searcher.take().unwrap_or_default();
+        let mut board = self.board.clone();
+        let out = self.out.clone();
+        let post = self.post || !play;
+        self.stop = searcher.stop_handle();
+        self.stop.store(false, Ordering::Relaxed);
+        self.cancelled = Arc::new(Mutex::new(false));
+        let cancelled = Arc::clone(&self.cancelled);
+
+        self.worker = Some(thread::spawn(move || {
+            let result = searcher.search_with(&mut board, limits, |info| {
+                if post {
+                    out.line(thinking(info));
+                }
+            });
+            let Some(mv) = result.best_move.filter(|_| play) else { return (searcher, None) };
+            // announcing the move and cancelling exclude each other
+            let cancelled = cancelled.lock().unwrap_or_else(|e| e.into_inner());
+            if *cancelled {
+                return (searcher, None);
+            }
+            out.line(format!("move {}", mv));
+            board.make_move(mv);
+            if let Some((result, termination)) = game_over(&mut board) {
+                out.line(format!("{} {{{}}}", result, termination.describe()));
+            }
+            drop(cancelled);
+            (searcher, Some(mv))
+        }));
+    }
+}
+
+// "ply score time nodes pv" with the time in centiseconds; mates are
+// 100000 + moves to mate, negative when getting mated
+fn thinking(info: &SearchInfo) -> String {
+    let score = if info.score > MATE_BOUND {
+        100_000 + (MATE - info.score + 1) / 2
+    } else if info.score < -MATE_BOUND {
+        -100_000 - (MATE + info.score) / 2
+    } else {
+        info.score
+    };
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+    format!("{} {} {} {} {}", info.depth, score, info.time.as_millis() / 10, info.nodes, pv.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SharedBuffer;
+
+    fn xboard() -> (XBoard, SharedBuffer) {
+        let buffer = SharedBuffer::default();
+        (XBoard::with_output(Output::new(Box::new(buffer.clone()))), buffer)
+    }
+
+    fn run_commands(commands: &[&str]) -> (XBoard, String) {
+        let (mut xboard, buffer) = xboard();
+        for command in commands {
+            xboard.handle(command);
+        }
+        xboard.wait();
+        (xboard, buffer.contents())
+    }
+
+    #[test]
+    fn test_handshake() {
+        let (_, output) = run_commands(&["xboard", "protover 2", "ping 7"]);
+        assert!(output.contains("setboard=1"));
+        assert!(output.contains("done=1"));
+        assert!(output.ends_with("pong 7\n"));
+
+        let (_, output) = run_commands(&["bogus"]);
+        assert_eq!(output, "Error (unknown command): bogus\n");
+    }
+
+    #[test]
+    fn test_plays_and_follows_the_game() {
+        // the engine plays black, answers e4 and keeps the game going
+        let (xboard, output) = run_commands(&["new", "sd 2", "post", "usermove e2e4"]);
+        assert!(output.lines().next().unwrap().starts_with("1 "), "{}", output);
+        assert!(output.lines().last().unwrap().starts_with("move "), "{}", output);
+        assert_eq!(xboard.board.ply(), 2);
+        assert_eq!(xboard.board.side_to_move, Color::White);
+
+        // illegal moves are refused and bare moves are accepted too
+        let (xboard, output) = run_commands(&["new", "force", "usermove e2e5", "e2e4", "e7e5"]);
+        assert_eq!(output, "Illegal move: e2e5\n");
+        assert_eq!(xboard.board.ply(), 2);
+
+        // undo and remove take moves back
+        let (xboard, _) = run_commands(&["new", "force", "e2e4", "e7e5", "d2d4", "remove"]);
+        assert_eq!(xboard.board.ply(), 1);
+        let (xboard, _) = run_commands(&["new", "force", "e2e4", "e7e5", "undo"]);
+        assert_eq!(xboard.board.ply(), 1);
+    }
+
+    #[test]
+    fn test_go_and_result() {
+        // white to move mates at once and claims the game
+        let (xboard, output) = run_commands(&[
+            "new",
+            "force",
+            "setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "st 1",
+            "go",
+        ]);
+        assert!(output.ends_with("move a1a8\n1-0 {checkmate}\n"), "{}", output);
+        assert_eq!(xboard.board.ply(), 1);
+
+        let (_, output) = run_commands(&["setboard not a fen"]);
+        assert!(output.starts_with("tellusererror Invalid FEN string\n"), "{}", output);
+        let (_, output) = run_commands(&["setboard 8/8/8/8/8/8/8/K7 w - - 0 1"]);
+        assert!(output.starts_with("tellusererror Illegal position: "), "{}", output);
+        assert!(!output.contains("Illegal position: Illegal position"), "{}", output);
+    }
+
+    #[test]
+    fn test_time_controls() {
+        let control = TimeControl::parse_level(&["40", "2:30", "1.5"]).unwrap();
+        assert_eq!((control.moves, control.base, control.increment), (40, Duration::from_secs(150), Duration::from_millis(1500)));
+        assert_eq!(TimeControl::parse_level(&["0", "5", "0"]).unwrap().base, Duration::from_secs(300));
+        assert_eq!(TimeControl::parse_level(&["0", "x", "0"]), None);
+
+        // 50 centiseconds left: the engine must move quickly
+        let start = std::time::Instant::now();
+        let (_, output) = run_commands(&["new", "level 0 1 0", "time 50", "otim 6000", "usermove e2e4"]);
+        assert!(start.elapsed() < Duration::from_millis(500), "{:?}", start.elapsed());
+        assert!(output.starts_with("move "));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let (mut xboard, buffer) = xboard();
+        xboard.handle("new");
+        xboard.handle("analyze");
+        thread::sleep(Duration::from_millis(50));
+        xboard.handle("usermove e2e4");
+        thread::sleep(Duration::from_millis(50));
+        xboard.handle("exit");
+        let output = buffer.contents();
+        // thinking output but never a move, and the user's move was taken
+        assert!(output.lines().next().is_some_and(|l| l.starts_with("1 ")), "{}", output);
+        assert!(!output.contains("move"));
+        assert_eq!(xboard.board.ply(), 1);
+    }
+}
@@ -1,12 +1,21 @@
 //! Bitboard operations and utilities
 //! 
 //! Board representation using 64-bit integer,
-//! where each bit represents a square's occupancy
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+//! where each bit represents a square's occupancy.
+//! Bitboards combine with the bitwise operators and iterate over their
+//! squares from a1 upwards
+use std::fmt;
+use std::ops::{ BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, ShlAssign, Shr, ShrAssign };
+use super::Square;
+
+#[derive(Default, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct BitBoard(pub u64);
 
 impl BitBoard {
-    pub fn new() -> Self {
+    pub const EMPTY: BitBoard = BitBoard(0);
+    pub const ALL: BitBoard = BitBoard(u64::MAX);
+
+    pub const fn new() -> Self {
         /*
         00000000_00000000_00000000_00000000_00000000_00000000_{0}000000{0}_0000000{0}
                                                                ^h2      ^a2        ^a1
@@ -14,21 +23,163 @@ impl BitBoard {
         BitBoard(0x00_00_00_00_00_00_00_00)
     }
 
-    pub fn set_bit(&mut self, square: usize) {
+    pub const fn set_bit(&mut self, square: usize) {
         self.0 |= 1u64 << square;
     }
 
-    pub fn clear_bit(&mut self, square: usize) {
+    pub const fn clear_bit(&mut self, square: usize) {
         self.0 &= !(1u64 << square);
     }
 
-    pub fn get_bit(&mut self, square: usize) -> bool {
+    pub const fn get_bit(&self, square: usize) -> bool {
         (self.0 >> square) & 1 != 0
     }
 
-    pub fn pop_count(&self) -> u32 {
+    pub const fn pop_count(&self) -> u32 {
         self.0.count_ones()
     }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn more_than_one(self) -> bool {
+        self.0 & self.0.wrapping_sub(1) != 0
+    }
+
+    pub const fn contains(self, square: Square) -> bool {
+        self.get_bit(square.index())
+    }
+
+    // lowest set square; the board must not be empty
+    pub const fn lsb(self) -> Square {
+        debug_assert!(self.0 != 0, "lsb of an empty bitboard");
+        Square(self.0.trailing_zeros() as u8)
+    }
+
+    // highest set square; the board must not be empty
+    pub const fn msb(self) -> Square {
+        debug_assert!(self.0 != 0, "msb of an empty bitboard");
+        Square(63 - self.0.leading_zeros() as u8)
+    }
+
+    // removes and returns the lowest set square
+    pub const fn pop_lsb(&mut self) -> Square {
+        let square = self.lsb();
+        self.0 &= self.0 - 1;
+        square
+    }
+
+    pub const fn squares(self) -> Squares {
+        Squares(self)
+    }
+}
+
+impl From<Square> for BitBoard {
+    fn from(square: Square) -> Self {
+        BitBoard(1u64 << square.0)
+    }
+}
+
+// squares from a1 to h8, each popped off a copy of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Squares(BitBoard);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        (!self.0.is_empty()).then(|| self.0.pop_lsb())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.pop_count() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for Squares {}
+
+impl IntoIterator for BitBoard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    fn into_iter(self) -> Squares {
+        Squares(self)
+    }
+}
+
+macro_rules! bitwise {
+    ($($op:ident $method:ident $assign:ident $assign_method:ident),*) => {$(
+        impl $op for BitBoard {
+            type Output = BitBoard;
+
+            fn $method(self, rhs: BitBoard) -> BitBoard {
+                BitBoard(self.0.$method(rhs.0))
+            }
+        }
+
+        impl $assign for BitBoard {
+            fn $assign_method(&mut self, rhs: BitBoard) {
+                self.0.$assign_method(rhs.0);
+            }
+        }
+    )*};
+}
+
+bitwise!(BitAnd bitand BitAndAssign bitand_assign, BitOr bitor BitOrAssign bitor_assign, BitXor bitxor BitXorAssign bitxor_assign);
+
+macro_rules! shift {
+    ($($op:ident $method:ident $assign:ident $assign_method:ident),*) => {$(
+        impl $op<u32> for BitBoard {
+            type Output = BitBoard;
+
+            fn $method(self, rhs: u32) -> BitBoard {
+                BitBoard(self.0.$method(rhs))
+            }
+        }
+
+        impl $assign<u32> for BitBoard {
+            fn $assign_method(&mut self, rhs: u32) {
+                self.0.$assign_method(rhs);
+            }
+        }
+    )*};
+}
+
+shift!(Shl shl ShlAssign shl_assign, Shr shr ShrAssign shr_assign);
+
+impl Not for BitBoard {
+    type Output = BitBoard;
+
+    fn not(self) -> BitBoard {
+        BitBoard(!self.0)
+    }
+}
+
+// a grid of set (1) and empty (.) squares, rank 8 on top
+impl fmt::Display for BitBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..8).rev() {
+            write!(f, "{} ", rank + 1)?;
+            for file in 0..8 {
+                write!(f, " {}", if (self.0 >> (rank * 8 + file)) & 1 == 1 { '1' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "   a b c d e f g h")
+    }
+}
+
+// hex on one line; `{:#?}` adds the grid
+impl fmt::Debug for BitBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BitBoard({:#018x})", self.0)?;
+        if f.alternate() {
+            write!(f, "\n{}", self)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -40,13 +191,61 @@ mod tests {
         let mut bb = BitBoard::new();
 
         bb.set_bit(0);
-        assert_eq!(bb.get_bit(0), true);
+        assert!(bb.get_bit(0));
 
         bb.clear_bit(0);
-        assert_eq!(bb.get_bit(0), false);
+        assert!(!bb.get_bit(0));
 
         bb.set_bit(63);
         bb.set_bit(27);
         assert_eq!(bb.pop_count(), 2);
     } // SUCCESS
+
+    #[test]
+    fn test_square_set() {
+        let a1 = BitBoard::from(Square(0));
+        let e4 = BitBoard::from(Square(28));
+        let mut bb = a1 | e4 | BitBoard::from(Square(63));
+        assert!(bb.contains(Square(28)) && !bb.contains(Square(27)));
+        assert_eq!((bb.lsb(), bb.msb()), (Square(0), Square(63)));
+        assert!(bb.more_than_one() && !e4.more_than_one() && !BitBoard::EMPTY.more_than_one());
+        assert_eq!(bb.squares().collect::<Vec<_>>(), vec![Square(0), Square(28), Square(63)]);
+        assert_eq!(bb.squares().len(), 3);
+        // iterating works on a copy and leaves the board alone
+        assert_eq!(bb.into_iter().count(), 3);
+        assert_eq!(bb.pop_count(), 3);
+
+        assert_eq!(bb.pop_lsb(), Square(0));
+        assert_eq!(bb & e4, e4);
+        assert_eq!(bb ^ e4, BitBoard(1 << 63));
+        assert_eq!(!bb & a1, a1);
+        assert_eq!(a1 << 28, e4);
+        assert_eq!(e4 >> 28, a1);
+
+        bb &= !e4;
+        bb |= a1;
+        bb ^= a1;
+        bb <<= 0;
+        bb >>= 63;
+        assert_eq!(bb, a1);
+        assert!(BitBoard::EMPTY.is_empty() && (!BitBoard::ALL).is_empty());
+    }
+
+    #[test]
+    fn test_bitboard_display() {
+        let bb = BitBoard(1 << 63 | 1 << 8 | 1);
+        let text = "\
+8  . . . . . . . 1
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . . . . . . . .
+2  1 . . . . . . .
+1  1 . . . . . . .
+   a b c d e f g h";
+        assert_eq!(bb.to_string(), text);
+        assert_eq!(format!("{:?}", bb), "BitBoard(0x8000000000000101)");
+        assert_eq!(format!("{:#?}", bb), format!("BitBoard(0x8000000000000101)\n{}", text));
+    }
 }
\ No newline at end of file
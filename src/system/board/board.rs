@@ -4,115 +4,351 @@
 //! - Piece positions (both array and bitboard representation)
 //! - Game state (castling rights, en passant, move counters)
 //! - Current side to move
-use super::{ BitBoard, Color, ParsedFen, Piece, Side, Square, Move };
+use std::fmt;
+use super::{ render, BitBoard, Color, ParsedFen, Piece, PieceType, RenderOptions, Side, Square, Move, KEYS };
 
+#[derive(Debug, Clone)]
 pub struct Board {
     pub pieces: [Option<Piece>; 64],
     pub piece_boards: [BitBoard; 12],
+    // the same pieces by colour and by type, kept in step with piece_boards
+    color_boards: [BitBoard; 2],
+    type_boards: [BitBoard; 6],
     pub castling_rights: CastlingRights,
     pub en_passant: Option<Square>,
     pub side_to_move: Color,
     pub half_move: u8,
     pub full_move: u16,
+    pub zobrist_key: u64,
+    history: Vec<Undo>,
+    deltas: PieceDeltas,
 }
 
+/// Pieces put on and taken off the board by the last `make_move` or
+/// `unmake_move`, so evaluators can update incrementally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceDeltas {
+    added: [(Piece, Square); 2],
+    removed: [(Piece, Square); 2],
+    num_added: u8,
+    num_removed: u8,
+}
+
+impl PieceDeltas {
+    const EMPTY: Self = Self {
+        added: [(Piece::WhitePawn, Square(0)); 2],
+        removed: [(Piece::WhitePawn, Square(0)); 2],
+        num_added: 0,
+        num_removed: 0,
+    };
+
+    pub fn added(&self) -> &[(Piece, Square)] {
+        &self.added[..self.num_added as usize]
+    }
+
+    pub fn removed(&self) -> &[(Piece, Square)] {
+        &self.removed[..self.num_removed as usize]
+    }
+}
+
+// irreversible state saved by make_move so unmake_move can restore it
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    mv: Move,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    half_move: u8,
+    zobrist_key: u64,
+}
+
+// castling rights kept when a move touches the square (king or rook leaving/captured)
+const CASTLING_MASK: [u8; 64] = {
+    let mut mask = [Castling::ANY_CASTLING; 64];
+    mask[0] = !Castling::WHITE_000 & Castling::ANY_CASTLING;  // a1
+    mask[4] = !Castling::WHITE_CASTLING & Castling::ANY_CASTLING; // e1
+    mask[7] = !Castling::WHITE_00 & Castling::ANY_CASTLING;   // h1
+    mask[56] = !Castling::BLACK_000 & Castling::ANY_CASTLING; // a8
+    mask[60] = !Castling::BLACK_CASTLING & Castling::ANY_CASTLING; // e8
+    mask[63] = !Castling::BLACK_00 & Castling::ANY_CASTLING;  // h8
+    mask
+};
+
 impl From<ParsedFen> for Board {
     fn from(fen: ParsedFen) -> Self {
         let mut pieces = [None; 64];
-        for (i, bb) in fen.piece_boards.iter().enumerate() {
-            let mut board = bb.0;
-            while board != 0 {
-                let square = board.trailing_zeros() as usize;
-                pieces[square] = Some(Piece::from_index(i));
-                board &= board - 1;
+        let mut color_boards = [BitBoard::EMPTY; 2];
+        let mut type_boards = [BitBoard::EMPTY; 6];
+        for (piece, &bb) in Piece::ALL.into_iter().zip(fen.piece_boards.iter()) {
+            for square in bb {
+                pieces[square.index()] = Some(piece);
             }
+            color_boards[piece.color() as usize] |= bb;
+            type_boards[piece.kind().index()] |= bb;
         }
 
-        Self {
+        let mut board = Self {
             pieces,
             piece_boards: fen.piece_boards,
+            color_boards,
+            type_boards,
             castling_rights: fen.castling_rights,
             en_passant: fen.en_passant,
             side_to_move: fen.side_to_move,
             half_move: fen.half_move,
             full_move: fen.full_move,
-        }
+            zobrist_key: 0,
+            history: Vec::new(),
+            deltas: PieceDeltas::EMPTY,
+        };
+        board.zobrist_key = KEYS.hash(&board);
+        board
     }
 }
 
 impl Board {
     pub fn make_move(&mut self, mv: Move) {
+        self.history.push(Undo {
+            mv,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            half_move: self.half_move,
+            zobrist_key: self.zobrist_key,
+        });
+        self.deltas = PieceDeltas::EMPTY;
+
+        if let Some(square) = self.en_passant.take() {
+            self.zobrist_key ^= KEYS.en_passant(square);
+        }
+        self.half_move = self.half_move.saturating_add(1);
+
         match mv {
             Move::Normal { from, to } => {
-                if let Some(piece) = self.pieces[from.0] {
-                    self.pieces[from.0] = None;
-                    self.pieces[to.0] = Some(piece);
-
-                    let piece_idx = piece.to_index();
-                    self.piece_boards[piece_idx].clear_bit(from.0);
-                    self.piece_boards[piece_idx].set_bit(to.0);
+                let is_pawn = self.pieces[from.index()].is_some_and(|p| p.kind() == PieceType::Pawn);
+                self.move_piece(from, to);
+                if is_pawn {
+                    self.half_move = 0;
+                    // double push leaves the skipped square open to en passant
+                    if from.0.abs_diff(to.0) == 16 {
+                        let square = Square((from.0 + to.0) / 2);
+                        self.en_passant = Some(square);
+                        self.zobrist_key ^= KEYS.en_passant(square);
+                    }
                 }
             },
+            Move::Capture { from, to, .. } => {
+                self.remove_piece(to);
+                self.move_piece(from, to);
+                self.half_move = 0;
+            },
+            Move::Promotion { from, to, piece } => {
+                self.remove_piece(from);
+                self.put_piece(to, piece);
+                self.half_move = 0;
+            },
+            Move::CapturePromotion { from, to, promotion, .. } => {
+                self.remove_piece(to);
+                self.remove_piece(from);
+                self.put_piece(to, promotion);
+                self.half_move = 0;
+            },
+            Move::Castle { from, to } => {
+                self.move_piece(from, to);
+                let (rook_from, rook_to) = Self::castle_rook_squares(to);
+                self.move_piece(rook_from, rook_to);
+            },
+            Move::EnPassant { from, to } => {
+                // captured pawn is on the mover's rank, in the target's file
+                self.remove_piece(Square::from_file_rank(to.file(), from.rank()));
+                self.move_piece(from, to);
+                self.half_move = 0;
+            },
+        }
+
+        let rights = self.castling_rights.0 & CASTLING_MASK[mv.from().index()] & CASTLING_MASK[mv.to().index()];
+        if rights != self.castling_rights.0 {
+            self.zobrist_key ^= KEYS.castling(self.castling_rights.0) ^ KEYS.castling(rights);
+            self.castling_rights = CastlingRights(rights);
+        }
+
+        if self.side_to_move == Color::Black {
+            self.full_move += 1;
+        }
+        self.side_to_move = !self.side_to_move;
+        self.zobrist_key ^= KEYS.side();
+        self.debug_validate();
+    }
+
+    // takes back the last move made, returning it
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.history.pop()?;
+        self.deltas = PieceDeltas::EMPTY;
+        self.side_to_move = !self.side_to_move;
+        if self.side_to_move == Color::Black {
+            self.full_move -= 1;
+        }
+
+        match undo.mv {
+            Move::Normal { from, to } => {
+                self.move_piece(to, from);
+            },
             Move::Capture { from, to, captured } => {
-                if let Some(piece) = self.pieces[from.0] {
-                    let capture_idx = captured.to_index();
-                    self.piece_boards[capture_idx].clear_bit(to.0);
-                    
-                    self.pieces[from.0] = None;
-                    self.pieces[to.0] = Some(piece);
-                    let piece_idx = piece.to_index();
-                    self.piece_boards[piece_idx].clear_bit(from.0);
-                    self.piece_boards[piece_idx].set_bit(to.0);
-                }
+                self.move_piece(to, from);
+                self.put_piece(to, captured);
             },
-            Move::Promotion { from, to, piece: promoted } => {
-                if let Some(_) = self.pieces[from.0] {
-                    self.pieces[from.0] = None;
-                    let pawn_idx = if self.side_to_move == Color::White { 0 } else { 6 };
-                    self.piece_boards[pawn_idx].clear_bit(from.0);
-
-                    self.pieces[to.0] = Some(promoted);
-                    let piece_idx = promoted.to_index();
-                    self.piece_boards[piece_idx].set_bit(to.0);
-                }
+            Move::Promotion { from, to, .. } => {
+                self.remove_piece(to);
+                self.put_piece(from, self.pawn(self.side_to_move));
+            },
+            Move::CapturePromotion { from, to, captured, .. } => {
+                self.remove_piece(to);
+                self.put_piece(to, captured);
+                self.put_piece(from, self.pawn(self.side_to_move));
+            },
+            Move::Castle { from, to } => {
+                let (rook_from, rook_to) = Self::castle_rook_squares(to);
+                self.move_piece(rook_to, rook_from);
+                self.move_piece(to, from);
+            },
+            Move::EnPassant { from, to } => {
+                self.move_piece(to, from);
+                self.put_piece(Square::from_file_rank(to.file(), from.rank()), self.pawn(!self.side_to_move));
             },
-            Move::CapturePromotion { from, to, captured, promotion } => {
-                if let Some(_) = self.pieces[from.0] {
-                    let capture_idx = captured.to_index();
-                    self.piece_boards[capture_idx].clear_bit(to.0);
-                    
-                    self.pieces[from.0] = None;
-                    let pawn_idx = if self.side_to_move == Color::White { 0 } else { 6 };
-                    self.piece_boards[pawn_idx].clear_bit(from.0);
-                    
-                    self.pieces[to.0] = Some(promotion);
-                    let piece_idx = promotion.to_index();
-                    self.piece_boards[piece_idx].set_bit(to.0);
-                }
-            }
         }
-        
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.half_move = undo.half_move;
+        self.zobrist_key = undo.zobrist_key;
+        self.debug_validate();
+        Some(undo.mv)
+    }
+
+    // passes the turn without moving, for null move pruning
+    pub fn make_null_move(&mut self) {
+        self.history.push(Undo {
+            mv: Move::Normal { from: Square(0), to: Square(0) },
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            half_move: self.half_move,
+            zobrist_key: self.zobrist_key,
+        });
+        self.deltas = PieceDeltas::EMPTY;
+        if let Some(square) = self.en_passant.take() {
+            self.zobrist_key ^= KEYS.en_passant(square);
+        }
+        // a null move breaks repetition chains like any irreversible move
+        self.half_move = 0;
         self.side_to_move = !self.side_to_move;
+        self.zobrist_key ^= KEYS.side();
+        self.debug_validate();
+    }
+
+    pub fn unmake_null_move(&mut self) {
+        if let Some(undo) = self.history.pop() {
+            self.deltas = PieceDeltas::EMPTY;
+            self.side_to_move = !self.side_to_move;
+            self.en_passant = undo.en_passant;
+            self.half_move = undo.half_move;
+            self.zobrist_key = undo.zobrist_key;
+            self.debug_validate();
+        }
+    }
+
+    // moves played since the board was set up, oldest first
+    pub fn move_history(&self) -> impl Iterator<Item = Move> + '_ {
+        self.history.iter().map(|undo| undo.mv)
+    }
+
+    pub fn last_deltas(&self) -> &PieceDeltas {
+        &self.deltas
+    }
+
+    pub fn ply(&self) -> usize {
+        self.history.len()
+    }
+
+    // true if the current position occurred before since the last irreversible move
+    pub fn is_repetition(&self) -> bool {
+        self.history.iter()
+            .rev()
+            .take(self.half_move as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|undo| undo.zobrist_key == self.zobrist_key)
+    }
+
+    // earlier occurrences of the current position; two means threefold repetition
+    pub fn repetition_count(&self) -> usize {
+        self.history.iter()
+            .rev()
+            .take(self.half_move as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|undo| undo.zobrist_key == self.zobrist_key)
+            .count()
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.pieces[square.index()]
+    }
+
+    fn pawn(&self, side: Color) -> Piece {
+        Piece::new(side, PieceType::Pawn)
+    }
+
+    fn castle_rook_squares(king_to: Square) -> (Square, Square) {
+        match king_to.0 {
+            6 => (Square(7), Square(5)),    // white O-O
+            2 => (Square(0), Square(3)),    // white O-O-O
+            62 => (Square(63), Square(61)), // black O-O
+            _ => (Square(56), Square(59)),  // black O-O-O
+        }
+    }
+
+    fn put_piece(&mut self, square: Square, piece: Piece) {
+        self.pieces[square.index()] = Some(piece);
+        self.piece_boards[piece.to_index()].set_bit(square.index());
+        self.color_boards[piece.color() as usize].set_bit(square.index());
+        self.type_boards[piece.kind().index()].set_bit(square.index());
+        self.zobrist_key ^= KEYS.piece(piece, square);
+        self.deltas.added[self.deltas.num_added as usize] = (piece, square);
+        self.deltas.num_added += 1;
+    }
+
+    fn remove_piece(&mut self, square: Square) -> Option<Piece> {
+        let piece = self.pieces[square.index()].take()?;
+        self.piece_boards[piece.to_index()].clear_bit(square.index());
+        self.color_boards[piece.color() as usize].clear_bit(square.index());
+        self.type_boards[piece.kind().index()].clear_bit(square.index());
+        self.zobrist_key ^= KEYS.piece(piece, square);
+        self.deltas.removed[self.deltas.num_removed as usize] = (piece, square);
+        self.deltas.num_removed += 1;
+        Some(piece)
+    }
+
+    fn move_piece(&mut self, from: Square, to: Square) {
+        if let Some(piece) = self.remove_piece(from) {
+            self.put_piece(to, piece);
+        }
     }
 
     // returns combined bitboard of one color
     pub fn side_pieces(&self, side: Side) -> BitBoard {
-        let mut combined = BitBoard(0);
-        for i in side.piece_range() {
-            combined.0 |= self.piece_boards[i].0;
-        }
-        combined
+        self.color_boards[side.0 as usize]
+    }
+
+    // returns combined bitboard of one piece type, both colors
+    pub fn type_pieces(&self, kind: PieceType) -> BitBoard {
+        self.type_boards[kind.index()]
     }
 
     // returns reference to piece bitboard given side and piece type
-    pub fn piece_bb(&self, side: Side, piece: Piece) -> &BitBoard {
-        &self.piece_boards[side.piece_range().start + piece as usize]
+    pub fn piece_bb(&self, side: Side, kind: PieceType) -> &BitBoard {
+        &self.piece_boards[Piece::new(side.0, kind).to_index()]
     }
 
     pub fn reset(&mut self) {
-        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let parsed = super::parse_fen(fen);
-        *self = parsed.into();
+        *self = super::parse_fen(super::START_FEN).into();
     }
 
     pub fn get_pieces(&self) -> &[Option<Piece>; 64] {
@@ -124,11 +360,11 @@ impl Board {
     }
 
     pub fn occupancy(&self) -> u32 {
-        let mut occupied = BitBoard(0);
-        for bb in self.piece_boards.iter() {
-            occupied.0 |= bb.0;
-        }
-        occupied.pop_count()
+        self.occupied().pop_count()
+    }
+
+    pub fn occupied(&self) -> BitBoard {
+        self.color_boards[0] | self.color_boards[1]
     }
 
     pub fn get_side_to_move(&self) -> Color {
@@ -136,20 +372,11 @@ impl Board {
     }
 
     pub fn king_square(&self, side: Color) -> Square {
-        let king_bb = match side {
-            Color::White => self.piece_boards[5], // White king
-            Color::Black => self.piece_boards[11], // Black king
-        };
-        Square(king_bb.0.trailing_zeros() as usize)
+        self.piece_bb(Side(side), PieceType::King).lsb()
     }
 
     pub fn has_bishop_pair(&self, side: Color) -> bool {
-        let bishop_idx = match side {
-            Color::White => 2,
-            Color::Black => 8,
-        };
-
-        let bishop_bb = self.piece_boards[bishop_idx];
+        let bishop_bb = *self.piece_bb(Side(side), PieceType::Bishop);
         let bishop_count = bishop_bb.pop_count();
 
         if bishop_count < 2 {
@@ -159,10 +386,22 @@ impl Board {
         let light_squares = BitBoard(0x55AA55AA55AA55AA);
         let dark_squares = BitBoard(0xAA55AA55AA55AA55);
 
-        let light_bishops = BitBoard(bishop_bb.0 & light_squares.0).pop_count();
-        let dark_bishops = BitBoard(bishop_bb.0 & dark_squares.0).pop_count();
+        !(bishop_bb & light_squares).is_empty() && !(bishop_bb & dark_squares).is_empty()
+    }
+}
 
-        light_bishops > 0 && dark_bishops > 0
+// the diagram, then the state it can't show; `{:#}` draws Unicode pieces
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", render(self.piece_boards, RenderOptions { flipped: false, unicode: f.alternate() }))?;
+        writeln!(f)?;
+        writeln!(f, "side to move: {}", if self.side_to_move == Color::White { "white" } else { "black" })?;
+        writeln!(f, "castling: {}", self.castling_rights)?;
+        let en_passant = self.en_passant.map_or("-".to_string(), |sq| sq.to_string());
+        writeln!(f, "en passant: {}", en_passant)?;
+        writeln!(f, "half-move clock: {}, full move: {}", self.half_move, self.full_move)?;
+        writeln!(f, "fen: {}", self.to_fen())?;
+        write!(f, "key: {:#018x}", self.zobrist_key)
     }
 }
 
@@ -179,6 +418,20 @@ impl CastlingRights {
     }
 }
 
+// FEN castling field: "KQkq", "Kq", "-"
+impl fmt::Display for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 & Castling::ANY_CASTLING == 0 {
+            return write!(f, "-");
+        }
+        for (flag, c) in [(Castling::WHITE_00, 'K'), (Castling::WHITE_000, 'Q'), (Castling::BLACK_00, 'k'), (Castling::BLACK_000, 'q')] {
+            if self.0 & flag != 0 {
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
 
 
 // {0000}{Black Queen Castle}{Black King}{White Queen}{White King}
@@ -201,76 +454,74 @@ impl Castling {
 mod tests {
     use crate::system::*;
 
-    fn bitboards_to_board(piece_bitboards: [BitBoard; 12]) -> [[char; 8]; 8] {
-        let mut board = [['.'; 8]; 8];
-        
-        for rank in 0..8 {
-            for file in 0..8 {
-                let square = rank * 8 + file;
-                let mask = 1u64 << square;
-                
-                for (i, bb) in piece_bitboards.iter().enumerate() {
-                    if bb.0 & mask != 0 {
-                        board[7 - rank][file] = match i {
-                            0 => 'P', 1 => 'N', 2 => 'B', 3 => 'R', 4 => 'Q', 5 => 'K',
-                            6 => 'p', 7 => 'n', 8 => 'b', 9 => 'r', 10 => 'q', 11 => 'k',
-                            _ => '.'
-                        };
-                    }
-                }
-            }
-        }
-        board
-    }
-
-    fn debug_print(bitboard: BitBoard) {
-        for rank in (0..8).rev() {
-            for file in 0..8 {
-                let mask: u16 = rank*8 + file;
-                let bit = (bitboard.0 >> mask) & 1;
-                print!("{} ", bit);
-            }
-            println!();
-        }
-    }
-    
     #[test]
     fn debug_bitboard_display() {
         let bitboard = BitBoard::new();
-        debug_print(bitboard);
+        assert_eq!(bitboard.to_string(), "\
+8  . . . . . . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . . . . . . . .
+2  . . . . . . . .
+1  . . . . . . . .
+   a b c d e f g h");
     } // SUCCESS
 
     #[test]
-    fn debug_display_position() {
-        let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let parsed = parse_fen(start_fen);
-        let piece_bitboards = parsed.piece_boards;
-        let board = bitboards_to_board(piece_bitboards);
-        
-        // update if position is not starting position
-        let expected = [
-            ['r', 'n', 'b', 'q', 'k', 'b', 'n', 'r'],
-            ['p', 'p', 'p', 'p', 'p', 'p', 'p', 'p'],
-            ['.', '.', '.', '.', '.', '.', '.', '.'],
-            ['.', '.', '.', '.', '.', '.', '.', '.'],
-            ['.', '.', '.', '.', '.', '.', '.', '.'],
-            ['.', '.', '.', '.', '.', '.', '.', '.'],
-            ['P', 'P', 'P', 'P', 'P', 'P', 'P', 'P'],
-            ['R', 'N', 'B', 'Q', 'K', 'B', 'N', 'R'],
-        ];
-
-        assert_eq!(board, expected);
-
-        // print board for visual verification
-        for rank in &board {
-            println!("{}", rank.iter().collect::<String>());
-        }
-    } // SUCCESS
+    fn test_board_display() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w Kq - 3 17";
+        let board: Board = parse_fen(fen).into();
+        let expected = format!("\
+8  r . . . k . . r
+7  p . p p q p b .
+6  b n . . p n p .
+5  . . . P N . . .
+4  . p . . P . . .
+3  . . N . . Q . p
+2  P P P B B P P P
+1  R . . . K . . R
+   a b c d e f g h
+
+side to move: white
+castling: Kq
+en passant: -
+half-move clock: 3, full move: 17
+fen: {}
+key: {:#018x}", fen, board.zobrist_key);
+        assert_eq!(board.to_string(), expected);
+
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        let board: Board = parse_fen(fen).into();
+        let expected = format!("\
+8  . . . . ♚ . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . ♟ ♙ . . .
+4  . . . . . . . .
+3  . . . . . . . .
+2  . . . . . . . .
+1  . . . . ♔ . . .
+   a b c d e f g h
+
+side to move: white
+castling: -
+en passant: d6
+half-move clock: 0, full move: 2
+fen: {}
+key: {:#018x}", fen, board.zobrist_key);
+        assert_eq!(format!("{:#}", board), expected);
+
+        let mut rights = CastlingRights(Castling::ANY_CASTLING);
+        assert_eq!(rights.to_string(), "KQkq");
+        assert_eq!(CastlingRights(Castling::WHITE_000 | Castling::BLACK_00).to_string(), "Qk");
+        assert_eq!(rights.empty().to_string(), "-");
+    }
 
     #[test]
     fn test_board_conversion() {
-        let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let parsed = parse_fen(start_fen);
+        let parsed = parse_fen(START_FEN);
         let mut board: Board = parsed.into();
 
         assert!(matches!(board.pieces[0], Some(Piece::WhiteRook)));
@@ -313,8 +564,11 @@ mod tests {
 
         let white = board.side_pieces(Side::WHITE);
         assert_eq!(white.pop_count(), 16);
-        let white_knights = board.piece_bb(Side::WHITE, Piece::WhiteKnight);
+        let white_knights = board.piece_bb(Side::WHITE, PieceType::Knight);
         assert_eq!(white_knights.pop_count(), 2);
+        assert_eq!(*board.piece_bb(Side::BLACK, PieceType::Queen), BitBoard::from(named::D8));
+        assert_eq!(board.type_pieces(PieceType::Rook), BitBoard::from(named::A1) | BitBoard::from(named::H1) | BitBoard::from(named::A8) | BitBoard::from(named::H8));
+        assert_eq!(board.occupied().pop_count(), 32);
     } // SUCCESS
 
     // testing move making on board; don't need string to move after testing
@@ -324,13 +578,8 @@ mod tests {
                 return Err("Invalid move format");
             }
 
-            let from_file = (move_str.chars().nth(0).unwrap() as u8 - b'a') as usize;
-            let from_rank = (move_str.chars().nth(1).unwrap() as u8 - b'1') as usize;
-            let to_file = (move_str.chars().nth(2).unwrap() as u8 - b'a') as usize;
-            let to_rank = (move_str.chars().nth(3).unwrap() as u8 - b'1') as usize;
-
-            let from = Square(from_file + from_rank * 8);
-            let to = Square(to_file + to_rank * 8);
+            let from: Square = move_str[..2].parse().map_err(|_| "Invalid from square")?;
+            let to: Square = move_str[2..].parse().map_err(|_| "Invalid to square")?;
 
             self.make_move(Move::Normal { from, to });
             Ok(())
@@ -339,24 +588,13 @@ mod tests {
 
     #[test]
     fn test_make_move() {
-        let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let parsed = parse_fen(start_fen);
+        let parsed = parse_fen(START_FEN);
         let mut board: Board = parsed.into();
 
-        let print_board = bitboards_to_board(board.piece_boards);
-        for rank in &print_board {
-            println!("{}", rank.iter().collect::<String>());
-        }
-        println!("\nAfter moving:\n");
-
         board.make_move_str("e2e4").unwrap();
-        assert!(matches!(board.pieces[named::E2.0], None));
-        assert!(matches!(board.pieces[named::E4.0], Some(Piece::WhitePawn)));
+        assert!(board.pieces[named::E2.index()].is_none());
+        assert!(matches!(board.pieces[named::E4.index()], Some(Piece::WhitePawn)));
         assert_eq!(board.side_to_move, Color::Black);
-
-        let print_board = bitboards_to_board(board.piece_boards);
-        for rank in &print_board {
-            println!("{}", rank.iter().collect::<String>());
-        }
+        assert!(board.to_string().contains("\n4  . . . . P . . .\n3  . . . . . . . .\n2  P P P P . P P P\n"));
     }
 }
\ No newline at end of file
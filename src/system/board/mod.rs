@@ -1,12 +1,17 @@
 pub use super::*;
+#[allow(clippy::module_inception)]
 pub mod board;
 pub mod piece;
 pub mod bitboard;
+pub mod render;
 pub mod side;
 pub mod square;
+pub mod validate;
 
 pub use board::*;
 pub use piece::*;
 pub use bitboard::*;
+pub use render::*;
 pub use side::*;
-pub use square::*;
\ No newline at end of file
+pub use square::*;
+pub use validate::*;
\ No newline at end of file
@@ -1,7 +1,48 @@
 //! Chess piece definitions and operations
-//! 
-//! Defines piece types, colors, and conversion utilities
-#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+//!
+//! A piece is a colour and a type packed into one index, white pawn (0)
+//! to black king (11), which also orders the board's piece bitboards.
+//! Conversions to FEN letters and glyphs live here; material values come
+//! from the evaluation parameters
+use super::Color;
+
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    pub const fn from_index(i: usize) -> Option<PieceType> {
+        if i < 6 { Some(PieceType::ALL[i]) } else { None }
+    }
+
+    // upper case letter, as in SAN
+    pub const fn to_char(self) -> char {
+        Piece::new(Color::White, self).to_char()
+    }
+
+    // either case
+    pub fn from_char(c: char) -> Option<PieceType> {
+        Piece::from_char(c).map(Piece::kind)
+    }
+
+    pub const fn is_slider(self) -> bool {
+        matches!(self, PieceType::Bishop | PieceType::Rook | PieceType::Queen)
+    }
+}
+
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Piece {
     WhitePawn,
     WhiteKnight,
@@ -15,43 +56,73 @@ pub enum Piece {
     BlackRook,
     BlackQueen,
     BlackKing,
-    NONE,
 }
 
+const FEN_CHARS: [char; 12] = ['P', 'N', 'B', 'R', 'Q', 'K', 'p', 'n', 'b', 'r', 'q', 'k'];
+const GLYPHS: [char; 12] = ['♙', '♘', '♗', '♖', '♕', '♔', '♟', '♞', '♝', '♜', '♛', '♚'];
+
 impl Piece {
-    pub fn to_index(&self) -> usize {
-        match self {
-            Piece::WhitePawn => 0,
-            Piece::WhiteKnight => 1,
-            Piece::WhiteBishop => 2,
-            Piece::WhiteRook => 3,
-            Piece::WhiteQueen => 4,
-            Piece::WhiteKing => 5,
-            Piece::BlackPawn => 6,
-            Piece::BlackKnight => 7,
-            Piece::BlackBishop => 8,
-            Piece::BlackRook => 9,
-            Piece::BlackQueen => 10,
-            Piece::BlackKing => 11,
-            Piece::NONE => 12,
-        }
+    pub const ALL: [Piece; 12] = [
+        Piece::WhitePawn, Piece::WhiteKnight, Piece::WhiteBishop, Piece::WhiteRook, Piece::WhiteQueen, Piece::WhiteKing,
+        Piece::BlackPawn, Piece::BlackKnight, Piece::BlackBishop, Piece::BlackRook, Piece::BlackQueen, Piece::BlackKing,
+    ];
+
+    pub const fn new(color: Color, kind: PieceType) -> Piece {
+        Piece::ALL[color as usize * 6 + kind as usize]
     }
 
-    pub fn from_index(i: usize) -> Piece {
-        match i {
-            0 => Piece::WhitePawn,
-            1 => Piece::WhiteKnight,
-            2 => Piece::WhiteBishop,
-            3 => Piece::WhiteRook,
-            4 => Piece::WhiteQueen,
-            5 => Piece::WhiteKing,
-            6 => Piece::BlackPawn,
-            7 => Piece::BlackKnight,
-            8 => Piece::BlackBishop,
-            9 => Piece::BlackRook,
-            10 => Piece::BlackQueen,
-            11 => Piece::BlackKing,
-            _ => Piece::NONE,
+    pub const fn color(self) -> Color {
+        if (self as usize) < 6 { Color::White } else { Color::Black }
+    }
+
+    pub const fn kind(self) -> PieceType {
+        PieceType::ALL[self as usize % 6]
+    }
+
+    pub const fn to_index(self) -> usize {
+        self as usize
+    }
+
+    // i must be below 12
+    pub const fn from_index(i: usize) -> Piece {
+        Piece::ALL[i]
+    }
+
+    // FEN letter: upper case for white
+    pub const fn to_char(self) -> char {
+        FEN_CHARS[self as usize]
+    }
+
+    pub fn from_char(c: char) -> Option<Piece> {
+        FEN_CHARS.iter().position(|&p| p == c).map(Piece::from_index)
+    }
+
+    pub const fn glyph(self) -> char {
+        GLYPHS[self as usize]
+    }
+
+    pub fn value(self) -> i32 {
+        self.kind().value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_and_kind() {
+        for piece in Piece::ALL {
+            assert_eq!(Piece::new(piece.color(), piece.kind()), piece);
+            assert_eq!(Piece::from_char(piece.to_char()), Some(piece));
         }
+        assert_eq!(Piece::new(Color::Black, PieceType::Knight), Piece::BlackKnight);
+        assert_eq!(Piece::BlackQueen.to_index(), 10);
+        assert_eq!((Piece::BlackRook.color(), Piece::BlackRook.kind()), (Color::Black, PieceType::Rook));
+        assert_eq!(Piece::from_char('x'), None);
+        assert_eq!(PieceType::from_char('q'), Some(PieceType::Queen));
+        assert_eq!(PieceType::Knight.to_char(), 'N');
+        assert_eq!((Piece::WhiteKing.glyph(), Piece::BlackKing.glyph()), ('♔', '♚'));
+        assert_eq!(Piece::BlackRook.value(), 500);
     }
-}
\ No newline at end of file
+}
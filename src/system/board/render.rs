@@ -0,0 +1,78 @@
+//! Text diagrams
+//!
+//! Draws positions as an 8x8 grid of piece letters or Unicode glyphs with
+//! rank and file labels, from white's side or flipped
+use super::{ BitBoard, Piece };
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    // black's side at the bottom
+    pub flipped: bool,
+    pub unicode: bool,
+}
+
+// pieces by row, rank 8 first
+fn bitboards_to_board(piece_boards: [BitBoard; 12]) -> [[Option<Piece>; 8]; 8] {
+    let mut board = [[None; 8]; 8];
+    for (piece, bb) in piece_boards.iter().enumerate() {
+        for square in *bb {
+            board[7 - square.rank().index()][square.file().index()] = Some(Piece::from_index(piece));
+        }
+    }
+    board
+}
+
+/// The position as a labelled grid, one rank per line.
+pub fn render(piece_boards: [BitBoard; 12], options: RenderOptions) -> String {
+    let grid = bitboards_to_board(piece_boards);
+    let mut text = String::new();
+    for row in 0..8 {
+        let row = if options.flipped { 7 - row } else { row };
+        text.push_str(&format!("{} ", 8 - row));
+        for col in 0..8 {
+            let col = if options.flipped { 7 - col } else { col };
+            let c = match grid[row][col] {
+                Some(piece) if options.unicode => piece.glyph(),
+                Some(piece) => piece.to_char(),
+                None => '.',
+            };
+            text.push(' ');
+            text.push(c);
+        }
+        text.push('\n');
+    }
+    text.push_str(if options.flipped { "   h g f e d c b a" } else { "   a b c d e f g h" });
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::parse_fen;
+
+    #[test]
+    fn test_render() {
+        let parsed = parse_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1");
+        assert_eq!(render(parsed.piece_boards, RenderOptions::default()), "\
+8  . . . . k . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . . . . . . . .
+2  . . . . . . . .
+1  R . . . K . . .
+   a b c d e f g h");
+
+        assert_eq!(render(parsed.piece_boards, RenderOptions { flipped: true, unicode: true }), "\
+1  . . . ♔ . . . ♖
+2  . . . . . . . .
+3  . . . . . . . .
+4  . . . . . . . .
+5  . . . . . . . .
+6  . . . . . . . .
+7  . . . . . . . .
+8  . . . ♚ . . . .
+   h g f e d c b a");
+    }
+}
@@ -1,134 +1,265 @@
+//! Squares, files and ranks
+//!
+//! A square is its index from a1 (0) to h8 (63). Files and ranks split it
+//! into coordinates and carry their bitboard masks
+use std::fmt;
+use std::str::FromStr;
+use super::BitBoard;
 
-#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
-pub struct Square(pub usize);
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Square(pub u8);
 
 pub const FILE_CHARS: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 pub const RANK_CHARS: [char; 8] = ['1', '2', '3', '4', '5', '6', '7', '8'];
 
-pub mod masks {
-
-    pub const A_FILE: u64 = 0x0101010101010101;
-    pub const B_FILE: u64 = 0x0202020202020202;
-    pub const C_FILE: u64 = 0x0404040404040404;
-    pub const D_FILE: u64 = 0x0808080808080808;
-    pub const H_FILE: u64 = 0x8080808080808080;
-    pub const G_FILE: u64 = 0x4040404040404040;
-    pub const F_FILE: u64 = 0x2020202020202020;
-    pub const E_FILE: u64 = 0x1010101010101010;
-
-    pub const RANK_1: u64 = 0x00000000000000FF;
-    pub const RANK_2: u64 = 0x000000000000FF00;
-    pub const RANK_3: u64 = 0x0000000000FF0000;
-    pub const RANK_4: u64 = 0x00000000FF000000;
-    pub const RANK_5: u64 = 0x000000FF00000000;
-    pub const RANK_6: u64 = 0x0000FF0000000000;
-    pub const RANK_7: u64 = 0x00FF000000000000;
-    pub const RANK_8: u64 = 0xFF00000000000000;
-
-    pub const ALL: u64 = 0xFFFFFFFFFFFFFFFF;
-    pub const NONE: u64 = 0x0;
-
-
-    #[inline]
-    pub fn file_for_x(x: u8) -> u64 {
-        match x {
-            0 => A_FILE,
-            1 => B_FILE,
-            2 => C_FILE,
-            3 => D_FILE,
-            4 => E_FILE,
-            5 => F_FILE,
-            6 => G_FILE,
-            7 => H_FILE,
-            _ => 0,
+impl Square {
+    pub const fn from_file_rank(file: File, rank: Rank) -> Square {
+        Square(rank as u8 * 8 + file as u8)
+    }
+
+    // for indexing tables by square
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub const fn file(self) -> File {
+        File::ALL[(self.0 % 8) as usize]
+    }
+
+    pub const fn rank(self) -> Rank {
+        Rank::ALL[(self.0 / 8) as usize]
+    }
+
+    // the square df files and dr ranks away, if still on the board
+    pub const fn offset(self, df: i8, dr: i8) -> Option<Square> {
+        let file = (self.0 % 8) as i8 + df;
+        let rank = (self.0 / 8) as i8 + dr;
+        if file < 0 || file > 7 || rank < 0 || rank > 7 {
+            return None;
         }
+        Some(Square(rank as u8 * 8 + file as u8))
     }
 
-    pub mod neighbors {
-        pub const A_FILE: u64 = super::B_FILE;
-        pub const B_FILE: u64 = super::A_FILE | super::C_FILE;
-        pub const C_FILE: u64 = super::B_FILE | super::D_FILE;
-        pub const D_FILE: u64 = super::C_FILE | super::E_FILE;
-        pub const E_FILE: u64 = super::D_FILE | super::F_FILE;
-        pub const F_FILE: u64 = super::E_FILE | super::G_FILE;
-        pub const G_FILE: u64 = super::F_FILE | super::H_FILE;
-        pub const H_FILE: u64 = super::G_FILE;
+    // same file, rank counted from the other side (e2 <-> e7)
+    pub const fn flip_vertical(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+
+    // same rank, file counted from the other side (b3 <-> g3)
+    pub const fn mirror_horizontal(self) -> Square {
+        Square(self.0 ^ 7)
+    }
+
+    // king moves between the squares (Chebyshev distance)
+    pub const fn distance(self, other: Square) -> u8 {
+        let (df, dr) = self.file_rank_deltas(other);
+        if df > dr { df } else { dr }
+    }
+
+    // rook moves of one step between the squares (Manhattan distance)
+    pub const fn manhattan_distance(self, other: Square) -> u8 {
+        let (df, dr) = self.file_rank_deltas(other);
+        df + dr
+    }
+
+    const fn file_rank_deltas(self, other: Square) -> (u8, u8) {
+        ((self.0 % 8).abs_diff(other.0 % 8), (self.0 / 8).abs_diff(other.0 / 8))
+    }
+
+    // a1, b1, ... h8
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    // square from a constant's name, e.g. "E4"
+    const fn from_name(name: &str) -> Square {
+        let bytes = name.as_bytes();
+        Square((bytes[1] - b'1') * 8 + bytes[0] - b'A')
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SquareError {
+    // not exactly two characters
+    Length(String),
+    File(char),
+    Rank(char),
+}
+
+impl fmt::Display for SquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SquareError::Length(text) => write!(f, "'{}' is not a square, expected a file and a rank like e4", text),
+            SquareError::File(c) => write!(f, "invalid file '{}', expected a-h", c),
+            SquareError::Rank(c) => write!(f, "invalid rank '{}', expected 1-8", c),
+        }
+    }
+}
+
+impl std::error::Error for SquareError {}
+
+impl FromStr for Square {
+    type Err = SquareError;
+
+    fn from_str(text: &str) -> Result<Square, SquareError> {
+        let mut chars = text.chars();
+        let (Some(file), Some(rank), None) = (chars.next(), chars.next(), chars.next()) else {
+            return Err(SquareError::Length(text.to_string()));
+        };
+        let file = File::from_char(file).ok_or(SquareError::File(file))?;
+        let rank = Rank::from_char(rank).ok_or(SquareError::Rank(rank))?;
+        Ok(Square::from_file_rank(file, rank))
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum File { A, B, C, D, E, F, G, H }
+
+impl File {
+    pub const ALL: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+
+    pub const fn from_index(index: u8) -> Option<File> {
+        if index < 8 { Some(File::ALL[index as usize]) } else { None }
+    }
+
+    pub fn from_char(c: char) -> Option<File> {
+        FILE_CHARS.iter().position(|&f| f == c).map(|i| File::ALL[i])
+    }
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    pub const fn to_char(self) -> char {
+        FILE_CHARS[self as usize]
+    }
+
+    pub const fn mask(self) -> BitBoard {
+        BitBoard(0x0101010101010101 << self as u8)
+    }
+
+    // the files either side of this one
+    pub const fn neighbors(self) -> BitBoard {
+        BitBoard(((self.mask().0 << 1) & !File::A.mask().0) | ((self.mask().0 >> 1) & !File::H.mask().0))
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum Rank { First, Second, Third, Fourth, Fifth, Sixth, Seventh, Eighth }
+
+impl Rank {
+    pub const ALL: [Rank; 8] = [Rank::First, Rank::Second, Rank::Third, Rank::Fourth, Rank::Fifth, Rank::Sixth, Rank::Seventh, Rank::Eighth];
+
+    pub const fn from_index(index: u8) -> Option<Rank> {
+        if index < 8 { Some(Rank::ALL[index as usize]) } else { None }
+    }
+
+    pub fn from_char(c: char) -> Option<Rank> {
+        RANK_CHARS.iter().position(|&r| r == c).map(|i| Rank::ALL[i])
+    }
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    pub const fn to_char(self) -> char {
+        RANK_CHARS[self as usize]
+    }
+
+    pub const fn mask(self) -> BitBoard {
+        BitBoard(0xFF << (self as u8 * 8))
+    }
+
+    // the same rank seen from black's side (Second <-> Seventh)
+    pub const fn flip(self) -> Rank {
+        Rank::ALL[7 - self as usize]
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
     }
 }
 
 pub mod named {
     use super::Square;
 
-    // Rank 1
-    pub const A1: Square = Square(0);
-    pub const B1: Square = Square(1);
-    pub const C1: Square = Square(2);
-    pub const D1: Square = Square(3);
-    pub const E1: Square = Square(4);
-    pub const F1: Square = Square(5);
-    pub const G1: Square = Square(6);
-    pub const H1: Square = Square(7);
-    // Rank 2
-    pub const A2: Square = Square(8);
-    pub const B2: Square = Square(9);
-    pub const C2: Square = Square(10);
-    pub const D2: Square = Square(11);
-    pub const E2: Square = Square(12);
-    pub const F2: Square = Square(13);
-    pub const G2: Square = Square(14);
-    pub const H2: Square = Square(15);
-    // Rank 3
-    pub const A3: Square = Square(16);
-    pub const B3: Square = Square(17);
-    pub const C3: Square = Square(18);
-    pub const D3: Square = Square(19);
-    pub const E3: Square = Square(20);
-    pub const F3: Square = Square(21);
-    pub const G3: Square = Square(22);
-    pub const H3: Square = Square(23);
-    // Rank 4
-    pub const A4: Square = Square(24);
-    pub const B4: Square = Square(25);
-    pub const C4: Square = Square(26);
-    pub const D4: Square = Square(27);
-    pub const E4: Square = Square(28);
-    pub const F4: Square = Square(29);
-    pub const G4: Square = Square(30);
-    pub const H4: Square = Square(31);
-    // Rank 5
-    pub const A5: Square = Square(32);
-    pub const B5: Square = Square(33);
-    pub const C5: Square = Square(34);
-    pub const D5: Square = Square(35);
-    pub const E5: Square = Square(36);
-    pub const F5: Square = Square(37);
-    pub const G5: Square = Square(38);
-    pub const H5: Square = Square(39);
-    // Rank 6
-    pub const A6: Square = Square(40);
-    pub const B6: Square = Square(41);
-    pub const C6: Square = Square(42);
-    pub const D6: Square = Square(43);
-    pub const E6: Square = Square(44);
-    pub const F6: Square = Square(45);
-    pub const G6: Square = Square(46);
-    pub const H6: Square = Square(47);
-    // Rank 7
-    pub const A7: Square = Square(48);
-    pub const B7: Square = Square(49);
-    pub const C7: Square = Square(50);
-    pub const D7: Square = Square(51);
-    pub const E7: Square = Square(52);
-    pub const F7: Square = Square(53);
-    pub const G7: Square = Square(54);
-    pub const H7: Square = Square(55);
-    // Rank 8
-    pub const A8: Square = Square(56);
-    pub const B8: Square = Square(57);
-    pub const C8: Square = Square(58);
-    pub const D8: Square = Square(59);
-    pub const E8: Square = Square(60);
-    pub const F8: Square = Square(61);
-    pub const G8: Square = Square(62);
-    pub const H8: Square = Square(63);
+    macro_rules! named {
+        ($($name:ident)*) => {
+            $(pub const $name: Square = Square::from_name(stringify!($name));)*
+        };
+    }
+
+    named!(
+        A1 B1 C1 D1 E1 F1 G1 H1
+        A2 B2 C2 D2 E2 F2 G2 H2
+        A3 B3 C3 D3 E3 F3 G3 H3
+        A4 B4 C4 D4 E4 F4 G4 H4
+        A5 B5 C5 D5 E5 F5 G5 H5
+        A6 B6 C6 D6 E6 F6 G6 H6
+        A7 B7 C7 D7 E7 F7 G7 H7
+        A8 B8 C8 D8 E8 F8 G8 H8
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinates() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!(e4, named::E4);
+        assert_eq!((e4.file(), e4.rank()), (File::E, Rank::Fourth));
+        assert_eq!(Square::from_file_rank(File::H, Rank::Eighth), named::H8);
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!(Square::all().map(|sq| sq.to_string()).nth(9).unwrap(), "b2");
+
+        assert_eq!(e4.offset(1, 2), Some(named::F6));
+        assert_eq!(named::H1.offset(1, 0), None);
+        assert_eq!(named::A8.offset(0, 1), None);
+        assert_eq!(named::A1.offset(-1, 1), None);
+        assert_eq!(e4.flip_vertical(), named::E5);
+        assert_eq!(named::B3.mirror_horizontal(), named::G3);
+        assert_eq!(named::A1.distance(named::H8), 7);
+        assert_eq!(named::A1.manhattan_distance(named::H8), 14);
+        assert_eq!(e4.distance(named::F6), 2);
+        assert_eq!(e4.manhattan_distance(named::F6), 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("e".parse::<Square>(), Err(SquareError::Length("e".to_string())));
+        assert_eq!("e44".parse::<Square>(), Err(SquareError::Length("e44".to_string())));
+        assert_eq!("i4".parse::<Square>(), Err(SquareError::File('i')));
+        assert_eq!("e9".parse::<Square>(), Err(SquareError::Rank('9')));
+        assert_eq!("E4".parse::<Square>(), Err(SquareError::File('E')));
+    }
+
+    #[test]
+    fn test_file_rank_masks() {
+        assert_eq!(File::A.mask(), BitBoard(0x0101010101010101));
+        assert_eq!(File::H.mask(), BitBoard(0x8080808080808080));
+        assert_eq!(Rank::First.mask(), BitBoard(0x00000000000000FF));
+        assert_eq!(Rank::Eighth.mask(), BitBoard(0xFF00000000000000));
+        assert_eq!(File::D.neighbors(), File::C.mask() | File::E.mask());
+        assert_eq!(File::A.neighbors(), File::B.mask());
+        assert_eq!(File::H.neighbors(), File::G.mask());
+        assert_eq!(File::ALL.iter().fold(BitBoard::EMPTY, |all, file| all | file.mask()), BitBoard::ALL);
+        assert_eq!(Rank::Second.flip(), Rank::Seventh);
+        assert!(File::E.mask().contains(named::E4) && Rank::Fourth.mask().contains(named::E4));
+    }
 }
@@ -0,0 +1,183 @@
+//! Position consistency checks
+//!
+//! Finds board states that can't arise in a game: bitboards that overlap
+//! or disagree with the square array, pawns on the back ranks, missing or
+//! extra kings, the side not to move left in check, castling rights without
+//! the king and rook at home, and en passant squares no pawn just skipped
+use std::fmt;
+use super::{ BitBoard, Board, Castling, Color, MoveGenerator, Piece, PieceType, Rank, Side, Square, KEYS };
+use super::named::{ A1, A8, E1, E8, H1, H8 };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    // the square is set in more than one piece bitboard
+    OverlappingPieces(Square),
+    // the square array and the bitboards disagree about the square
+    OutOfSync(Square),
+    PawnOnBackRank(Square),
+    KingCount { color: Color, count: u32 },
+    // the side that just moved left its king attacked
+    OpponentInCheck,
+    // a castling right (as its FEN letter) whose king or rook has moved
+    CastlingWithoutPieces(char),
+    BadEnPassant(Square),
+    // the incremental zobrist key differs from a fresh hash
+    StaleKey,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::OverlappingPieces(sq) => write!(f, "more than one piece on {}", sq),
+            PositionError::OutOfSync(sq) => write!(f, "square array and bitboards disagree on {}", sq),
+            PositionError::PawnOnBackRank(sq) => write!(f, "pawn on the back rank at {}", sq),
+            PositionError::KingCount { color, count } => write!(f, "{} has {} kings", if *color == Color::White { "white" } else { "black" }, count),
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            PositionError::CastlingWithoutPieces(right) => write!(f, "castling right {} without the king and rook at home", right),
+            PositionError::BadEnPassant(sq) => write!(f, "en passant square {} does not follow a double pawn push", sq),
+            PositionError::StaleKey => write!(f, "zobrist key does not match the position"),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+// (right, FEN letter, king square, rook square, colour)
+const CASTLING_HOMES: [(u8, char, Square, Square, Color); 4] = [
+    (Castling::WHITE_00, 'K', E1, H1, Color::White),
+    (Castling::WHITE_000, 'Q', E1, A1, Color::White),
+    (Castling::BLACK_00, 'k', E8, H8, Color::Black),
+    (Castling::BLACK_000, 'q', E8, A8, Color::Black),
+];
+
+impl Board {
+    /// Every way the position breaks the rules, or Ok if it could occur in a game.
+    pub fn validate(&self) -> Result<(), Vec<PositionError>> {
+        let mut errors = self.structural_errors();
+        let kings_present = !errors.iter().any(|e| matches!(e, PositionError::KingCount { .. }));
+        if kings_present && MoveGenerator::global().in_check(self, !self.side_to_move) {
+            errors.push(PositionError::OpponentInCheck);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // all of validate but the check test; make_move plays pseudo-legal moves
+    // that may leave the mover in check, and the caller takes those back
+    pub(crate) fn debug_validate(&self) {
+        debug_assert_eq!(self.structural_errors(), Vec::new(), "after {:?} in {}", self.move_history().last(), self.to_fen());
+    }
+
+    fn structural_errors(&self) -> Vec<PositionError> {
+        let mut errors = Vec::new();
+
+        let mut seen = BitBoard::EMPTY;
+        for bb in self.piece_boards {
+            for sq in bb & seen {
+                errors.push(PositionError::OverlappingPieces(sq));
+            }
+            seen |= bb;
+        }
+        // the colour and type boards are unions of the piece boards
+        let by_color = |color| PieceType::ALL.iter().fold(BitBoard::EMPTY, |acc, &kind| acc | *self.piece_bb(Side(color), kind));
+        let by_type = |kind| *self.piece_bb(Side::WHITE, kind) | *self.piece_bb(Side::BLACK, kind);
+        let mut stray = self.occupied() ^ seen;
+        for color in [Color::White, Color::Black] {
+            stray |= self.side_pieces(Side(color)) ^ by_color(color);
+        }
+        for kind in PieceType::ALL {
+            stray |= self.type_pieces(kind) ^ by_type(kind);
+        }
+        for sq in Square::all() {
+            let in_sync = match self.pieces[sq.index()] {
+                Some(piece) => self.piece_boards[piece.to_index()].contains(sq),
+                None => !seen.contains(sq),
+            };
+            if !in_sync || stray.contains(sq) {
+                errors.push(PositionError::OutOfSync(sq));
+            }
+        }
+
+        let back_ranks = Rank::First.mask() | Rank::Eighth.mask();
+        for sq in self.type_pieces(PieceType::Pawn) & back_ranks {
+            errors.push(PositionError::PawnOnBackRank(sq));
+        }
+
+        for color in [Color::White, Color::Black] {
+            let count = self.piece_bb(Side(color), PieceType::King).pop_count();
+            if count != 1 {
+                errors.push(PositionError::KingCount { color, count });
+            }
+        }
+
+        for (right, letter, king, rook, color) in CASTLING_HOMES {
+            let home = self.pieces[king.index()] == Some(Piece::new(color, PieceType::King))
+                && self.pieces[rook.index()] == Some(Piece::new(color, PieceType::Rook));
+            if self.castling_rights.0 & right != 0 && !home {
+                errors.push(PositionError::CastlingWithoutPieces(letter));
+            }
+        }
+
+        if let Some(sq) = self.en_passant {
+            // the pawn that just moved belongs to the side not to move
+            let pusher = !self.side_to_move;
+            let (rank, forward) = match pusher {
+                Color::White => (Rank::Third, 1),
+                Color::Black => (Rank::Sixth, -1),
+            };
+            let pushed = sq.offset(0, forward).and_then(|to| self.pieces[to.index()]) == Some(Piece::new(pusher, PieceType::Pawn));
+            let vacated = sq.offset(0, -forward).is_some_and(|from| self.pieces[from.index()].is_none());
+            if sq.rank() != rank || self.pieces[sq.index()].is_some() || !pushed || !vacated {
+                errors.push(PositionError::BadEnPassant(sq));
+            }
+        }
+
+        if self.zobrist_key != KEYS.hash(self) {
+            errors.push(PositionError::StaleKey);
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ try_parse_fen, try_parse_fen_unchecked };
+
+    fn errors(fen: &str) -> Vec<PositionError> {
+        let board: Board = try_parse_fen_unchecked(fen).unwrap().into();
+        board.validate().err().unwrap_or_default()
+    }
+
+    #[test]
+    fn test_legal_positions() {
+        assert_eq!(errors("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"), vec![]);
+        assert_eq!(errors("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![]);
+        // the side to move may be in check, only the other side may not
+        assert_eq!(errors("4k3/8/8/8/8/8/8/4K2r w - - 0 1"), vec![]);
+    }
+
+    #[test]
+    fn test_illegal_positions() {
+        assert_eq!(errors("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), vec![PositionError::CastlingWithoutPieces('K')]);
+        assert_eq!(errors("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"), vec![PositionError::PawnOnBackRank(A8)]);
+        assert_eq!(errors("4k2R/8/8/8/8/8/8/4K3 w - - 0 1"), vec![PositionError::OpponentInCheck]);
+        assert_eq!(errors("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"), vec![PositionError::BadEnPassant("e3".parse().unwrap())]);
+        assert_eq!(errors("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"), vec![PositionError::BadEnPassant("e3".parse().unwrap())]);
+
+        let mut board: Board = try_parse_fen_unchecked("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap().into();
+        board.piece_boards[Piece::WhiteQueen.to_index()].set_bit(E1.index());
+        board.pieces[A1.index()] = Some(Piece::BlackRook);
+        let found = board.validate().unwrap_err();
+        assert!(found.contains(&PositionError::OverlappingPieces(E1)));
+        assert!(found.contains(&PositionError::OutOfSync(A1)));
+        assert!(found.contains(&PositionError::OutOfSync(E1)));
+    }
+
+    #[test]
+    fn test_fen_is_validated() {
+        let error = try_parse_fen("4k3/8/8/8/8/8/8/8 w - - 0 1").err().unwrap();
+        assert!(error.contains("white has 0 kings"), "{}", error);
+        assert!(try_parse_fen("4k2R/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(try_parse_fen("4k2R/8/8/8/8/8/8/4K3 b - - 0 1").is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+//! EPD parsing
+//!
+//! Extended Position Description: the first four FEN fields followed by
+//! semicolon-terminated operations such as `bm Qg6; id "WAC.001";`
+use super::{ try_parse_fen, Board, Move, MoveGenerator };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub board: Board,
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn operation(&self, opcode: &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.opcode == opcode)
+    }
+
+    // operands of `opcode`, empty when it is missing
+    pub fn operands(&self, opcode: &str) -> &[String] {
+        self.operation(opcode).map_or(&[], |op| &op.operands)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id").first().map(String::as_str)
+    }
+
+    // comments c0 to c9
+    pub fn comment(&self, n: usize) -> Option<&str> {
+        self.operands(&format!("c{}", n)).first().map(String::as_str)
+    }
+
+    pub fn best_moves(&self) -> Result<Vec<Move>, String> {
+        self.moves("bm")
+    }
+
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, String> {
+        self.moves("am")
+    }
+
+    /// Predicted variation; each move is read in the position after the previous ones.
+    pub fn pv(&self) -> Result<Vec<Move>, String> {
+        let mut board = self.board.clone();
+        let mut line = Vec::new();
+        for text in self.operands("pv") {
+            let mv = read_move(&mut board, text)?;
+            board.make_move(mv);
+            line.push(mv);
+        }
+        Ok(line)
+    }
+
+    // mate in this many moves
+    pub fn direct_mate(&self) -> Option<u32> {
+        self.operands("dm").first()?.parse().ok()
+    }
+
+    // centipawn evaluation for the side to move
+    pub fn centipawn_eval(&self) -> Option<i32> {
+        self.operands("ce").first()?.parse().ok()
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, String> {
+        let mut board = self.board.clone();
+        self.operands(opcode).iter().map(|text| read_move(&mut board, text)).collect()
+    }
+}
+
+// SAN as the standard wants, coordinate notation as some suites have it
+fn read_move(board: &mut Board, text: &str) -> Result<Move, String> {
+    let mg = MoveGenerator::global();
+    mg.parse_san(board, text)
+        .or_else(|| mg.parse_move(board, text))
+        .ok_or_else(|| format!("illegal move '{}'", text))
+}
+
+fn read_operations(text: &str) -> Result<Vec<Operation>, String> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    let mut finish = |words: &mut Vec<String>| -> Result<(), String> {
+        if words.is_empty() {
+            return Ok(());
+        }
+        let opcode = words.remove(0);
+        if !opcode.chars().next().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return Err(format!("invalid opcode '{}'", opcode));
+        }
+        operations.push(Operation { opcode, operands: std::mem::take(words) });
+        Ok(())
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => finish(&mut words)?,
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unterminated string".to_string()),
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                    }
+                }
+                words.push(string);
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == ';' || next == '"' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                words.push(word);
+            },
+        }
+    }
+    // the last operation may be missing its semicolon
+    finish(&mut words)?;
+    Ok(operations)
+}
+
+/// Parses one EPD record. The half-move clock and move number come from the
+/// `hmvc` and `fmvn` operations when present.
+pub fn parse_epd(line: &str) -> Result<Epd, String> {
+    let mut rest = line.trim();
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err("Invalid EPD: expected four position fields".to_string());
+        }
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let operations = read_operations(rest)?;
+    let number = |opcode: &str, default: &str| {
+        operations.iter()
+            .find(|op| op.opcode == opcode)
+            .and_then(|op| op.operands.first().cloned())
+            .unwrap_or_else(|| default.to_string())
+    };
+    let fen = format!("{} {} {}", fields.join(" "), number("hmvc", "0"), number("fmvn", "1"));
+    let board = try_parse_fen(&fen)?.into();
+    Ok(Epd { board, operations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_epd() {
+        let epd = parse_epd("2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate; in 3\";").unwrap();
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.comment(0), Some("mate; in 3"));
+        assert_eq!(epd.comment(1), None);
+        assert_eq!(epd.best_moves().unwrap().iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), vec!["g3g6"]);
+        assert!(epd.avoid_moves().unwrap().is_empty());
+        assert_eq!(epd.board.to_fen(), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+
+        let epd = parse_epd("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - am Kf1 h3; bm Rd8#; dm 1; ce 32766; pv Rd8#; hmvc 12; fmvn 40").unwrap();
+        assert_eq!(epd.avoid_moves().unwrap().len(), 2);
+        assert_eq!(epd.direct_mate(), Some(1));
+        assert_eq!(epd.centipawn_eval(), Some(32766));
+        assert_eq!(epd.pv().unwrap()[0].to_string(), "d1d8");
+        assert_eq!(epd.board.half_move, 12);
+        assert_eq!(epd.board.full_move, 40);
+    }
+
+    #[test]
+    fn test_invalid_epd() {
+        assert!(parse_epd("8/8/8 w -").is_err());
+        assert!(parse_epd("8/8/8/8/8/8/8/8 w - - bm Qg6;").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/6K1 w - - id \"open").is_err());
+        let epd = parse_epd("6k1/8/8/8/8/8/8/6K1 w - - bm Qd8;").unwrap();
+        assert_eq!(epd.best_moves().unwrap_err(), "illegal move 'Qd8'");
+    }
+}
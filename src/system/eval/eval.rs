@@ -0,0 +1,269 @@
+//! Static evaluation
+//! 
+//! Material, piece-square tables, pawn structure, mobility and king safety,
+//! tapered between middlegame and endgame by the remaining material. The
+//! weights come from `EvalParams`; every term is linear in them.
+use super::{ layout, BitBoard, Board, Color, EvalParams, File, MoveGenerator, Piece, Side, Square };
+
+// phase weight of each piece type; 24 is the full starting material
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// 0 (bare kings) ..= MAX_PHASE (all pieces on the board)
+pub fn game_phase(piece_boards: &[BitBoard; 12]) -> i32 {
+    let phase: i32 = piece_boards.iter()
+        .enumerate()
+        .map(|(i, bb)| PHASE_WEIGHTS[i % 6] * bb.pop_count() as i32)
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+/// Receives every evaluation term as a parameter index and how often it
+/// applies, positive for white and negative for black.
+pub trait Trace {
+    fn add(&mut self, index: usize, count: i32);
+}
+
+// sums weighted terms into middlegame and endgame scores
+struct Scorer<'a> {
+    params: &'a EvalParams,
+    mg: i32,
+    eg: i32,
+}
+
+impl Trace for Scorer<'_> {
+    #[inline]
+    fn add(&mut self, index: usize, count: i32) {
+        let weight = self.params.weights[index];
+        self.mg += weight.mg * count;
+        self.eg += weight.eg * count;
+    }
+}
+
+/// Terms of a position, for tuning: (parameter index, count) pairs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features(pub Vec<(usize, i32)>);
+
+impl Trace for Features {
+    fn add(&mut self, index: usize, count: i32) {
+        if count != 0 {
+            self.0.push((index, count));
+        }
+    }
+}
+
+fn pawn_attacks(pawns: BitBoard, side: Color) -> BitBoard {
+    match side {
+        Color::White => ((pawns << 7) & !File::H.mask()) | ((pawns << 9) & !File::A.mask()),
+        Color::Black => ((pawns >> 9) & !File::H.mask()) | ((pawns >> 7) & !File::A.mask()),
+    }
+}
+
+// squares strictly in front of `sq` from `side`'s point of view
+fn ahead(sq: Square, side: Color) -> BitBoard {
+    let rank = sq.rank().index() as u32;
+    match side {
+        Color::White if rank < 7 => BitBoard::ALL << ((rank + 1) * 8),
+        Color::Black if rank > 0 => BitBoard::ALL >> ((8 - rank) * 8),
+        _ => BitBoard::EMPTY,
+    }
+}
+
+// every term for one side, counted with `sign`
+fn trace_side<T: Trace>(board: &Board, side: Color, trace: &mut T) {
+    let mg = MoveGenerator::global();
+    let sign = if side == Color::White { 1 } else { -1 };
+    let (base, enemy_base) = if side == Color::White { (0, 6) } else { (6, 0) };
+    let bb = &board.piece_boards;
+    let own = board.side_pieces(Side(side));
+    let occupied = board.occupied();
+    let pawns = bb[base];
+    let enemy_pawns = bb[enemy_base];
+    // tables are stored rank 8 first from white's side
+    let table_sq = |sq: usize| if side == Color::White { sq ^ 56 } else { sq };
+
+    for kind in 0..6 {
+        let pieces = bb[base + kind];
+        trace.add(layout::MATERIAL + kind, sign * pieces.pop_count() as i32);
+        for sq in pieces {
+            trace.add(layout::PSQT + kind * 64 + table_sq(sq.index()), sign);
+        }
+    }
+    if bb[base + 2].pop_count() >= 2 {
+        trace.add(layout::BISHOP_PAIR, sign);
+    }
+
+    // pawn structure
+    for file in File::ALL {
+        let on_file = (pawns & file.mask()).pop_count() as i32;
+        if on_file > 1 {
+            trace.add(layout::DOUBLED_PAWN, sign * (on_file - 1));
+        }
+    }
+    for sq in pawns {
+        let file = sq.file();
+        if (pawns & file.neighbors()).is_empty() {
+            trace.add(layout::ISOLATED_PAWN, sign);
+        }
+        let front_span = ahead(sq, side) & (file.mask() | file.neighbors());
+        if (enemy_pawns & front_span).is_empty() {
+            let rank = if side == Color::White { sq.rank() } else { sq.rank().flip() };
+            trace.add(layout::PASSED_PAWN + rank.index(), sign);
+        }
+    }
+
+    // mobility and attacks on the squares around the enemy king
+    let enemy = !side;
+    let safe = !own & !pawn_attacks(enemy_pawns, enemy);
+    let enemy_king = board.king_square(enemy);
+    let king_zone = mg.king_attacks(enemy_king) | BitBoard::from(enemy_king);
+    for kind in 1..5 {
+        let piece = Piece::from_index(base + kind);
+        for sq in bb[base + kind] {
+            let attacks = mg.piece_attacks(piece, sq, occupied);
+            trace.add(layout::MOBILITY + kind - 1, sign * (attacks & safe).pop_count() as i32);
+            trace.add(layout::KING_ATTACK + kind - 1, sign * (attacks & king_zone).pop_count() as i32);
+        }
+    }
+
+    // pawns sheltering the king
+    let king = board.king_square(side);
+    let king_rank = king.rank().index() as u32;
+    let shelter_ranks = ahead(king, side) & match side {
+        Color::White => BitBoard::ALL >> (64 - ((king_rank + 3) * 8).min(64)),
+        Color::Black => BitBoard::ALL << (king_rank.saturating_sub(2) * 8),
+    };
+    let shelter = shelter_ranks & (king.file().mask() | king.file().neighbors());
+    trace.add(layout::PAWN_SHIELD, sign * (pawns & shelter).pop_count() as i32);
+}
+
+/// Feeds every term of the position to `trace`, from white's point of view.
+pub fn trace<T: Trace>(board: &Board, trace: &mut T) {
+    trace_side(board, Color::White, trace);
+    trace_side(board, Color::Black, trace);
+}
+
+pub fn features(board: &Board) -> Features {
+    let mut features = Features::default();
+    trace(board, &mut features);
+    features
+}
+
+// blends middlegame and endgame scores by the game phase
+pub fn taper(mg: i32, eg: i32, phase: i32) -> i32 {
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// (middlegame, endgame) piece-square score from white's point of view
+pub fn psqt(piece_boards: &[BitBoard; 12]) -> (i32, i32) {
+    let params = EvalParams::global();
+    let (mut mg, mut eg) = (0, 0);
+    for (i, bb) in piece_boards.iter().enumerate() {
+        let white = i < 6;
+        for sq in *bb {
+            let idx = if white { sq.flip_vertical() } else { sq }.index();
+            let sign = if white { 1 } else { -1 };
+            let weight = params.weights[layout::PSQT + (i % 6) * 64 + idx];
+            mg += sign * weight.mg;
+            eg += sign * weight.eg;
+        }
+    }
+    (mg, eg)
+}
+
+// material balance from white's point of view
+pub fn material(piece_boards: &[BitBoard; 12]) -> i32 {
+    Piece::ALL.iter()
+        .zip(piece_boards)
+        .map(|(piece, bb)| {
+            let value = piece.value() * bb.pop_count() as i32;
+            if piece.color() == Color::White { value } else { -value }
+        })
+        .sum()
+}
+
+// piece-square score blended by game phase, from white's point of view
+pub fn tapered_psqt(piece_boards: &[BitBoard; 12]) -> i32 {
+    let (mg, eg) = psqt(piece_boards);
+    taper(mg, eg, game_phase(piece_boards))
+}
+
+// centipawn score from the side to move's point of view, with the given weights
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> i32 {
+    let mut scorer = Scorer { params, mg: 0, eg: 0 };
+    trace(board, &mut scorer);
+    let score = taper(scorer.mg, scorer.eg, game_phase(&board.piece_boards));
+
+    match board.side_to_move {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// centipawn score from the side to move's point of view
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with(board, EvalParams::global())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    #[test]
+    fn test_start_position_is_balanced() {
+        let board: Board = parse_fen(START_FEN).into();
+        assert_eq!(evaluate(&board), 0);
+        assert_eq!(game_phase(&board.piece_boards), MAX_PHASE);
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        // same position with colours flipped should score the same for the side to move
+        let white: Board = parse_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").into();
+        let black: Board = parse_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4").into();
+        assert_eq!(evaluate(&white), evaluate(&black));
+    }
+
+    #[test]
+    fn test_material_advantage() {
+        // white is a queen up
+        let board: Board = parse_fen("rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").into();
+        assert!(evaluate(&board) < -800);
+        assert_eq!(material(&board.piece_boards), 900);
+    }
+
+    #[test]
+    fn test_features_match_evaluation() {
+        let params = EvalParams::default();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "6k1/5ppp/8/3P4/8/8/5PPP/6K1 w - - 0 1",
+        ] {
+            let board: Board = parse_fen(fen).into();
+            let (mut mg, mut eg) = (0, 0);
+            for (index, count) in features(&board).0 {
+                mg += params.weights[index].mg * count;
+                eg += params.weights[index].eg * count;
+            }
+            let white = taper(mg, eg, game_phase(&board.piece_boards));
+            let expected = if board.side_to_move == Color::White { white } else { -white };
+            assert_eq!(evaluate_with(&board, &params), expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // white: doubled and isolated c pawns; black: a passed pawn on a3
+        let board: Board = parse_fen("6k1/8/8/8/2P5/p1P5/8/6K1 w - - 0 1").into();
+        let features = features(&board).0;
+        let count = |index: usize| features.iter().filter(|(i, _)| *i == index).map(|(_, c)| c).sum::<i32>();
+        assert_eq!(count(layout::DOUBLED_PAWN), 1);
+        // two isolated white pawns against one black
+        assert_eq!(count(layout::ISOLATED_PAWN), 1);
+        // a3 is on black's sixth rank; both white pawns are passed as well
+        assert_eq!(count(layout::PASSED_PAWN + 5), -1);
+        assert_eq!(count(layout::PASSED_PAWN + 2) + count(layout::PASSED_PAWN + 3), 2);
+    }
+}
@@ -0,0 +1,11 @@
+use super::*;
+#[allow(clippy::module_inception)]
+pub mod eval;
+pub mod nnue;
+pub mod params;
+pub mod see;
+
+pub use eval::*;
+pub use nnue::*;
+pub use params::*;
+pub use see::*;
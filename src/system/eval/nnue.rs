@@ -0,0 +1,433 @@
+//! NNUE evaluation
+//!
+//! A 768 -> N -> 1 perspective network: one input per (colour relative to
+//! the perspective, piece type, square), a clipped-ReLU hidden layer per
+//! side and one output neuron. Accumulators are int16 and follow the board
+//! incrementally from the piece deltas of each move; unmaking a move pops
+//! back to the previous accumulator. Inference has a scalar path and SSE2 and
+//! AVX2 paths picked at runtime, all giving identical results.
+use std::fs;
+use std::sync::Arc;
+use super::{ Board, Color, Piece, Square, MAX_PLY };
+
+const MAGIC: &[u8; 8] = b"RUSHNNUE";
+pub const NNUE_VERSION: u32 = 1;
+pub const INPUTS: usize = 768;
+// hidden sizes must be a multiple of the widest SIMD register
+const MAX_HIDDEN: usize = 4096;
+const LANES: usize = 16;
+const HEADER_LEN: usize = 20;
+
+// quantisation of the hidden activations and output weights, and the
+// scale from the output to centipawns
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i64 = 400;
+
+/// Input index of a piece seen from `perspective`: own pieces first, and
+/// black sees the board flipped.
+pub fn feature(perspective: Color, piece: Piece, square: Square) -> usize {
+    let kind = piece.kind().index();
+    let relative = if piece.color() == perspective { kind } else { kind + 6 };
+    let square = if perspective == Color::White { square } else { square.flip_vertical() };
+    relative * 64 + square.index()
+}
+
+// FNV-1a, over everything after the header
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simd {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl Simd {
+    /// The widest instruction set this CPU supports.
+    pub fn detect() -> Self {
+        [Simd::Avx2, Simd::Sse2].into_iter().find(|simd| simd.is_supported()).unwrap_or(Simd::Scalar)
+    }
+
+    /// Whether this CPU can run the instruction set.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Simd::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse2 => is_x86_feature_detected!("sse2"),
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => false,
+        }
+    }
+
+    // acc += weights, wrapping like the vector instructions do
+    fn add(self, acc: &mut [i16], weights: &[i16]) {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { x86::add_avx2(acc, weights) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse2 => unsafe { x86::add_sse2(acc, weights) },
+            _ => acc.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_add(*w)),
+        }
+    }
+
+    fn sub(self, acc: &mut [i16], weights: &[i16]) {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { x86::sub_avx2(acc, weights) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse2 => unsafe { x86::sub_sse2(acc, weights) },
+            _ => acc.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_sub(*w)),
+        }
+    }
+
+    // sum of clamp(acc, 0, QA) * weights
+    fn dot(self, acc: &[i16], weights: &[i16]) -> i32 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { x86::dot_avx2(acc, weights) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse2 => unsafe { x86::dot_sse2(acc, weights) },
+            _ => acc.iter()
+                .zip(weights)
+                .fold(0i32, |sum, (&a, &w)| sum.wrapping_add(a.clamp(0, QA as i16) as i32 * w as i32)),
+        }
+    }
+}
+
+// callers guarantee equal lengths that are a multiple of LANES and that the
+// CPU supports the instruction set
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+    use super::QA;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn add_avx2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i) as *mut __m256i, _mm256_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn sub_avx2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i) as *mut __m256i, _mm256_sub_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn dot_avx2(acc: &[i16], weights: &[i16]) -> i32 {
+        let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(QA as i16));
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..acc.len()).step_by(16) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), max);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, w));
+        }
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+        lanes.iter().fold(0, |total, &lane| total.wrapping_add(lane))
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn add_sse2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(8) {
+            let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+            let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+            _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_add_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn sub_sse2(acc: &mut [i16], weights: &[i16]) {
+        for i in (0..acc.len()).step_by(8) {
+            let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+            let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+            _mm_storeu_si128(acc.as_mut_ptr().add(i) as *mut __m128i, _mm_sub_epi16(a, w));
+        }
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn dot_sse2(acc: &[i16], weights: &[i16]) -> i32 {
+        let (zero, max) = (_mm_setzero_si128(), _mm_set1_epi16(QA as i16));
+        let mut sum = _mm_setzero_si128();
+        for i in (0..acc.len()).step_by(8) {
+            let a = _mm_loadu_si128(acc.as_ptr().add(i) as *const __m128i);
+            let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+            let clipped = _mm_min_epi16(_mm_max_epi16(a, zero), max);
+            sum = _mm_add_epi32(sum, _mm_madd_epi16(clipped, w));
+        }
+        let mut lanes = [0i32; 4];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, sum);
+        lanes.iter().fold(0, |total, &lane| total.wrapping_add(lane))
+    }
+}
+
+/// Network weights.
+///
+/// File layout, little-endian: the magic `RUSHNNUE`, the format version, the
+/// hidden size N and an FNV-1a hash of the rest of the file, followed by the
+/// input weights (i16, 768 rows of N), the hidden biases (i16, N), the output
+/// weights (i16, N for the side to move then N for the other side) and the
+/// output bias (i32).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn new(hidden: usize, feature_weights: Vec<i16>, feature_bias: Vec<i16>, output_weights: Vec<i16>, output_bias: i32) -> Result<Self, String> {
+        if hidden == 0 || hidden > MAX_HIDDEN || !hidden.is_multiple_of(LANES) {
+            return Err(format!("hidden size {} is not a multiple of {} up to {}", hidden, LANES, MAX_HIDDEN));
+        }
+        if feature_weights.len() != INPUTS * hidden || feature_bias.len() != hidden || output_weights.len() != 2 * hidden {
+            return Err("weight counts do not match the hidden size".to_string());
+        }
+        Ok(Self { hidden, feature_weights, feature_bias, output_weights, output_bias })
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err("not an NNUE file".to_string());
+        }
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let (version, hidden, hash) = (word(8), word(12) as usize, word(16));
+        if version != NNUE_VERSION {
+            return Err(format!("unsupported network version {} (expected {})", version, NNUE_VERSION));
+        }
+        let body = &bytes[HEADER_LEN..];
+        if body.len() != 2 * (INPUTS * hidden + 3 * hidden) + 4 {
+            return Err(format!("wrong file size for hidden size {}", hidden));
+        }
+        if checksum(body) != hash {
+            return Err("hash mismatch, the file is corrupt".to_string());
+        }
+
+        let mut values = body.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let mut take = |n: usize| values.by_ref().take(n).collect::<Vec<i16>>();
+        let feature_weights = take(INPUTS * hidden);
+        let feature_bias = take(hidden);
+        let output_weights = take(2 * hidden);
+        let output_bias = i32::from_le_bytes(body[body.len() - 4..].try_into().unwrap());
+        Self::new(hidden, feature_weights, feature_bias, output_weights, output_bias)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(2 * (INPUTS + 3) * self.hidden + 4);
+        for value in self.feature_weights.iter().chain(&self.feature_bias).chain(&self.output_weights) {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend_from_slice(&self.output_bias.to_le_bytes());
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&NNUE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+        bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn column(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+}
+
+/// Accumulators for one search: a stack with one entry per ply, each holding
+/// the white-perspective half followed by the black-perspective half.
+#[derive(Debug, Clone)]
+pub struct Nnue {
+    network: Arc<Network>,
+    simd: Simd,
+    stack: Vec<Vec<i16>>,
+    top: usize,
+}
+
+impl Nnue {
+    pub fn new(network: Arc<Network>) -> Self {
+        Self::with_simd(network, Simd::detect())
+    }
+
+    // an instruction set the CPU lacks falls back to the scalar code
+    pub fn with_simd(network: Arc<Network>, simd: Simd) -> Self {
+        let simd = if simd.is_supported() { simd } else { Simd::Scalar };
+        let stack = vec![vec![0; 2 * network.hidden]; MAX_PLY + 1];
+        Self { network, simd, stack, top: 0 }
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Rebuilds the accumulator from scratch and empties the stack.
+    pub fn refresh(&mut self, board: &Board) {
+        let (network, simd) = (&self.network, self.simd);
+        let hidden = network.hidden;
+        self.top = 0;
+        let acc = &mut self.stack[0];
+        acc[..hidden].copy_from_slice(&network.feature_bias);
+        acc[hidden..].copy_from_slice(&network.feature_bias);
+        for (square, piece) in board.pieces.iter().enumerate() {
+            let Some(piece) = *piece else { continue };
+            let (white, black) = acc.split_at_mut(hidden);
+            simd.add(white, network.column(feature(Color::White, piece, Square(square as u8))));
+            simd.add(black, network.column(feature(Color::Black, piece, Square(square as u8))));
+        }
+    }
+
+    /// Follows a move just made on `board`, from its piece deltas.
+    pub fn push(&mut self, board: &Board) {
+        let (network, simd) = (&self.network, self.simd);
+        let hidden = network.hidden;
+        if self.top + 1 == self.stack.len() {
+            self.stack.push(vec![0; 2 * hidden]);
+        }
+        let (done, rest) = self.stack.split_at_mut(self.top + 1);
+        let acc = &mut rest[0];
+        acc.copy_from_slice(&done[self.top]);
+        self.top += 1;
+
+        let deltas = board.last_deltas();
+        let (white, black) = acc.split_at_mut(hidden);
+        for &(piece, square) in deltas.removed() {
+            simd.sub(white, network.column(feature(Color::White, piece, square)));
+            simd.sub(black, network.column(feature(Color::Black, piece, square)));
+        }
+        for &(piece, square) in deltas.added() {
+            simd.add(white, network.column(feature(Color::White, piece, square)));
+            simd.add(black, network.column(feature(Color::Black, piece, square)));
+        }
+    }
+
+    /// Goes back to the accumulator before the last `push`.
+    pub fn pop(&mut self) {
+        self.top = self.top.saturating_sub(1);
+    }
+
+    /// Centipawn score from the side to move's point of view.
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let network = &self.network;
+        let hidden = network.hidden;
+        let (white, black) = self.stack[self.top].split_at(hidden);
+        let (us, them) = match board.side_to_move {
+            Color::White => (white, black),
+            Color::Black => (black, white),
+        };
+        let sum = self.simd.dot(us, &network.output_weights[..hidden])
+            .wrapping_add(self.simd.dot(them, &network.output_weights[hidden..]));
+        ((sum as i64 + network.output_bias as i64) * SCALE / (QA * QB) as i64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, LcgRng, MoveGenerator, START_FEN };
+
+    fn random_network(hidden: usize, seed: u64) -> Network {
+        let mut rng = LcgRng::new(seed);
+        let mut values = |n: usize, range: i64| -> Vec<i16> {
+            (0..n).map(|_| ((rng.next_u64() >> 33) as i64 % (2 * range) - range) as i16).collect()
+        };
+        let feature_weights = values(INPUTS * hidden, 48);
+        let feature_bias = values(hidden, 64);
+        let output_weights = values(2 * hidden, 64);
+        Network::new(hidden, feature_weights, feature_bias, output_weights, 1234).unwrap()
+    }
+
+    fn simd_paths() -> Vec<Simd> {
+        [Simd::Scalar, Simd::Sse2, Simd::Avx2].into_iter().filter(|simd| simd.is_supported()).collect()
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let network = random_network(32, 1);
+        let bytes = network.to_bytes();
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), network);
+
+        let mut corrupt = bytes.clone();
+        corrupt[100] ^= 1;
+        assert!(Network::from_bytes(&corrupt).unwrap_err().contains("hash"));
+        let mut old = bytes.clone();
+        old[8] = 0;
+        assert!(Network::from_bytes(&old).unwrap_err().contains("version"));
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Network::from_bytes(b"not a network").is_err());
+        assert!(Network::new(20, vec![0; INPUTS * 20], vec![0; 20], vec![0; 40], 0).is_err());
+    }
+
+    #[test]
+    fn test_incremental_matches_refresh() {
+        let network = Arc::new(random_network(64, 7));
+        let mg = MoveGenerator::global();
+        // castling both ways, en passant and promotions with and without capture
+        let fen = "r3k2r/pPpp1ppp/8/3Pp3/8/8/P1PP1PpP/R3K2R w KQkq e6 0 1";
+        for simd in simd_paths() {
+            let mut board: Board = parse_fen(fen).into();
+            let mut nnue = Nnue::with_simd(Arc::clone(&network), simd);
+            nnue.refresh(&board);
+            let root = nnue.evaluate(&board);
+
+            for mv in mg.legal_moves(&mut board) {
+                board.make_move(mv);
+                nnue.push(&board);
+                for reply in mg.legal_moves(&mut board) {
+                    board.make_move(reply);
+                    nnue.push(&board);
+                    let mut fresh = Nnue::with_simd(Arc::clone(&network), Simd::Scalar);
+                    fresh.refresh(&board);
+                    assert_eq!(nnue.evaluate(&board), fresh.evaluate(&board), "{:?} {} {}", simd, mv, reply);
+                    board.unmake_move();
+                    nnue.pop();
+                }
+                board.unmake_move();
+                nnue.pop();
+            }
+            assert_eq!(nnue.evaluate(&board), root);
+        }
+    }
+
+    #[test]
+    fn test_simd_paths_agree() {
+        // large weights so the clipping and wrapping paths are exercised
+        let mut network = random_network(128, 3);
+        network.feature_weights.iter_mut().step_by(5).for_each(|w| *w = w.wrapping_mul(300));
+        network.output_weights.iter_mut().step_by(3).for_each(|w| *w = w.wrapping_mul(500));
+        let network = Arc::new(network);
+
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+        ] {
+            let board: Board = parse_fen(fen).into();
+            let scores: Vec<i32> = simd_paths().into_iter().map(|simd| {
+                let mut nnue = Nnue::with_simd(Arc::clone(&network), simd);
+                nnue.refresh(&board);
+                nnue.evaluate(&board)
+            }).collect();
+            assert!(scores.windows(2).all(|pair| pair[0] == pair[1]), "{:?}", scores);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Evaluation parameters
+//!
+//! Every weight the evaluation uses, as (middlegame, endgame) pairs in one
+//! flat vector so the tuner can treat them all alike. The tables below are
+//! the defaults; `tune` prints replacements for them in the same form.
+use std::fmt::Write as _;
+use std::fs;
+use std::ops::Range;
+use std::sync::OnceLock;
+use super::PieceType;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+pub const fn s(mg: i32, eg: i32) -> Score {
+    Score { mg, eg }
+}
+
+// pawn, knight, bishop, rook, queen, king; the king is never traded
+const MATERIAL: [Score; 6] = [s(100, 100), s(320, 320), s(330, 330), s(500, 500), s(900, 900), s(0, 0)];
+
+// tables are written from white's point of view with rank 8 on top,
+// so white looks up `sq ^ 56` and black looks up `sq` directly
+const PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+const KNIGHT_TABLE: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+const KNIGHT_ENDGAME_TABLE: [i32; 64] = KNIGHT_TABLE;
+
+const BISHOP_TABLE: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+const BISHOP_ENDGAME_TABLE: [i32; 64] = BISHOP_TABLE;
+
+const ROOK_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+const ROOK_ENDGAME_TABLE: [i32; 64] = ROOK_TABLE;
+
+const QUEEN_TABLE: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+const QUEEN_ENDGAME_TABLE: [i32; 64] = QUEEN_TABLE;
+
+const KING_TABLE: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+const BISHOP_PAIR: Score = s(30, 30);
+const DOUBLED_PAWN: Score = s(-10, -20);
+const ISOLATED_PAWN: Score = s(-10, -10);
+// by rank from the pawn's own side, on top of the pawn tables
+const PASSED_PAWN: [Score; 8] = [s(0, 0), s(0, 5), s(5, 10), s(10, 20), s(20, 35), s(30, 55), s(45, 80), s(0, 0)];
+// per reachable square: knight, bishop, rook, queen
+const MOBILITY: [Score; 4] = [s(4, 4), s(4, 5), s(2, 4), s(1, 2)];
+// per attacked square next to the enemy king: knight, bishop, rook, queen
+const KING_ATTACK: [Score; 4] = [s(8, 0), s(6, 0), s(8, 0), s(12, 0)];
+// per own pawn on the two ranks in front of the king
+const PAWN_SHIELD: Score = s(10, 0);
+
+// (middlegame, endgame) tables per piece type
+const TABLES: [(&str, &[i32; 64], &str, &[i32; 64]); 6] = [
+    ("PAWN_TABLE", &PAWN_TABLE, "PAWN_ENDGAME_TABLE", &PAWN_ENDGAME_TABLE),
+    ("KNIGHT_TABLE", &KNIGHT_TABLE, "KNIGHT_ENDGAME_TABLE", &KNIGHT_ENDGAME_TABLE),
+    ("BISHOP_TABLE", &BISHOP_TABLE, "BISHOP_ENDGAME_TABLE", &BISHOP_ENDGAME_TABLE),
+    ("ROOK_TABLE", &ROOK_TABLE, "ROOK_ENDGAME_TABLE", &ROOK_ENDGAME_TABLE),
+    ("QUEEN_TABLE", &QUEEN_TABLE, "QUEEN_ENDGAME_TABLE", &QUEEN_ENDGAME_TABLE),
+    ("KING_TABLE", &KING_TABLE, "KING_ENDGAME_TABLE", &KING_ENDGAME_TABLE),
+];
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// positions of each group in the parameter vector
+pub mod layout {
+    pub const MATERIAL: usize = 0;
+    pub const PSQT: usize = MATERIAL + 6;
+    pub const BISHOP_PAIR: usize = PSQT + 6 * 64;
+    pub const DOUBLED_PAWN: usize = BISHOP_PAIR + 1;
+    pub const ISOLATED_PAWN: usize = DOUBLED_PAWN + 1;
+    pub const PASSED_PAWN: usize = ISOLATED_PAWN + 1;
+    pub const MOBILITY: usize = PASSED_PAWN + 8;
+    pub const KING_ATTACK: usize = MOBILITY + 4;
+    pub const PAWN_SHIELD: usize = KING_ATTACK + 4;
+    pub const NUM_PARAMS: usize = PAWN_SHIELD + 1;
+}
+
+// groups of scalar and short array parameters, for naming and printing
+const GROUPS: [(&str, usize, usize); 7] = [
+    ("BISHOP_PAIR", layout::BISHOP_PAIR, 1),
+    ("DOUBLED_PAWN", layout::DOUBLED_PAWN, 1),
+    ("ISOLATED_PAWN", layout::ISOLATED_PAWN, 1),
+    ("PASSED_PAWN", layout::PASSED_PAWN, 8),
+    ("MOBILITY", layout::MOBILITY, 4),
+    ("KING_ATTACK", layout::KING_ATTACK, 4),
+    ("PAWN_SHIELD", layout::PAWN_SHIELD, 1),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalParams {
+    pub weights: Vec<Score>,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let mut weights = vec![Score::default(); layout::NUM_PARAMS];
+        weights[layout::MATERIAL..layout::PSQT].copy_from_slice(&MATERIAL);
+        for (kind, (_, mg, _, eg)) in TABLES.iter().enumerate() {
+            for sq in 0..64 {
+                weights[layout::PSQT + kind * 64 + sq] = s(mg[sq], eg[sq]);
+            }
+        }
+        weights[layout::BISHOP_PAIR] = BISHOP_PAIR;
+        weights[layout::DOUBLED_PAWN] = DOUBLED_PAWN;
+        weights[layout::ISOLATED_PAWN] = ISOLATED_PAWN;
+        weights[layout::PASSED_PAWN..layout::MOBILITY].copy_from_slice(&PASSED_PAWN);
+        weights[layout::MOBILITY..layout::KING_ATTACK].copy_from_slice(&MOBILITY);
+        weights[layout::KING_ATTACK..layout::PAWN_SHIELD].copy_from_slice(&KING_ATTACK);
+        weights[layout::PAWN_SHIELD] = PAWN_SHIELD;
+        Self { weights }
+    }
+}
+
+static GLOBAL: OnceLock<EvalParams> = OnceLock::new();
+
+impl EvalParams {
+    /// The parameters `evaluate` uses: the defaults unless `set_global` came first.
+    pub fn global() -> &'static EvalParams {
+        GLOBAL.get_or_init(EvalParams::default)
+    }
+
+    /// Installs tuned parameters for the whole process. Only possible before
+    /// anything has evaluated a position or asked for a piece value.
+    pub fn set_global(params: EvalParams) -> Result<(), String> {
+        GLOBAL.set(params).map_err(|_| "evaluation parameters are already in use".to_string())
+    }
+
+    /// Stable name of a parameter, used in parameter files.
+    pub fn name(index: usize) -> String {
+        use layout::*;
+        match index {
+            i if i < PSQT => format!("material.{}", PIECE_NAMES[i - MATERIAL]),
+            i if i < BISHOP_PAIR => {
+                let (kind, sq) = ((i - PSQT) / 64, (i - PSQT) % 64);
+                // table order: a8 first
+                let (file, rank) = (sq % 8, 7 - sq / 8);
+                format!("psqt.{}.{}{}", PIECE_NAMES[kind], (b'a' + file as u8) as char, rank + 1)
+            },
+            i => {
+                let (group, start, len) = GROUPS.iter().rev().find(|(_, start, _)| i >= *start).unwrap();
+                let name = group.to_lowercase();
+                if *len == 1 { name } else { format!("{}.{}", name, i - start) }
+            },
+        }
+    }
+
+    /// One `name mg eg` line per parameter.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (i, w) in self.weights.iter().enumerate() {
+            let _ = writeln!(text, "{} {} {}", Self::name(i), w.mg, w.eg);
+        }
+        text
+    }
+
+    /// Reads `name mg eg` lines; parameters not mentioned keep their defaults.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+        let names: Vec<String> = (0..layout::NUM_PARAMS).map(Self::name).collect();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("line {}: expected '<name> <mg> <eg>'", number + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, mg, eg] = fields[..] else { return Err(error()) };
+            let index = names.iter().position(|n| n == name)
+                .ok_or_else(|| format!("line {}: unknown parameter '{}'", number + 1, name))?;
+            params.weights[index] = s(mg.parse().map_err(|_| error())?, eg.parse().map_err(|_| error())?);
+        }
+        Ok(params)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn scores(&self, range: Range<usize>) -> String {
+        let scores: Vec<String> = self.weights[range].iter().map(|w| format!("s({}, {})", w.mg, w.eg)).collect();
+        scores.join(", ")
+    }
+
+    /// The parameters as the constant definitions at the top of this file.
+    pub fn to_rust(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "const MATERIAL: [Score; 6] = [{}];\n", self.scores(layout::MATERIAL..layout::PSQT));
+        for (kind, (mg_name, _, eg_name, _)) in TABLES.iter().enumerate() {
+            for (name, endgame) in [(mg_name, false), (eg_name, true)] {
+                let _ = writeln!(out, "const {}: [i32; 64] = [", name);
+                for rank in 0..8 {
+                    let row: Vec<String> = (0..8).map(|file| {
+                        let w = self.weights[layout::PSQT + kind * 64 + rank * 8 + file];
+                        format!("{:>3}", if endgame { w.eg } else { w.mg })
+                    }).collect();
+                    let _ = writeln!(out, "    {},", row.join(", "));
+                }
+                let _ = writeln!(out, "];\n");
+            }
+        }
+        for (name, start, len) in GROUPS {
+            if len == 1 {
+                let w = self.weights[start];
+                let _ = writeln!(out, "const {}: Score = s({}, {});", name, w.mg, w.eg);
+            } else {
+                let _ = writeln!(out, "const {}: [Score; {}] = [{}];", name, len, self.scores(start..start + len));
+            }
+        }
+        out
+    }
+}
+
+impl PieceType {
+    /// Middlegame material weight, for exchanges and move ordering.
+    pub fn value(self) -> i32 {
+        EvalParams::global().weights[layout::MATERIAL + self.index()].mg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(EvalParams::name(1), "material.knight");
+        assert_eq!(EvalParams::name(layout::PSQT), "psqt.pawn.a8");
+        assert_eq!(EvalParams::name(layout::PSQT + 64 + 63), "psqt.knight.h1");
+        assert_eq!(EvalParams::name(layout::PASSED_PAWN + 6), "passed_pawn.6");
+        assert_eq!(EvalParams::name(layout::PAWN_SHIELD), "pawn_shield");
+        let names: std::collections::HashSet<String> = (0..layout::NUM_PARAMS).map(EvalParams::name).collect();
+        assert_eq!(names.len(), layout::NUM_PARAMS);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut params = EvalParams::default();
+        params.weights[layout::MOBILITY + 2] = s(7, -3);
+        assert_eq!(EvalParams::from_text(&params.to_text()), Ok(params));
+
+        let partial = EvalParams::from_text("# tuned\nbishop_pair 41 55\n").unwrap();
+        assert_eq!(partial.weights[layout::BISHOP_PAIR], s(41, 55));
+        assert_eq!(partial.weights[layout::MATERIAL], s(100, 100));
+        assert!(EvalParams::from_text("bishop_pair 41").is_err());
+        assert!(EvalParams::from_text("nonsense 1 2").unwrap_err().contains("unknown parameter"));
+    }
+
+    #[test]
+    fn test_global() {
+        let rook = EvalParams::global().weights[layout::MATERIAL + PieceType::Rook.index()];
+        assert_eq!(PieceType::Rook.value(), rook.mg);
+        assert!(EvalParams::set_global(EvalParams::default()).is_err());
+    }
+
+    #[test]
+    fn test_rust_source() {
+        let rust = EvalParams::default().to_rust();
+        assert!(rust.starts_with("const MATERIAL: [Score; 6] = [s(100, 100), s(320, 320),"));
+        assert!(rust.contains("const KNIGHT_ENDGAME_TABLE: [i32; 64] = [\n    -50, -40, -30, -30, -30, -30, -40, -50,\n"));
+        assert!(rust.contains("const PASSED_PAWN: [Score; 8] = [s(0, 0), s(0, 5),"));
+        assert!(rust.contains("const PAWN_SHIELD: Score = s(10, 0);\n"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Static exchange evaluation
+//! 
+//! Plays out the capture sequence on one square, cheapest attacker first,
+//! to judge whether a capture wins or loses material
+use super::{ Board, Move, MoveGenerator, Piece, PieceType, Side, Square };
+
+// kings can only recapture last, so they count as worth more than anything
+const KING_VALUE: i32 = 20_000;
+
+fn exchange_value(piece: Piece) -> i32 {
+    match piece.kind() {
+        PieceType::King => KING_VALUE,
+        _ => piece.value(),
+    }
+}
+
+// material gained by the side to move after all sensible recaptures on mv's target square
+pub fn see(mg: &MoveGenerator, board: &Board, mv: Move) -> i32 {
+    let (from, to) = (mv.from(), mv.to());
+    let Some(mover) = board.pieces[from.index()] else { return 0 };
+
+    let mut occupied = board.occupied();
+    let mut gain = [0i32; 32];
+    gain[0] = mv.captured().map_or(0, Piece::value);
+    let mut on_square = exchange_value(mover);
+    if let Some(promotion) = mv.promotion() {
+        gain[0] += promotion.value() - mover.value();
+        on_square = promotion.value();
+    }
+
+    occupied.clear_bit(from.index());
+    if let Move::EnPassant { .. } = mv {
+        occupied.clear_bit(Square::from_file_rank(to.file(), from.rank()).index());
+    }
+
+    let mut side = !board.side_to_move;
+    let mut depth = 0;
+    loop {
+        let attackers = mg.attackers_to(board, to, occupied) & occupied;
+        let ours = attackers & board.side_pieces(Side(side));
+        if ours.is_empty() {
+            break;
+        }
+
+        // least valuable attacker first
+        let Some((piece, bb)) = PieceType::ALL.into_iter()
+            .map(|kind| (Piece::new(side, kind), board.type_pieces(kind) & ours))
+            .find(|&(_, bb)| !bb.is_empty())
+        else {
+            break;
+        };
+
+        depth += 1;
+        gain[depth] = on_square - gain[depth - 1];
+        // standing pat beats continuing for both sides, so this capture is never made
+        if (-gain[depth - 1]).max(gain[depth]) < 0 {
+            depth -= 1;
+            break;
+        }
+        if depth == gain.len() - 1 {
+            break;
+        }
+
+        occupied.clear_bit(bb.lsb().index());
+        on_square = exchange_value(piece);
+        side = !side;
+    }
+
+    while depth > 0 {
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        depth -= 1;
+    }
+    gain[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::parse_fen;
+
+    fn see_uci(fen: &str, mv: &str) -> i32 {
+        let mg = MoveGenerator::global();
+        let mut board: Board = parse_fen(fen).into();
+        let mv = mg.parse_move(&mut board, mv).unwrap();
+        see(mg, &board, mv)
+    }
+
+    #[test]
+    fn test_see_exchanges() {
+        // undefended pawn
+        assert_eq!(see_uci("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+        // pawn defended by a pawn: rook for pawn
+        assert_eq!(see_uci("1k6/8/3p4/4p3/8/8/8/1K2R3 w - - 0 1", "e1e5"), 100 - 500);
+        // x-ray: queen behind rook wins the exchange sequence
+        assert_eq!(see_uci("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), 100 - 320);
+    }
+}
@@ -1,8 +1,11 @@
 //! FEN string parsing and board setup
 //! 
 //! Converts FEN strings into board positions
-use super::{ BitBoard, Castling, CastlingRights, Color, Square };
+use super::{ BitBoard, Board, Castling, CastlingRights, Color, Piece, Square };
 
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone)]
 pub struct ParsedFen {
     pub piece_boards: [BitBoard; 12],
     pub side_to_move: Color,
@@ -12,18 +15,35 @@ pub struct ParsedFen {
     pub full_move: u16,
 }
 
+// for FENs written into the source (START_FEN, tests); panics on anything
+// try_parse_fen would reject, so input from users or files goes through that
 pub fn parse_fen(fen: &str) -> ParsedFen {
+    try_parse_fen(fen).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// like parse_fen, but reports malformed input and illegal positions instead of panicking
+pub fn try_parse_fen(fen: &str) -> Result<ParsedFen, String> {
+    let parsed = try_parse_fen_unchecked(fen)?;
+    if let Err(errors) = Board::from(parsed.clone()).validate() {
+        let reasons: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(format!("Illegal position: {}", reasons.join(", ")));
+    }
+    Ok(parsed)
+}
+
+// reads the fields without checking that the position could occur in a game
+pub fn try_parse_fen_unchecked(fen: &str) -> Result<ParsedFen, String> {
     let parts: Vec<&str> = fen.split_whitespace().collect();
     if parts.len() != 6 {
-        panic!("Invalid FEN string");
+        return Err("Invalid FEN string".to_string());
     }
 
-    let piece_boards = fen_to_bb(parts[0].to_string());
+    let piece_boards = fen_to_bb(parts[0].to_string())?;
 
     let side_to_move = match parts[1] {
         "w" => Color::White,
         "b" => Color::Black,
-        _ => panic!("Invalid side to move. Use FEN ('w'/'b')"),
+        _ => return Err("Invalid side to move. Use FEN ('w'/'b')".to_string()),
     };
 
     let mut castling = 0u8;
@@ -34,7 +54,7 @@ pub fn parse_fen(fen: &str) -> ParsedFen {
             'k' => castling |= Castling::BLACK_00,
             'q' => castling |= Castling::BLACK_000,
             '-' => break,
-            _ => panic!("Invalid castling rights. Use FEN (KQkq)"),
+            _ => return Err("Invalid castling rights. Use FEN (KQkq)".to_string()),
         }
     }
 
@@ -42,80 +62,91 @@ pub fn parse_fen(fen: &str) -> ParsedFen {
 
     let en_passant = match parts[3] {
         "-" => None,
-        square => Some(parse_square(square)),
+        square => Some(square.parse::<Square>().map_err(|e| format!("Invalid en passant square: {}", e))?),
     };
 
     let half_move = parts[4].parse::<u8>()
-        .expect("Invalid halfmove clock");
+        .map_err(|_| "Invalid halfmove clock")?;
 
     let full_move = parts[5].parse::<u16>()
-        .expect("Invalid fullmove number");
+        .map_err(|_| "Invalid fullmove number")?;
 
-    ParsedFen {
+    Ok(ParsedFen {
         piece_boards,
         side_to_move,
         castling_rights,
         en_passant,
         half_move,
         full_move,
-    }
+    })
 }
 
-fn fen_to_bb(position: String) -> [BitBoard; 12] {
-    let mut piece_bitboards = [BitBoard(0); 12];
-
-    let mut rank = 7;
-    let mut file = 0;
-
-    for c in position.chars() {
-        match c {
-            '/' => {
-                rank -= 1;
-                file = 0;
+impl Board {
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.pieces[rank * 8 + file] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece.to_char());
+                    },
+                    None => empty += 1,
+                }
             }
-            '1'..'8' => {
-                file += c.to_digit(10).unwrap() as usize;
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
             }
-            piece => {
-                if let Some(piece_index) = char_to_piece(piece) {
-                    let square = rank * 8 + file;
-                    piece_bitboards[piece_index].0 |= 1u64 << square;
-                }
-                file += 1;
+            if rank > 0 {
+                placement.push('/');
             }
         }
-    }
-    piece_bitboards
-}
 
-fn parse_square(s: &str) -> Square {
-    let chars: Vec<char> = s.chars().collect();
-    if chars.len() != 2 {
-        panic!("Invalid square notation");
-    }
+        let side = match self.side_to_move {
+            Color::White => 'w',
+            Color::Black => 'b',
+        };
 
-    let file = (chars[0] as u8 - b'a') as usize;
-    let rank = (chars[1] as u8 - b'1') as usize;
+        let en_passant = self.en_passant.map_or("-".to_string(), |sq| sq.to_string());
 
-    Square(rank * 8 + file)
+        format!("{} {} {} {} {} {}", placement, side, self.castling_rights, en_passant, self.half_move, self.full_move)
+    }
 }
 
-fn char_to_piece(c: char) -> Option<usize> {
-    match c {
-        'P' => Some(0),  // White Pawn
-        'N' => Some(1),  // White Knight
-        'B' => Some(2),  // White Bishop
-        'R' => Some(3),  // White Rook
-        'Q' => Some(4),  // White Queen
-        'K' => Some(5),  // White King
-        'p' => Some(6),  // Black Pawn
-        'n' => Some(7),  // Black Knight
-        'b' => Some(8),  // Black Bishop
-        'r' => Some(9),  // Black Rook
-        'q' => Some(10), // Black Queen
-        'k' => Some(11), // Black King
-        _ => None
+fn fen_to_bb(position: String) -> Result<[BitBoard; 12], String> {
+    let mut piece_bitboards = [BitBoard::EMPTY; 12];
+
+    let ranks: Vec<&str> = position.split('/').collect();
+    if ranks.len() != 8 {
+        return Err("Invalid FEN: expected 8 ranks".to_string());
+    }
+
+    for (row, text) in ranks.iter().enumerate() {
+        let rank = 7 - row;
+        let mut file = 0;
+        for c in text.chars() {
+            match c {
+                '1'..='8' => file += c.to_digit(10).unwrap() as usize,
+                piece => {
+                    let piece = Piece::from_char(piece)
+                        .ok_or_else(|| format!("Invalid FEN: unknown piece '{}'", piece))?;
+                    if file >= 8 {
+                        return Err(format!("Invalid FEN: rank {} has more than 8 squares", rank + 1));
+                    }
+                    piece_bitboards[piece.to_index()].set_bit(rank * 8 + file);
+                    file += 1;
+                }
+            }
+        }
+        if file != 8 {
+            return Err(format!("Invalid FEN: rank {} does not have 8 squares", rank + 1));
+        }
     }
+    Ok(piece_bitboards)
 }
 
 #[cfg(test)]
@@ -124,8 +155,7 @@ mod tests {
 
     #[test]
     fn debug_fen_position_state() {
-        let start_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let parsed = parse_fen(start_fen);
+        let parsed = parse_fen(START_FEN);
 
         assert_eq!(parsed.piece_boards[0].pop_count(), 8); // 8 white pawns
 
@@ -136,4 +166,32 @@ mod tests {
         assert_eq!(parsed.half_move, 0);
         assert_eq!(parsed.full_move, 1);
     } // SUCCESS
+
+    #[test]
+    fn test_invalid_fen() {
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0").is_err());
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnrr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR1 w KQkq - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1").is_err());
+        assert!(try_parse_fen("8/8/8/8/8/8/8/K7 w - - 0 1").is_err());
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").is_ok());
+        // well formed, but no pawn just crossed e3
+        assert!(try_parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1").is_err());
+    }
+
+    #[test]
+    fn test_to_fen_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 3",
+            "8/8/8/8/8/8/8/K1k5 b - - 42 97",
+        ] {
+            let board: Board = parse_fen(fen).into();
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
 }
\ No newline at end of file
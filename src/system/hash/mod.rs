@@ -2,4 +2,4 @@ pub mod prng;
 pub mod zobrist;
 
 pub use prng::LcgRng;
-pub use zobrist::Zobrist;
\ No newline at end of file
+pub use zobrist::{ Zobrist, KEYS };
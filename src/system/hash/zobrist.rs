@@ -3,14 +3,20 @@
 //! Generates unique position keys for board states
 //! Used for position repetition detection and transposition
 use super::LcgRng;
-use super::super::{ Board, Color };
+use super::super::{ Board, Color, Piece, Square };
 
 
 const NUM_PIECES: usize = 12;
 const NUM_SQUARES: usize = 64;
-const NUM_CASTLING_RIGHTS: usize = 4;
+// one key per combination of the four castling bits
+const NUM_CASTLING_RIGHTS: usize = 16;
 const NUM_EN_PASSANT_FILES: usize = 8;
 
+pub const DEFAULT_SEED: u64 = 0x5275_7368_4b65_7973;
+
+// shared keys used by Board for incremental hashing
+pub static KEYS: Zobrist = Zobrist::new(DEFAULT_SEED);
+
 pub struct Zobrist {
     piece_square: [[u64; NUM_SQUARES]; NUM_PIECES],
     castling_rights: [u64; NUM_CASTLING_RIGHTS],
@@ -19,24 +25,34 @@ pub struct Zobrist {
 }
 
 impl Zobrist {
-    pub fn new(seed: u64) -> Self {
+    pub const fn new(seed: u64) -> Self {
         let mut rng = LcgRng::new(seed);
         let mut piece_square = [[0u64; NUM_SQUARES]; NUM_PIECES];
         let mut castling_rights = [0u64; NUM_CASTLING_RIGHTS];
         let mut en_passant_file = [0u64; NUM_EN_PASSANT_FILES];
 
-        for piece in 0..NUM_PIECES {
-            for square in 0..NUM_SQUARES {
+        // while loops so the tables can be built at compile time
+        let mut piece = 0;
+        while piece < NUM_PIECES {
+            let mut square = 0;
+            while square < NUM_SQUARES {
                 piece_square[piece][square] = rng.next_u64();
+                square += 1;
             }
+            piece += 1;
         }
 
-        for i in 0..NUM_CASTLING_RIGHTS {
+        // no castling rights hashes to zero
+        let mut i = 1;
+        while i < NUM_CASTLING_RIGHTS {
             castling_rights[i] = rng.next_u64();
+            i += 1;
         }
 
-        for i in 0..NUM_EN_PASSANT_FILES {
+        let mut i = 0;
+        while i < NUM_EN_PASSANT_FILES {
             en_passant_file[i] = rng.next_u64();
+            i += 1;
         }
 
         let side_to_move = rng.next_u64();
@@ -54,15 +70,14 @@ impl Zobrist {
 
         for (square, piece) in board.pieces.iter().enumerate() {
             if let Some(piece) = piece {
-                let piece_index = piece.to_index();
-                hash ^= self.piece_square[piece_index][square];
+                hash ^= self.piece(*piece, Square(square as u8));
             }
         }
 
-        hash ^= self.castling_rights[board.castling_rights.0 as usize];
+        hash ^= self.castling(board.castling_rights.0);
 
         if let Some(square) = board.en_passant {
-            hash ^= self.en_passant_file[(square.0 & 7) as usize];
+            hash ^= self.en_passant(square);
         }
 
         if board.side_to_move == Color::Black {
@@ -71,4 +86,24 @@ impl Zobrist {
 
         hash
     }
-}
\ No newline at end of file
+
+    #[inline]
+    pub fn piece(&self, piece: Piece, square: Square) -> u64 {
+        self.piece_square[piece.to_index()][square.index()]
+    }
+
+    #[inline]
+    pub fn castling(&self, rights: u8) -> u64 {
+        self.castling_rights[rights as usize & (NUM_CASTLING_RIGHTS - 1)]
+    }
+
+    #[inline]
+    pub fn en_passant(&self, square: Square) -> u64 {
+        self.en_passant_file[square.file().index()]
+    }
+
+    #[inline]
+    pub fn side(&self) -> u64 {
+        self.side_to_move
+    }
+}
@@ -1,11 +1,23 @@
 pub mod board;
+pub mod epd;
+pub mod eval;
 pub mod fen;
 pub mod hash;
 pub mod movegen;
+pub mod outcome;
+pub mod pgn;
+pub mod search;
 pub mod state;
+pub mod tactics;
 
 pub use board::*;
+pub use epd::*;
+pub use eval::*;
 pub use fen::*;
 pub use hash::*;
 pub use movegen::*;
-pub use state::*;
\ No newline at end of file
+pub use outcome::*;
+pub use pgn::*;
+pub use search::*;
+pub use state::*;
+pub use tactics::Motif;
\ No newline at end of file
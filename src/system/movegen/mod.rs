@@ -1,4 +1,6 @@
 use super::*;
+#[allow(clippy::module_inception)]
 pub mod movegen;
+pub mod san;
 
-use movegen::*;
\ No newline at end of file
+pub use movegen::*;
@@ -1,12 +1,17 @@
-use super::{ Board, Square, Piece, BitBoard, Move };
+use std::sync::OnceLock;
+use super::{ Board, Castling, Color, Rank, Square, Piece, PieceType, BitBoard, Move, Side };
+use super::named::{ A1, A8, C1, C8, D1, D8, E1, E8, F1, F8, G1, G8, H1, H8 };
 
 pub struct MoveGenerator {
     // pre-calculated lookup tables
     pawn_attacks: [[BitBoard; 64]; 2], // sizes: BitBoard (8 bytes) * 64 (for each square) * 2 = 1KB total
     knight_moves: [BitBoard; 64], // 8 bytes * 64 = 512 bytes
     king_moves: [BitBoard; 64], // 512 bytes -- should be optimal for leaping pieces?
+    rays: [[BitBoard; 64]; 8], // 8 directions * 512 bytes = 4KB; sliders cut these at the first blocker
 }
 
+static GENERATOR: OnceLock<MoveGenerator> = OnceLock::new();
+
 #[derive(Debug, Clone, Copy)]
 enum Direction {
     // move directions to make life simple later
@@ -20,19 +25,61 @@ enum Direction {
     SouthWest = -9,
 }
 
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Direction::North, Direction::South, Direction::East, Direction::West,
+        Direction::NorthEast, Direction::NorthWest, Direction::SouthEast, Direction::SouthWest,
+    ];
+    const ROOK: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+    const BISHOP: [Direction; 4] = [Direction::NorthEast, Direction::NorthWest, Direction::SouthEast, Direction::SouthWest];
+
+    fn index(self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::South => 1,
+            Direction::East => 2,
+            Direction::West => 3,
+            Direction::NorthEast => 4,
+            Direction::NorthWest => 5,
+            Direction::SouthEast => 6,
+            Direction::SouthWest => 7,
+        }
+    }
+
+    // rays pointing towards higher square indices find their first blocker with the lsb
+    fn is_positive(self) -> bool {
+        (self as i8) > 0
+    }
+}
+
+impl Default for MoveGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveGenerator {
     // note: in binary, board is flipped
-    const NOT_A_FILE: u64 = 0xfefefefefefefefe;  // 11111110
-    const NOT_AB_FILE: u64 = 0xfcfcfcfcfcfcfcfc; // 11111100
-    const NOT_H_FILE: u64 = 0x7f7f7f7f7f7f7f7f;  // 01111111
-    const NOT_GH_FILE: u64 = 0x3f3f3f3f3f3f3f3f; // 00111111
+    const NOT_A_FILE: BitBoard = BitBoard(0xfefefefefefefefe);  // 11111110
+    const NOT_AB_FILE: BitBoard = BitBoard(0xfcfcfcfcfcfcfcfc); // 11111100
+    const NOT_H_FILE: BitBoard = BitBoard(0x7f7f7f7f7f7f7f7f);  // 01111111
+    const NOT_GH_FILE: BitBoard = BitBoard(0x3f3f3f3f3f3f3f3f); // 00111111
     
     pub fn new() -> Self {
-        Self {
-            pawn_attacks: [[BitBoard(0); 64]; 2],
-            knight_moves: [BitBoard(0); 64],
-            king_moves: [BitBoard(0); 64],
-        }
+        let mut mg = Self {
+            pawn_attacks: [[BitBoard::EMPTY; 64]; 2],
+            knight_moves: [BitBoard::EMPTY; 64],
+            king_moves: [BitBoard::EMPTY; 64],
+            rays: [[BitBoard::EMPTY; 64]; 8],
+        };
+        mg.init_leaping_pieces();
+        mg.init_sliding_pieces();
+        mg
+    }
+
+    // shared generator, built on first use
+    pub fn global() -> &'static MoveGenerator {
+        GENERATOR.get_or_init(MoveGenerator::new)
     }
 
     pub fn init_leaping_pieces(&mut self) {
@@ -41,90 +88,294 @@ impl MoveGenerator {
         self.init_king_moves();
     }
 
+    pub fn init_sliding_pieces(&mut self) {
+        for dir in Direction::ALL {
+            for sq in Square::all() {
+                let mut bb = Self::shift(BitBoard::from(sq), dir);
+                let mut ray = BitBoard::EMPTY;
+                while !bb.is_empty() {
+                    ray |= bb;
+                    bb = Self::shift(bb, dir);
+                }
+                self.rays[dir.index()][sq.index()] = ray;
+            }
+        }
+    }
+
     fn shift(bb: BitBoard, dir: Direction) -> BitBoard {
-        let val = bb.0 as u64;
         match dir {
-            Direction::North => BitBoard((val << 8) as u64),
-            Direction::South => BitBoard((val >> 8) as u64),
-            Direction::East => BitBoard(((val << 1) & Self::NOT_A_FILE as u64) as u64),
-            Direction::West => BitBoard(((val >> 1) & Self::NOT_H_FILE as u64) as u64),
-            Direction::NorthEast => BitBoard(((val << 9) & Self::NOT_A_FILE as u64) as u64),
-            Direction::NorthWest => BitBoard(((val << 7) & Self::NOT_H_FILE as u64) as u64),
-            Direction::SouthEast => BitBoard(((val >> 7) & Self::NOT_A_FILE as u64) as u64),
-            Direction::SouthWest => BitBoard(((val >> 9) & Self::NOT_H_FILE as u64) as u64),
+            Direction::North => bb << 8,
+            Direction::South => bb >> 8,
+            Direction::East => (bb << 1) & Self::NOT_A_FILE,
+            Direction::West => (bb >> 1) & Self::NOT_H_FILE,
+            Direction::NorthEast => (bb << 9) & Self::NOT_A_FILE,
+            Direction::NorthWest => (bb << 7) & Self::NOT_H_FILE,
+            Direction::SouthEast => (bb >> 7) & Self::NOT_A_FILE,
+            Direction::SouthWest => (bb >> 9) & Self::NOT_H_FILE,
         }
     }
 
     fn init_pawn_attacks(&mut self) {
-        for sq in 0..64 {
-            let bb = BitBoard(1 << sq);
+        for sq in Square::all() {
+            let bb = BitBoard::from(sq);
 
             // white pawns
-            self.pawn_attacks[0][sq] = BitBoard(
-                Self::shift(bb, Direction::NorthEast).0 |
-                Self::shift(bb, Direction::NorthWest).0
-            );
+            self.pawn_attacks[0][sq.index()] = Self::shift(bb, Direction::NorthEast) | Self::shift(bb, Direction::NorthWest);
 
             // black pawns
-            self.pawn_attacks[1][sq] = BitBoard(
-                Self::shift(bb, Direction::SouthEast).0 |
-                Self::shift(bb, Direction::SouthWest).0
-            );
+            self.pawn_attacks[1][sq.index()] = Self::shift(bb, Direction::SouthEast) | Self::shift(bb, Direction::SouthWest);
         }
     }
 
     fn init_knight_moves(&mut self) {
-        for sq in 0..64 {
-            let bb = BitBoard(1 << sq);
-            let mut moves = BitBoard(0);
+        for sq in Square::all() {
+            let bb = BitBoard::from(sq);
 
             // generate knight moves
-            moves.0 |= ((bb.0 << 17) & Self::NOT_A_FILE) | // checks down2left1 
-                        ((bb.0 << 15) & Self::NOT_H_FILE) |
-                        ((bb.0 >> 17) & Self::NOT_H_FILE) |
-                        ((bb.0 >> 15) & Self::NOT_A_FILE) |
-                        ((bb.0 << 10) & Self::NOT_AB_FILE) |
-                        ((bb.0 << 6) & Self::NOT_GH_FILE) |
-                        ((bb.0 >> 10) & Self::NOT_GH_FILE) |
-                        ((bb.0 >> 6) & Self::NOT_AB_FILE);
-            self.knight_moves[sq] = moves;
+            self.knight_moves[sq.index()] = ((bb << 17) & Self::NOT_A_FILE) | // checks down2left1 
+                        ((bb << 15) & Self::NOT_H_FILE) |
+                        ((bb >> 17) & Self::NOT_H_FILE) |
+                        ((bb >> 15) & Self::NOT_A_FILE) |
+                        ((bb << 10) & Self::NOT_AB_FILE) |
+                        ((bb << 6) & Self::NOT_GH_FILE) |
+                        ((bb >> 10) & Self::NOT_GH_FILE) |
+                        ((bb >> 6) & Self::NOT_AB_FILE);
         }
     }
 
     fn init_king_moves(&mut self) {
-        for sq in 0..64 {
-            let bb = BitBoard(1 << sq);
-            let mut moves = BitBoard(0);
-            self.king_moves[sq] = BitBoard(
-                Self::shift(bb, Direction::NorthEast).0 |
-                Self::shift(bb, Direction::North).0 |
-                Self::shift(bb, Direction::NorthWest).0 |
-                Self::shift(bb, Direction::West).0 |
-                Self::shift(bb, Direction::East).0 |
-                Self::shift(bb, Direction::SouthEast).0 |
-                Self::shift(bb, Direction::South).0 |
-                Self::shift(bb, Direction::SouthWest).0
-            );
+        for sq in Square::all() {
+            let bb = BitBoard::from(sq);
+            self.king_moves[sq.index()] = Direction::ALL.iter().fold(BitBoard::EMPTY, |acc, &dir| acc | Self::shift(bb, dir));
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    pub fn pawn_attacks(&self, side: Color, sq: Square) -> BitBoard {
+        self.pawn_attacks[side as usize][sq.index()]
+    }
+
+    pub fn knight_attacks(&self, sq: Square) -> BitBoard {
+        self.knight_moves[sq.index()]
+    }
+
+    pub fn king_attacks(&self, sq: Square) -> BitBoard {
+        self.king_moves[sq.index()]
+    }
+
+    fn ray_attacks(&self, sq: Square, occupied: BitBoard, dir: Direction) -> BitBoard {
+        let ray = self.rays[dir.index()][sq.index()];
+        let blockers = ray & occupied;
+        if blockers.is_empty() {
+            return ray;
+        }
+        let blocker = if dir.is_positive() { blockers.lsb() } else { blockers.msb() };
+        // keep the blocker itself, drop everything behind it
+        ray ^ self.rays[dir.index()][blocker.index()]
+    }
+
+    pub fn bishop_attacks(&self, sq: Square, occupied: BitBoard) -> BitBoard {
+        Direction::BISHOP.iter().fold(BitBoard::EMPTY, |acc, &dir| acc | self.ray_attacks(sq, occupied, dir))
+    }
+
+    pub fn rook_attacks(&self, sq: Square, occupied: BitBoard) -> BitBoard {
+        Direction::ROOK.iter().fold(BitBoard::EMPTY, |acc, &dir| acc | self.ray_attacks(sq, occupied, dir))
+    }
+
+    pub fn queen_attacks(&self, sq: Square, occupied: BitBoard) -> BitBoard {
+        self.bishop_attacks(sq, occupied) | self.rook_attacks(sq, occupied)
+    }
+
+    // squares attacked by a piece standing on sq, given the occupancy
+    pub fn piece_attacks(&self, piece: Piece, sq: Square, occupied: BitBoard) -> BitBoard {
+        match piece.kind() {
+            PieceType::Pawn => self.pawn_attacks(piece.color(), sq),
+            PieceType::Knight => self.knight_attacks(sq),
+            PieceType::Bishop => self.bishop_attacks(sq, occupied),
+            PieceType::Rook => self.rook_attacks(sq, occupied),
+            PieceType::Queen => self.queen_attacks(sq, occupied),
+            PieceType::King => self.king_attacks(sq),
+        }
+    }
+
+    // every piece of either colour attacking sq, given the occupancy
+    pub fn attackers_to(&self, board: &Board, sq: Square, occupied: BitBoard) -> BitBoard {
+        let queens = board.type_pieces(PieceType::Queen);
+        let diagonal = board.type_pieces(PieceType::Bishop) | queens;
+        let straight = board.type_pieces(PieceType::Rook) | queens;
+
+        let attackers = (self.pawn_attacks(Color::Black, sq) & *board.piece_bb(Side::WHITE, PieceType::Pawn))
+            | (self.pawn_attacks(Color::White, sq) & *board.piece_bb(Side::BLACK, PieceType::Pawn))
+            | (self.knight_attacks(sq) & board.type_pieces(PieceType::Knight))
+            | (self.king_attacks(sq) & board.type_pieces(PieceType::King))
+            | (self.bishop_attacks(sq, occupied) & diagonal)
+            | (self.rook_attacks(sq, occupied) & straight);
+        attackers & occupied
+    }
+
+    pub fn is_square_attacked(&self, board: &Board, sq: Square, by: Color) -> bool {
+        let attackers = self.attackers_to(board, sq, board.occupied());
+        !(attackers & board.side_pieces(Side(by))).is_empty()
+    }
+
+    pub fn in_check(&self, board: &Board, side: Color) -> bool {
+        self.is_square_attacked(board, board.king_square(side), !side)
+    }
+
+    pub fn gives_check(&self, board: &mut Board, mv: Move) -> bool {
+        board.make_move(mv);
+        let check = self.in_check(board, board.side_to_move);
+        board.unmake_move();
+        check
+    }
+
+    // all moves for the side to move, including ones leaving the king in check
+    pub fn pseudo_legal_moves(&self, board: &Board) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        let us = board.side_to_move;
+        let own = board.side_pieces(Side(us));
+        let enemy = board.side_pieces(Side(!us));
+        let occupied = own | enemy;
 
-    fn debug_print(bb: BitBoard) {
-        for rank in (0..8).rev() {
-            for file in 0..8 {
-                let square = rank * 8 + file;
-                let bit = (bb.0 >> square) & 1;
-                print!("{} ", if bit == 1 { "1" } else { "." });
+        for kind in PieceType::ALL {
+            let piece = Piece::new(us, kind);
+            for from in board.piece_boards[piece.to_index()] {
+                if kind == PieceType::Pawn {
+                    self.pawn_moves(board, from, enemy, occupied, &mut moves);
+                    continue;
+                }
+
+                for to in self.piece_attacks(piece, from, occupied) & !own {
+                    moves.push(match board.pieces[to.index()] {
+                        Some(captured) => Move::Capture { from, to, captured },
+                        None => Move::Normal { from, to },
+                    });
+                }
+            }
+        }
+
+        self.castling_moves(board, occupied, &mut moves);
+        moves
+    }
+
+    fn pawn_moves(&self, board: &Board, from: Square, enemy: BitBoard, occupied: BitBoard, moves: &mut Vec<Move>) {
+        let us = board.side_to_move;
+        let (forward, start_rank, promo_rank) = match us {
+            Color::White => (1, Rank::Second, Rank::Eighth),
+            Color::Black => (-1, Rank::Seventh, Rank::First),
+        };
+        let promotions = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight].map(|kind| Piece::new(us, kind));
+
+        let Some(push) = from.offset(0, forward) else { return };
+        if !occupied.contains(push) {
+            if push.rank() == promo_rank {
+                for piece in promotions {
+                    moves.push(Move::Promotion { from, to: push, piece });
+                }
+            } else {
+                moves.push(Move::Normal { from, to: push });
+                let double = push.offset(0, forward).filter(|&sq| from.rank() == start_rank && !occupied.contains(sq));
+                if let Some(double) = double {
+                    moves.push(Move::Normal { from, to: double });
+                }
+            }
+        }
+
+        let attacks = self.pawn_attacks(us, from);
+        for to in attacks & enemy {
+            let Some(captured) = board.pieces[to.index()] else { continue };
+            if to.rank() == promo_rank {
+                for promotion in promotions {
+                    moves.push(Move::CapturePromotion { from, to, captured, promotion });
+                }
+            } else {
+                moves.push(Move::Capture { from, to, captured });
+            }
+        }
+
+        if let Some(ep) = board.en_passant {
+            if attacks.contains(ep) {
+                moves.push(Move::EnPassant { from, to: ep });
             }
-            println!();
         }
-        println!();
     }
 
+    fn castling_moves(&self, board: &Board, occupied: BitBoard, moves: &mut Vec<Move>) {
+        let us = board.side_to_move;
+        let rights = board.castling_rights.0;
+        // (right, king from, king to, rook square, squares that must be empty, squares the king crosses)
+        let options: [(u8, Square, Square, Square, BitBoard, [Square; 2]); 2] = match us {
+            Color::White => [
+                (Castling::WHITE_00, E1, G1, H1, BitBoard(0x60), [F1, G1]),
+                (Castling::WHITE_000, E1, C1, A1, BitBoard(0x0e), [D1, C1]),
+            ],
+            Color::Black => [
+                (Castling::BLACK_00, E8, G8, H8, BitBoard(0x60 << 56), [F8, G8]),
+                (Castling::BLACK_000, E8, C8, A8, BitBoard(0x0e << 56), [D8, C8]),
+            ],
+        };
+        let (king, rook) = match us {
+            Color::White => (Piece::WhiteKing, Piece::WhiteRook),
+            Color::Black => (Piece::BlackKing, Piece::BlackRook),
+        };
+
+        for (right, from, to, rook_sq, between, path) in options {
+            if rights & right == 0
+                || !(occupied & between).is_empty()
+                || board.pieces[from.index()] != Some(king)
+                || board.pieces[rook_sq.index()] != Some(rook)
+            {
+                continue;
+            }
+            if self.is_square_attacked(board, from, !us)
+                || path.iter().any(|&sq| self.is_square_attacked(board, sq, !us))
+            {
+                continue;
+            }
+            moves.push(Move::Castle { from, to });
+        }
+    }
+
+    pub fn legal_moves(&self, board: &mut Board) -> Vec<Move> {
+        let us = board.side_to_move;
+        let mut moves = self.pseudo_legal_moves(board);
+        moves.retain(|&mv| {
+            board.make_move(mv);
+            let legal = !self.in_check(board, us);
+            board.unmake_move();
+            legal
+        });
+        moves
+    }
+
+    // finds the legal move matching long algebraic notation (e2e4, e7e8q)
+    pub fn parse_move(&self, board: &mut Board, text: &str) -> Option<Move> {
+        let text = text.trim().to_ascii_lowercase();
+        self.legal_moves(board).into_iter().find(|mv| mv.to_string() == text)
+    }
+
+    // counts leaf nodes of the legal move tree; used to verify generation
+    pub fn perft(&self, board: &mut Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves(board);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            board.make_move(mv);
+            nodes += self.perft(board, depth - 1);
+            board.unmake_move();
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
     #[test]
     fn test_is_knight_move() {
         let mut mg = MoveGenerator::new();
@@ -138,12 +389,16 @@ mod test {
         assert_eq!(a1_moves.pop_count(), 2);
         assert_ne!(a2_moves.pop_count(), 2);
 
-        println!("Knight on e4 moves:");
-        debug_print(e4_moves);
-        println!("Knight on a1 moves:");
-        debug_print(a1_moves);
-        println!("Knight on a2 moves:");
-        debug_print(a2_moves);
+        assert_eq!(a1_moves.to_string(), "\
+8  . . . . . . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . 1 . . . . . .
+2  . . 1 . . . . .
+1  . . . . . . . .
+   a b c d e f g h");
     }
 
     #[test]
@@ -157,10 +412,65 @@ mod test {
         assert_eq!(e4_moves.pop_count(), 8);
         assert_eq!(a8_moves.pop_count(), 3);
 
-        println!("King on e4 moves:");
-        debug_print(e4_moves);
-        println!("King on a8 moves:");
-        debug_print(a8_moves);
+        assert_eq!(e4_moves.to_string(), "\
+8  . . . . . . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . 1 1 1 . .
+4  . . . 1 . 1 . .
+3  . . . 1 1 1 . .
+2  . . . . . . . .
+1  . . . . . . . .
+   a b c d e f g h");
+    }
+
+    fn perft_fen(fen: &str, depth: u32) -> u64 {
+        let mut board: Board = crate::system::parse_fen(fen).into();
+        MoveGenerator::global().perft(&mut board, depth)
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        let fen = crate::system::START_FEN;
+        assert_eq!(perft_fen(fen, 1), 20);
+        assert_eq!(perft_fen(fen, 2), 400);
+        assert_eq!(perft_fen(fen, 3), 8902);
+        assert_eq!(perft_fen(fen, 4), 197281);
+    }
+
+    #[test]
+    fn test_perft_tricky_positions() {
+        // castling, en passant, promotions and pins
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft_fen(kiwipete, 1), 48);
+        assert_eq!(perft_fen(kiwipete, 2), 2039);
+        assert_eq!(perft_fen(kiwipete, 3), 97862);
+
+        let endgame = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(perft_fen(endgame, 4), 43238);
+
+        let promotions = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft_fen(promotions, 3), 9467);
+
+        let buggy = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+        assert_eq!(perft_fen(buggy, 3), 62379);
+    }
+
+    #[test]
+    fn test_unmake_restores_position() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board: Board = crate::system::parse_fen(fen).into();
+        let mg = MoveGenerator::global();
+        let key = board.zobrist_key;
+        let pieces = board.pieces;
+
+        for mv in mg.legal_moves(&mut board) {
+            board.make_move(mv);
+            assert_eq!(board.zobrist_key, crate::system::KEYS.hash(&board), "incremental key after {}", mv);
+            board.unmake_move();
+            assert_eq!(board.zobrist_key, key);
+            assert_eq!(board.pieces, pieces);
+        }
     }
 
     #[test]
@@ -178,11 +488,25 @@ mod test {
         assert_eq!(a2_white.pop_count(), 1);
         assert_eq!(h2_white.pop_count(), 1);
 
-        println!("White pawn on e2 attacks:");
-        debug_print(e2_white);
-        println!("Black pawn on e7 attacks:");
-        debug_print(e7_black);
-        println!("White pawn on a2 attack:");
-        debug_print(a2_white);
+        assert_eq!(e2_white.to_string(), "\
+8  . . . . . . . .
+7  . . . . . . . .
+6  . . . . . . . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . . . 1 . 1 . .
+2  . . . . . . . .
+1  . . . . . . . .
+   a b c d e f g h");
+        assert_eq!(e7_black.to_string(), "\
+8  . . . . . . . .
+7  . . . . . . . .
+6  . . . 1 . 1 . .
+5  . . . . . . . .
+4  . . . . . . . .
+3  . . . . . . . .
+2  . . . . . . . .
+1  . . . . . . . .
+   a b c d e f g h");
     }
 }
\ No newline at end of file
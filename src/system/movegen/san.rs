@@ -0,0 +1,190 @@
+//! Standard algebraic notation
+//!
+//! Writes and reads moves the way PGN files and humans do (Nf3, exd5, O-O, e8=Q+)
+use super::{ Board, File, Move, MoveGenerator, Piece, PieceType, Rank, Square };
+
+// piece letters are upper case in SAN; lower case ones are files
+fn letter_to_kind(c: char) -> Option<PieceType> {
+    PieceType::from_char(c).filter(|_| c.is_ascii_uppercase())
+}
+
+impl MoveGenerator {
+    /// SAN for a legal move, including the check or mate suffix.
+    pub fn to_san(&self, board: &mut Board, mv: Move) -> String {
+        let mut san = match mv {
+            Move::Castle { from, to } if to.0 > from.0 => "O-O".to_string(),
+            Move::Castle { .. } => "O-O-O".to_string(),
+            _ => self.piece_san(board, mv),
+        };
+
+        board.make_move(mv);
+        if self.in_check(board, board.side_to_move) {
+            san.push(if self.legal_moves(board).is_empty() { '#' } else { '+' });
+        }
+        board.unmake_move();
+        san
+    }
+
+    fn piece_san(&self, board: &mut Board, mv: Move) -> String {
+        let (from, to) = (mv.from(), mv.to());
+        let kind = board.pieces[from.index()].map_or(PieceType::Pawn, Piece::kind);
+        let mut san = String::new();
+
+        if kind == PieceType::Pawn {
+            if mv.is_capture() {
+                san.push(from.file().to_char());
+            }
+        } else {
+            san.push(kind.to_char());
+
+            // other pieces of the same kind that could also go to `to`
+            let rivals: Vec<Square> = self.legal_moves(board).into_iter()
+                .filter(|other| other.to() == to && other.from() != from)
+                .filter(|other| board.pieces[other.from().index()].map(Piece::kind) == Some(kind))
+                .map(|other| other.from())
+                .collect();
+            if !rivals.is_empty() {
+                let same_file = rivals.iter().any(|sq| sq.file() == from.file());
+                let same_rank = rivals.iter().any(|sq| sq.rank() == from.rank());
+                if !same_file {
+                    san.push(from.file().to_char());
+                } else if !same_rank {
+                    san.push(from.rank().to_char());
+                } else {
+                    san.push_str(&from.to_string());
+                }
+            }
+        }
+
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&to.to_string());
+        if let Some(piece) = mv.promotion() {
+            san.push('=');
+            san.push(piece.kind().to_char());
+        }
+        san
+    }
+
+    /// Finds the legal move written in SAN. Check marks and annotations are
+    /// ignored, and over-specified moves (Ngf3, Qd1e2) are accepted.
+    pub fn parse_san(&self, board: &mut Board, text: &str) -> Option<Move> {
+        let text = text.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal = self.legal_moves(board);
+
+        let castle = match text {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(king_side) = castle {
+            return legal.into_iter().find(|mv| matches!(*mv, Move::Castle { from, to } if (to.0 > from.0) == king_side));
+        }
+
+        let mut bytes = text.as_bytes();
+        let kind = match bytes.first().and_then(|&c| letter_to_kind(c as char)) {
+            Some(kind) => {
+                bytes = &bytes[1..];
+                kind
+            },
+            None => PieceType::Pawn,
+        };
+
+        // promotion suffix, with or without '='
+        let mut promotion = None;
+        if let Some(&last) = bytes.last() {
+            if let Some(piece) = letter_to_kind(last.to_ascii_uppercase() as char).filter(|_| kind == PieceType::Pawn && !last.is_ascii_digit()) {
+                promotion = Some(piece);
+                bytes = &bytes[..bytes.len() - 1];
+                bytes = bytes.strip_suffix(b"=").unwrap_or(bytes);
+            }
+        }
+
+        if bytes.len() < 2 {
+            return None;
+        }
+        let to: Square = std::str::from_utf8(&bytes[bytes.len() - 2..]).ok()?.parse().ok()?;
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in bytes[..bytes.len() - 2].iter() {
+            match c {
+                b'a'..=b'h' => from_file = File::from_char(c as char),
+                b'1'..=b'8' => from_rank = Rank::from_char(c as char),
+                b'x' | b':' | b'-' => {},
+                _ => return None,
+            }
+        }
+
+        let mut candidates = legal.into_iter().filter(|mv| {
+            mv.to() == to
+                && !matches!(mv, Move::Castle { .. })
+                && board.pieces[mv.from().index()].map(Piece::kind) == Some(kind)
+                && from_file.is_none_or(|f| mv.from().file() == f)
+                && from_rank.is_none_or(|r| mv.from().rank() == r)
+                && mv.promotion().map(Piece::kind) == promotion
+        });
+        let mv = candidates.next()?;
+        // ambiguous input matches nothing
+        candidates.next().is_none().then_some(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    fn board(fen: &str) -> Board {
+        parse_fen(fen).into()
+    }
+
+    #[test]
+    fn test_san_round_trip() {
+        let mg = MoveGenerator::global();
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let mut board = board(fen);
+            for mv in mg.legal_moves(&mut board) {
+                let san = mg.to_san(&mut board, mv);
+                assert_eq!(mg.parse_san(&mut board, &san), Some(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_san_notation() {
+        let mg = MoveGenerator::global();
+        let mut kiwipete = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let san = |board: &mut Board, text: &str| {
+            let mv = mg.parse_move(board, text).unwrap();
+            mg.to_san(board, mv)
+        };
+        assert_eq!(san(&mut kiwipete, "e1g1"), "O-O");
+        assert_eq!(san(&mut kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san(&mut kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san(&mut kiwipete, "e5f7"), "Nxf7");
+        assert_eq!(san(&mut kiwipete, "e5d3"), "Nd3");
+
+        let mut rooks = board("1k6/8/8/R7/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(san(&mut rooks, "a1d1"), "Rad1");
+        assert_eq!(san(&mut rooks, "h1d1"), "Rhd1");
+        assert_eq!(san(&mut rooks, "a1a3"), "R1a3");
+        assert_eq!(san(&mut rooks, "a5a3"), "R5a3");
+        assert_eq!(mg.parse_san(&mut rooks, "Rd1"), None);
+
+        let mut promotion = board("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1");
+        assert_eq!(san(&mut promotion, "g2h1q"), "gxh1=Q");
+        assert_eq!(san(&mut promotion, "g2g1r"), "g1=R");
+        assert_eq!(mg.parse_san(&mut promotion, "g1N"), mg.parse_move(&mut promotion, "g2g1n"));
+
+        let mut mate = board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(san(&mut mate, "a1a8"), "Ra8#");
+        assert_eq!(mg.parse_san(&mut mate, "Ra8"), mg.parse_move(&mut mate, "a1a8"));
+        assert_eq!(mg.parse_san(&mut mate, "Ra1a8#"), mg.parse_move(&mut mate, "a1a8"));
+        assert_eq!(mg.parse_san(&mut mate, "Rb9"), None);
+    }
+}
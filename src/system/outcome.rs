@@ -0,0 +1,100 @@
+//! Game end detection
+//!
+//! Decides when the rules end a game and records why, for the match
+//! runner, self-play and the protocol front ends
+use super::{ insufficient_material, Board, Color, MoveGenerator };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    TimeForfeit,
+    IllegalMove,
+    Disconnected,
+    ResignAdjudication,
+    DrawAdjudication,
+    MoveLimit,
+}
+
+impl Termination {
+    // the PGN Termination tag value
+    pub fn tag(self) -> &'static str {
+        match self {
+            Termination::TimeForfeit => "time forfeit",
+            Termination::IllegalMove | Termination::Disconnected => "rules infraction",
+            Termination::ResignAdjudication | Termination::DrawAdjudication | Termination::MoveLimit => "adjudication",
+            _ => "normal",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "threefold repetition",
+            Termination::FiftyMoves => "fifty-move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::TimeForfeit => "loss on time",
+            Termination::IllegalMove => "illegal move",
+            Termination::Disconnected => "engine disconnected",
+            Termination::ResignAdjudication => "resign adjudication",
+            Termination::DrawAdjudication => "draw adjudication",
+            Termination::MoveLimit => "move limit",
+        }
+    }
+}
+
+// result string for a win by `winner`
+pub fn win_for(winner: Color) -> &'static str {
+    match winner {
+        Color::White => "1-0",
+        Color::Black => "0-1",
+    }
+}
+
+/// Ends the game if the rules say it is over.
+pub fn game_over(board: &mut Board) -> Option<(&'static str, Termination)> {
+    let mg = MoveGenerator::global();
+    if mg.legal_moves(board).is_empty() {
+        return Some(if mg.in_check(board, board.side_to_move) {
+            (win_for(!board.side_to_move), Termination::Checkmate)
+        } else {
+            ("1/2-1/2", Termination::Stalemate)
+        });
+    }
+    if board.repetition_count() >= 2 {
+        Some(("1/2-1/2", Termination::Repetition))
+    } else if board.half_move >= 100 {
+        Some(("1/2-1/2", Termination::FiftyMoves))
+    } else if insufficient_material(board) {
+        Some(("1/2-1/2", Termination::InsufficientMaterial))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    #[test]
+    fn test_game_over() {
+        let mut mate: Board = parse_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").into();
+        assert_eq!(game_over(&mut mate), Some(("1-0", Termination::Checkmate)));
+        let mut bare: Board = parse_fen("8/8/4k3/8/8/3NK3/8/8 w - - 0 1").into();
+        assert_eq!(game_over(&mut bare), Some(("1/2-1/2", Termination::InsufficientMaterial)));
+
+        let mut board: Board = parse_fen(START_FEN).into();
+        let mg = MoveGenerator::global();
+        for (i, mv) in ["g1f3", "g8f6", "f3g1", "f6g8"].iter().cycle().take(8).enumerate() {
+            assert_eq!(game_over(&mut board), None, "ply {}", i);
+            let mv = mg.parse_move(&mut board, mv).unwrap();
+            board.make_move(mv);
+        }
+        assert_eq!(game_over(&mut board), Some(("1/2-1/2", Termination::Repetition)));
+    }
+}
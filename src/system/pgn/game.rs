@@ -0,0 +1,119 @@
+//! PGN game tree
+//!
+//! A game is an arena of nodes: the root is the starting position and every
+//! other node is one move with the annotations that followed it. The first
+//! child of a node continues the main line, the rest are variations.
+use std::time::Duration;
+use super::{ Board, Move };
+
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evaluation {
+    // from white's point of view
+    Centipawns(i32),
+    // moves until mate, negative when black mates
+    Mate(i32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    // None only for the root
+    pub mv: Option<Move>,
+    // Zobrist key of the position after the move
+    pub key: u64,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    // comment written before the move, e.g. at the start of a variation
+    pub starting_comment: Option<String>,
+    pub comment: Option<String>,
+    pub nags: Vec<u8>,
+    pub clock: Option<Duration>,
+    pub eval: Option<Evaluation>,
+    // search depth behind `eval`, when the annotator gave one
+    pub eval_depth: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    // tag pairs in the order they were read
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub nodes: Vec<Node>,
+    // termination marker from the movetext (1-0, 0-1, 1/2-1/2 or *)
+    pub result: String,
+}
+
+impl Game {
+    pub const ROOT: NodeId = 0;
+
+    pub fn new(start: Board) -> Self {
+        let root = Node { key: start.zobrist_key, ..Node::default() };
+        Self { tags: Vec::new(), start, nodes: vec![root], result: "*".to_string() }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(key, _)| key == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    /// Adds `mv` after `parent` as a new main line or variation. Playing a
+    /// move that already follows `parent` returns the existing node.
+    pub fn add_move(&mut self, parent: NodeId, mv: Move, key: u64) -> NodeId {
+        if let Some(&existing) = self.nodes[parent].children.iter().find(|&&c| self.nodes[c].mv == Some(mv)) {
+            return existing;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node { mv: Some(mv), key, parent: Some(parent), ..Node::default() });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// Node ids along the main line, not including the root.
+    pub fn mainline(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut current = Self::ROOT;
+        std::iter::from_fn(move || {
+            current = *self.nodes[current].children.first()?;
+            Some(current)
+        })
+    }
+
+    pub fn mainline_moves(&self) -> Vec<Move> {
+        self.mainline().filter_map(|id| self.nodes[id].mv).collect()
+    }
+
+    /// Moves from the root to `id`.
+    pub fn moves_to(&self, id: NodeId) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            moves.extend(self.nodes[current].mv);
+            current = parent;
+        }
+        moves.reverse();
+        moves
+    }
+
+    /// Position after the move at `id`, replayed from the start.
+    pub fn board_at(&self, id: NodeId) -> Board {
+        let mut board = self.start.clone();
+        for mv in self.moves_to(id) {
+            board.make_move(mv);
+        }
+        board
+    }
+}
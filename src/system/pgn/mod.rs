@@ -0,0 +1,8 @@
+use super::*;
+
+pub mod game;
+pub mod reader;
+pub mod writer;
+
+pub use game::*;
+pub use reader::*;
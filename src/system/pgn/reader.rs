@@ -0,0 +1,535 @@
+//! Streaming PGN reader
+//!
+//! Splits a PGN stream into games and parses each one into a `Game` tree.
+//! A malformed game yields an error carrying its line number, and reading
+//! carries on with the next game.
+use std::fmt;
+use std::io::{ BufRead, Lines };
+use std::time::Duration;
+use super::{ try_parse_fen, Board, Evaluation, Game, MoveGenerator, NodeId, START_FEN };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl PgnError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self { line, message: message.into() }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+pub struct PgnReader<R> {
+    lines: Lines<R>,
+    line_number: usize,
+    // first line of the next game, read while looking for the end of this one
+    lookahead: Option<(usize, String)>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: reader.lines(), line_number: 0, lookahead: None }
+    }
+
+    fn next_line(&mut self) -> Option<Result<(usize, String), PgnError>> {
+        if let Some(line) = self.lookahead.take() {
+            return Some(Ok(line));
+        }
+        let line = self.lines.next()?;
+        self.line_number += 1;
+        Some(line.map(|l| (self.line_number, l)).map_err(|e| PgnError::new(self.line_number, e.to_string())))
+    }
+
+    // raw text of the next game and the line it starts on
+    fn next_chunk(&mut self) -> Option<Result<(usize, String), PgnError>> {
+        let mut text = String::new();
+        let mut first_line = 0;
+        let mut in_movetext = false;
+        let mut in_comment = false;
+
+        while let Some(line) = self.next_line() {
+            let (number, line) = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let trimmed = line.trim_start();
+
+            // a tag after the movetext starts the next game
+            if in_movetext && !in_comment && trimmed.starts_with('[') {
+                self.lookahead = Some((number, line));
+                break;
+            }
+            if text.is_empty() && trimmed.is_empty() {
+                continue;
+            }
+            if text.is_empty() {
+                first_line = number;
+            }
+            if !in_comment && !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+                in_movetext = true;
+            }
+
+            for c in line.chars() {
+                match (in_comment, c) {
+                    (false, '{') => in_comment = true,
+                    (false, ';') => break,
+                    (true, '}') => in_comment = false,
+                    _ => {},
+                }
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        (!text.is_empty()).then_some(Ok((first_line, text)))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_chunk()?.and_then(|(line, text)| parse_game(&text, line)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    San(String),
+    Nag(u8),
+    Comment(String),
+    Open,
+    Close,
+    Result(String),
+}
+
+fn suffix_nag(text: &str) -> Option<u8> {
+    match text {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn tokenize(text: &str, first_line: usize) -> Result<Vec<(usize, Token)>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut line = first_line;
+    let mut line_start = true;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let at = line;
+        match c {
+            '\n' => {
+                line += 1;
+                line_start = true;
+                continue;
+            },
+            c if c.is_whitespace() => continue,
+            // escape lines are for other programs
+            '%' if line_start => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+                continue;
+            },
+            '[' => {
+                let mut body = String::new();
+                let mut in_string = false;
+                loop {
+                    match chars.next() {
+                        None | Some('\n') => return Err(PgnError::new(at, "unterminated tag pair")),
+                        Some('\\') if in_string => body.extend(chars.next()),
+                        Some('"') => {
+                            in_string = !in_string;
+                            body.push('\u{0}');
+                        },
+                        Some(']') if !in_string => break,
+                        Some(c) => body.push(c),
+                    }
+                }
+                // the quote markers split the body into name, value and the rest
+                let mut parts = body.split('\u{0}');
+                let name = parts.next().unwrap_or("").trim().to_string();
+                let value = parts.next().ok_or_else(|| PgnError::new(at, format!("tag '{}' has no quoted value", name)))?;
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(PgnError::new(at, "malformed tag name"));
+                }
+                tokens.push((at, Token::Tag(name, value.to_string())));
+            },
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(PgnError::new(at, "unterminated comment")),
+                        Some('}') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            comment.push(c);
+                        },
+                    }
+                }
+                tokens.push((at, Token::Comment(comment)));
+            },
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                line += 1;
+                line_start = true;
+                tokens.push((at, Token::Comment(comment)));
+                continue;
+            },
+            '(' => tokens.push((at, Token::Open)),
+            ')' => tokens.push((at, Token::Close)),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                let nag = digits.parse().map_err(|_| PgnError::new(at, format!("bad NAG '${}'", digits)))?;
+                tokens.push((at, Token::Nag(nag)));
+            },
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "{}()[];$".contains(next) {
+                        break;
+                    }
+                    symbol.push(next);
+                    chars.next();
+                }
+
+                match symbol.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => tokens.push((at, Token::Result(symbol))),
+                    _ => {
+                        // "12." and "12..." are move numbers, "12.e4" carries its move;
+                        // digits without a dot are not, as in "0-0" castling
+                        let after_digits = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+                        let rest = match after_digits.strip_prefix('.') {
+                            Some(after) => after.trim_start_matches('.'),
+                            None => symbol.as_str(),
+                        };
+                        let san = rest.trim_end_matches(['!', '?']);
+                        if !san.is_empty() {
+                            tokens.push((at, Token::San(san.to_string())));
+                        }
+                        let annotation = &rest[san.len()..];
+                        if !annotation.is_empty() {
+                            let nag = suffix_nag(annotation)
+                                .ok_or_else(|| PgnError::new(at, format!("unknown annotation '{}'", annotation)))?;
+                            tokens.push((at, Token::Nag(nag)));
+                        }
+                    },
+                }
+            },
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+// "1:02:03" or "0:00:09.5"
+fn parse_clock(text: &str) -> Option<Duration> {
+    let mut seconds = 0.0;
+    for part in text.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+// an evaluation and the search depth behind it
+type DepthEval = (Evaluation, Option<u32>);
+
+// "0.35", "-1.2" or "#-3", with an optional ",depth" suffix
+fn parse_eval(text: &str) -> Option<DepthEval> {
+    let (value, depth) = match text.split_once(',') {
+        Some((value, depth)) => (value.trim(), Some(depth.trim().parse().ok()?)),
+        None => (text.trim(), None),
+    };
+    let eval = match value.strip_prefix('#') {
+        Some(mate) => Evaluation::Mate(mate.parse().ok()?),
+        None => Evaluation::Centipawns((value.parse::<f64>().ok()? * 100.0).round() as i32),
+    };
+    Some((eval, depth))
+}
+
+// pulls [%clk] and [%eval] out of a comment, leaving any other text
+fn extract_commands(comment: &str) -> (String, Option<Duration>, Option<DepthEval>) {
+    let mut text = String::new();
+    let (mut clock, mut eval) = (None, None);
+    let mut rest = comment;
+
+    while let Some(start) = rest.find("[%") {
+        let Some(end) = rest[start..].find(']') else { break };
+        let command = &rest[start + 2..start + end];
+        let (name, args) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let parsed = match name {
+            "clk" => parse_clock(args.trim()).map(|c| clock = Some(c)),
+            "eval" => parse_eval(args).map(|e| eval = Some(e)),
+            _ => None,
+        };
+        text.push_str(&rest[..start]);
+        if parsed.is_none() {
+            text.push_str(&rest[start..start + end + 1]);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    (text.split_whitespace().collect::<Vec<_>>().join(" "), clock, eval)
+}
+
+fn append(target: &mut Option<String>, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    match target {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(&text);
+        },
+        None => *target = Some(text),
+    }
+}
+
+/// Parses the text of a single game; `first_line` is used for error positions.
+pub fn parse_game(text: &str, first_line: usize) -> Result<Game, PgnError> {
+    let mg = MoveGenerator::global();
+    let tokens = tokenize(text, first_line)?;
+
+    let mut tags = Vec::new();
+    let mut fen = None;
+    let mut body = tokens.len();
+    for (i, (line, token)) in tokens.iter().enumerate() {
+        match token {
+            Token::Tag(name, value) => {
+                if name == "FEN" {
+                    fen = Some((*line, value.clone()));
+                }
+                tags.push((name.clone(), value.clone()));
+            },
+            _ => {
+                body = i;
+                break;
+            },
+        }
+    }
+
+    let start: Board = match fen {
+        Some((line, fen)) => try_parse_fen(&fen).map_err(|e| PgnError::new(line, e))?.into(),
+        None => try_parse_fen(START_FEN).map_err(|e| PgnError::new(first_line, e))?.into(),
+    };
+    let mut game = Game::new(start.clone());
+    game.tags = tags;
+
+    let mut board = start;
+    let mut current: NodeId = Game::ROOT;
+    let mut stack: Vec<(NodeId, Board)> = Vec::new();
+    // a comment right after '(' belongs in front of the variation's first move
+    let mut starting_comment: Option<String> = None;
+    let mut variation_start = false;
+
+    for (line, token) in tokens.into_iter().skip(body) {
+        match token {
+            Token::Tag(..) => return Err(PgnError::new(line, "tag pair inside movetext")),
+            Token::San(san) => {
+                let mv = mg.parse_san(&mut board, &san)
+                    .ok_or_else(|| PgnError::new(line, format!("illegal or ambiguous move '{}'", san)))?;
+                board.make_move(mv);
+                current = game.add_move(current, mv, board.zobrist_key);
+                if let Some(comment) = starting_comment.take() {
+                    append(&mut game.node_mut(current).starting_comment, comment);
+                }
+                variation_start = false;
+            },
+            Token::Nag(nag) => {
+                if current == Game::ROOT || variation_start {
+                    return Err(PgnError::new(line, "annotation before any move"));
+                }
+                game.node_mut(current).nags.push(nag);
+            },
+            Token::Comment(comment) => {
+                let (text, clock, eval) = extract_commands(&comment);
+                if variation_start {
+                    append(&mut starting_comment, text);
+                    continue;
+                }
+                let node = game.node_mut(current);
+                append(&mut node.comment, text);
+                node.clock = clock.or(node.clock);
+                if let Some((eval, depth)) = eval {
+                    node.eval = Some(eval);
+                    node.eval_depth = depth;
+                }
+            },
+            Token::Open => {
+                let parent = game.node(current).parent
+                    .ok_or_else(|| PgnError::new(line, "variation before any move"))?;
+                stack.push((current, board.clone()));
+                board.unmake_move();
+                current = parent;
+                variation_start = true;
+            },
+            Token::Close => {
+                let (node, saved) = stack.pop().ok_or_else(|| PgnError::new(line, "unmatched ')'"))?;
+                if variation_start {
+                    return Err(PgnError::new(line, "empty variation"));
+                }
+                current = node;
+                board = saved;
+            },
+            Token::Result(result) => {
+                if !stack.is_empty() {
+                    return Err(PgnError::new(line, "result inside a variation"));
+                }
+                game.result = result;
+                return Ok(game);
+            },
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(PgnError::new(first_line, "unterminated variation"));
+    }
+    if let Some(result) = game.tag("Result") {
+        game.result = result.to_string();
+    }
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Casual Game"]
+[Site "https://lichess.org/abcdefgh"]
+[Date "2024.01.01"]
+[Round "-"]
+[White "Alice \"A\""]
+[Black "Bob"]
+[Result "1-0"]
+[TimeControl "180+2"]
+
+{ A short game. } 1. e4 { [%clk 0:03:00] [%eval 0.3] } 1... e5 { [%clk 0:02:58.5] }
+2. Bc4 Nc6 3. Qh5 (3. Nf3 { developing } 3... Nf6 (3... d6 $6) 4. Ng5) 3... Nf6?? $4
+; the only move is mate
+4. Qxf7# { [%eval #0] } 1-0
+
+[Event "From a position"]
+[SetUp "1"]
+[FEN "6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1"]
+[Result "*"]
+
+1... Kf8 2. Rd8# *
+"#;
+
+    fn games(text: &str) -> Vec<Result<Game, PgnError>> {
+        PgnReader::new(text.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_reads_tags_and_mainline() {
+        let games = games(PGN);
+        assert_eq!(games.len(), 2);
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("White"), Some("Alice \"A\""));
+        assert_eq!(game.tag("TimeControl"), Some("180+2"));
+        assert_eq!(game.tags.len(), 8);
+        assert_eq!(game.result, "1-0");
+
+        let moves: Vec<String> = game.mainline_moves().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(moves, vec!["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"]);
+        assert_eq!(game.node(Game::ROOT).comment.as_deref(), Some("A short game."));
+
+        // every node's key matches its replayed position
+        for id in 1..game.nodes.len() {
+            assert_eq!(game.board_at(id).zobrist_key, game.node(id).key);
+        }
+    }
+
+    #[test]
+    fn test_annotations_and_variations() {
+        let game = games(PGN).remove(0).unwrap();
+        let mainline: Vec<NodeId> = game.mainline().collect();
+
+        let e4 = game.node(mainline[0]);
+        assert_eq!(e4.clock, Some(Duration::from_secs(180)));
+        assert_eq!(e4.eval, Some(Evaluation::Centipawns(30)));
+        assert_eq!(e4.comment, None);
+        assert_eq!(game.node(mainline[1]).clock, Some(Duration::from_secs_f64(178.5)));
+
+        let nf6 = game.node(mainline[5]);
+        assert_eq!(nf6.nags, vec![4, 4]);
+        assert_eq!(nf6.comment.as_deref(), Some("the only move is mate"));
+        assert_eq!(game.node(mainline[6]).eval, Some(Evaluation::Mate(0)));
+
+        // 3. Nf3 is a variation of 3. Qh5, with 3... d6 nested inside it
+        let bc6 = game.node(mainline[3]);
+        assert_eq!(bc6.children.len(), 2);
+        let nf3 = game.node(bc6.children[1]);
+        assert_eq!(nf3.mv.unwrap().to_string(), "g1f3");
+        assert_eq!(nf3.comment.as_deref(), Some("developing"));
+        let nf6_alt = game.node(nf3.children[0]);
+        assert_eq!(nf6_alt.children.len(), 1);
+        assert_eq!(nf3.children.len(), 2);
+        assert_eq!(game.node(nf3.children[1]).nags, vec![6]);
+    }
+
+    #[test]
+    fn test_zero_castling() {
+        let text = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4.0-0 Nf6 5. d3 d6 6. Bg5 Qe7 7. Nc3 Bd7 8. Qd2 0-0-0 *";
+        let game = games(text).remove(0).unwrap();
+        let moves: Vec<String> = game.mainline_moves().iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(moves.len(), 16);
+        assert_eq!(moves[6], "e1g1");
+        assert_eq!(moves[15], "e8c8");
+    }
+
+    #[test]
+    fn test_setup_position() {
+        let game = games(PGN).remove(1).unwrap();
+        assert_eq!(game.start.to_fen(), "6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1");
+        assert_eq!(game.mainline_moves().len(), 2);
+        assert_eq!(game.result, "*");
+    }
+
+    #[test]
+    fn test_recovers_from_bad_games() {
+        let text = "[Event \"bad\"]\n\n1. e4 e5 2. Ke3 Nc6 *\n\n[Event \"good\"]\n\n1. d4 d5 *\n\n[Event \"open\"]\n\n1. e4 (1. d4 *\n\n[Event \"last\"]\n\n1. c4 *\n";
+        let games = games(text);
+        assert_eq!(games.len(), 4);
+        assert_eq!(games[0].as_ref().unwrap_err(), &PgnError::new(3, "illegal or ambiguous move 'Ke3'"));
+        assert_eq!(games[1].as_ref().unwrap().mainline_moves().len(), 2);
+        assert!(games[2].is_err());
+        assert_eq!(games[3].as_ref().unwrap().tag("Event"), Some("last"));
+    }
+
+    #[test]
+    fn test_error_lines() {
+        let error = parse_game("[Event \"x\"]\n\n1. e4 e5\n2. Nf3 {unclosed\n", 10).unwrap_err();
+        assert_eq!(error.line, 13);
+        let error = parse_game("[FEN \"not a fen\"]\n1. e4 *", 1).unwrap_err();
+        assert_eq!(error.line, 1);
+        let error = parse_game("1. e4 ) *", 5).unwrap_err();
+        assert_eq!(error.to_string(), "line 5: unmatched ')'");
+    }
+}
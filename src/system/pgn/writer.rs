@@ -0,0 +1,255 @@
+//! PGN writer
+//!
+//! Serializes a `Game` in export format: the Seven Tag Roster first, SAN
+//! movetext with variations and annotations, and lines of at most 80 columns.
+use std::fmt;
+use std::time::Duration;
+use super::{ Board, Color, Evaluation, Game, MoveGenerator, NodeId, START_FEN };
+
+const LINE_WIDTH: usize = 80;
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+fn format_clock(clock: Duration) -> String {
+    let secs = clock.as_secs();
+    let mut text = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    let millis = clock.subsec_millis();
+    if millis > 0 {
+        text.push_str(format!(".{:03}", millis).trim_end_matches('0'));
+    }
+    text
+}
+
+fn format_eval(eval: Evaluation, depth: Option<u32>) -> String {
+    let value = match eval {
+        Evaluation::Centipawns(cp) => format!("{:.2}", cp as f64 / 100.0),
+        Evaluation::Mate(n) => format!("#{}", n),
+    };
+    match depth {
+        Some(depth) => format!("{},{}", value, depth),
+        None => value,
+    }
+}
+
+// a comment as words, so long comments can wrap like the rest of the movetext
+fn push_comment(words: &mut Vec<String>, text: &str) {
+    // '}' can't be escaped inside a comment, so it is dropped
+    let text = text.replace('}', " ");
+    let mut parts: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+    if parts.is_empty() {
+        return;
+    }
+    parts[0].insert(0, '{');
+    parts.last_mut().unwrap().push('}');
+    words.extend(parts);
+}
+
+impl Game {
+    /// The game in PGN export format, ending with a blank line.
+    pub fn to_pgn(&self) -> String {
+        self.to_string()
+    }
+
+    fn export_tags(&self) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = SEVEN_TAG_ROSTER.iter().map(|&name| {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            (name.to_string(), value.to_string())
+        }).collect();
+
+        let fen = self.start.to_fen();
+        if fen != START_FEN && self.tag("FEN").is_none() {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+        tags.extend(self.tags.iter().filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str())).cloned());
+        tags
+    }
+
+    // starting comment, move number, SAN, NAGs and comment of one node;
+    // returns true when a comment follows the move
+    fn move_words(&self, board: &mut Board, id: NodeId, force_number: bool, words: &mut Vec<String>) -> bool {
+        let node = self.node(id);
+        let mv = node.mv.expect("only the root has no move");
+        let mut force_number = force_number;
+        if let Some(comment) = &node.starting_comment {
+            push_comment(words, comment);
+            force_number = true;
+        }
+
+        // the move number stays on the same line as its move
+        let san = MoveGenerator::global().to_san(board, mv);
+        words.push(match board.side_to_move {
+            Color::White => format!("{}. {}", board.full_move, san),
+            _ if force_number => format!("{}... {}", board.full_move, san),
+            _ => san,
+        });
+        words.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+
+        let mut comment = Vec::new();
+        if let Some(eval) = node.eval {
+            comment.push(format!("[%eval {}]", format_eval(eval, node.eval_depth)));
+        }
+        if let Some(clock) = node.clock {
+            comment.push(format!("[%clk {}]", format_clock(clock)));
+        }
+        comment.extend(node.comment.clone());
+        push_comment(words, &comment.join(" "));
+        !comment.is_empty()
+    }
+
+    // the main line after `parent` with every variation on the way
+    fn line_words(&self, board: &mut Board, parent: NodeId, force_number: bool, words: &mut Vec<String>) {
+        let children = &self.node(parent).children;
+        let Some(&main) = children.first() else { return };
+        let commented = self.move_words(board, main, force_number, words);
+
+        for &alternative in &children[1..] {
+            words.push("(".to_string());
+            let commented = self.move_words(board, alternative, true, words);
+            board.make_move(self.node(alternative).mv.unwrap());
+            self.line_words(board, alternative, commented, words);
+            board.unmake_move();
+            words.push(")".to_string());
+        }
+
+        board.make_move(self.node(main).mv.unwrap());
+        self.line_words(board, main, commented || children.len() > 1, words);
+        board.unmake_move();
+    }
+
+    fn movetext_words(&self) -> Vec<String> {
+        let mut words = Vec::new();
+        if let Some(comment) = &self.node(Game::ROOT).comment {
+            push_comment(&mut words, comment);
+        }
+        // a game starting with black still numbers its first move
+        let mut board = self.start.clone();
+        self.line_words(&mut board, Game::ROOT, true, &mut words);
+        words.push(self.result.clone());
+
+        // parentheses hug the moves they enclose
+        let mut glued: Vec<String> = Vec::new();
+        let mut open = false;
+        for word in words {
+            match word.as_str() {
+                "(" => open = true,
+                ")" => glued.last_mut().unwrap().push(')'),
+                _ if open => {
+                    glued.push(format!("({}", word));
+                    open = false;
+                },
+                _ => glued.push(word),
+            }
+        }
+        glued
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in self.export_tags() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        let mut line = String::new();
+        for word in self.movetext_words() {
+            if !line.is_empty() && line.len() + 1 + word.len() > LINE_WIDTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        writeln!(f, "{}", line)?;
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_game, Node, PgnReader };
+
+    const PGN: &str = r#"[Event "Casual Game"]
+[Site "https://lichess.org/abcdefgh"]
+[White "Alice \"A\""]
+[Black "Bob"]
+[Result "1-0"]
+[TimeControl "180+2"]
+
+{ A short game. } 1. e4 { [%clk 0:03:00] [%eval 0.3,18] } 1... e5 { [%clk 0:02:58.5] }
+2. Bc4 Nc6 3. Qh5 (3. Nf3 { developing } 3... Nf6 ({ the Philidor } 3... d6 $6) 4. Ng5)
+3... Nf6?? $18 { a long comment that goes on and on so that the movetext has to wrap somewhere }
+4. Qxf7# { [%eval #0] } 1-0
+"#;
+
+    fn same_tree(a: &Game, b: &Game) {
+        assert_eq!(a.nodes.len(), b.nodes.len());
+        let fields = |n: &Node| (n.mv, n.key, n.parent, n.children.clone(), n.starting_comment.clone(), n.comment.clone(), n.nags.clone(), n.clock, n.eval, n.eval_depth);
+        for (x, y) in a.nodes.iter().zip(&b.nodes) {
+            assert_eq!(fields(x), fields(y));
+        }
+        assert_eq!(a.result, b.result);
+        assert_eq!(a.start.to_fen(), b.start.to_fen());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let game = parse_game(PGN, 1).unwrap();
+        let written = game.to_pgn();
+        let again = parse_game(&written, 1).unwrap();
+        same_tree(&game, &again);
+        assert_eq!(again.to_pgn(), written);
+
+        assert!(written.starts_with("[Event \"Casual Game\"]\n[Site \"https://lichess.org/abcdefgh\"]\n[Date \"?\"]\n"));
+        assert!(written.contains("[White \"Alice \\\"A\\\"\"]\n"));
+        assert!(written.contains("[TimeControl \"180+2\"]\n\n{A short game.} 1. e4 {[%eval 0.30,18] [%clk 0:03:00]} 1... e5"));
+        let movetext = written.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(movetext.contains("3. Qh5 (3. Nf3 {developing} 3... Nf6 ({the Philidor} 3... d6 $6) 4. Ng5) 3... Nf6 $4 $18"));
+        assert!(written.ends_with("{[%eval #0]} 1-0\n\n"));
+        assert!(written.lines().all(|line| line.len() <= LINE_WIDTH));
+        assert_eq!(again.node(1).eval_depth, Some(18));
+    }
+
+    #[test]
+    fn test_comment_with_brace() {
+        let mut game = parse_game("1. e4 e5 *", 1).unwrap();
+        game.node_mut(1).comment = Some("closes} early".to_string());
+        game.node_mut(2).eval = Some(Evaluation::Mate(-3));
+        game.node_mut(2).eval_depth = Some(25);
+
+        let written = game.to_pgn();
+        assert!(written.contains("1. e4 {closes early} 1... e5 {[%eval #-3,25]} *"), "{}", written);
+        let again = parse_game(&written, 1).unwrap();
+        assert_eq!(again.node(1).comment.as_deref(), Some("closes early"));
+        assert_eq!((again.node(2).eval, again.node(2).eval_depth), (Some(Evaluation::Mate(-3)), Some(25)));
+    }
+
+    #[test]
+    fn test_setup_position() {
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1";
+        let mut board: Board = crate::system::parse_fen(fen).into();
+        let mut game = Game::new(board.clone());
+        let mg = MoveGenerator::global();
+        let mut parent = Game::ROOT;
+        for san in ["Kh8", "Rd8#"] {
+            let mv = mg.parse_san(&mut board, san).unwrap();
+            board.make_move(mv);
+            parent = game.add_move(parent, mv, board.zobrist_key);
+        }
+        game.result = "1-0".to_string();
+
+        let written = game.to_pgn();
+        assert!(written.contains("[SetUp \"1\"]\n[FEN \"6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1\"]\n"));
+        assert!(written.contains("\n1... Kh8 2. Rd8# 1-0\n"));
+
+        let again = PgnReader::new(written.as_bytes()).next().unwrap().unwrap();
+        same_tree(&game, &again);
+    }
+}
@@ -0,0 +1,214 @@
+//! Forced mate search
+//! 
+//! Proves or refutes mate in N for the side to move.
+//! Attacker nodes try checks first, defender nodes try every evasion
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use super::{ Board, Move, MoveGenerator };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MateResult {
+    // forced line starting with the attacker's move; defender plays the longest resistance
+    Mate { moves: u32, line: Vec<Move> },
+    // every line within max_moves was refuted
+    NoMate { max_moves: u32 },
+    // stopped from outside; every line within `searched` moves was refuted
+    Stopped { searched: u32 },
+}
+
+impl MateResult {
+    pub fn best_move(&self) -> Option<Move> {
+        match self {
+            MateResult::Mate { line, .. } => line.first().copied(),
+            MateResult::NoMate { .. } | MateResult::Stopped { .. } => None,
+        }
+    }
+}
+
+pub struct MateSearch<'a> {
+    mg: &'a MoveGenerator,
+    checks_only: bool,
+    nodes: u64,
+    stop: Arc<AtomicBool>,
+}
+
+// proves mate in up to max_moves (attacker moves) with the default settings
+pub fn mate_search(board: &mut Board, max_moves: u32) -> MateResult {
+    MateSearch::new(MoveGenerator::global()).search(board, max_moves)
+}
+
+impl<'a> MateSearch<'a> {
+    pub fn new(mg: &'a MoveGenerator) -> Self {
+        Self { mg, checks_only: false, nodes: 0, stop: Arc::new(AtomicBool::new(false)) }
+    }
+
+    // restrict the attacker to checking moves (much faster, misses quiet mates)
+    pub fn checks_only(mut self, checks_only: bool) -> Self {
+        self.checks_only = checks_only;
+        self
+    }
+
+    // share a flag that ends the search early when set, e.g. the searcher's stop handle
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // iterative deepening, so the first mate found is the shortest
+    pub fn search(&mut self, board: &mut Board, max_moves: u32) -> MateResult {
+        self.nodes = 0;
+        for moves in 1..=max_moves {
+            if let Some(line) = self.attack(board, moves) {
+                return MateResult::Mate { moves, line };
+            }
+            if self.stopped() {
+                return MateResult::Stopped { searched: moves - 1 };
+            }
+        }
+        MateResult::NoMate { max_moves }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // a stopped search refutes everything, so only complete proofs come back
+    fn attack(&mut self, board: &mut Board, moves_left: u32) -> Option<Vec<Move>> {
+        if self.stopped() {
+            return None;
+        }
+        self.nodes += 1;
+        // only a check can mate on the last move
+        let checks_only = self.checks_only || moves_left == 1;
+
+        for (mv, _) in self.ordered_moves(board, checks_only) {
+            board.make_move(mv);
+            let result = self.defend(board, moves_left);
+            board.unmake_move();
+
+            if let Some(mut line) = result {
+                line.insert(0, mv);
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    // returns the longest forced line if every evasion still loses
+    fn defend(&mut self, board: &mut Board, moves_left: u32) -> Option<Vec<Move>> {
+        if self.stopped() {
+            return None;
+        }
+        self.nodes += 1;
+        let evasions = self.mg.legal_moves(board);
+        if evasions.is_empty() {
+            // mate, or stalemate which refutes the attempt
+            return self.mg.in_check(board, board.side_to_move).then(Vec::new);
+        }
+        if moves_left == 1 {
+            return None;
+        }
+
+        let mut longest: Vec<Move> = Vec::new();
+        for mv in evasions {
+            board.make_move(mv);
+            let result = self.attack(board, moves_left - 1);
+            board.unmake_move();
+
+            let mut line = result?;
+            if line.len() + 1 > longest.len() {
+                line.insert(0, mv);
+                longest = line;
+            }
+        }
+        Some(longest)
+    }
+
+    // checks first, then captures, then quiet moves
+    fn ordered_moves(&self, board: &mut Board, checks_only: bool) -> Vec<(Move, u8)> {
+        let mut moves: Vec<(Move, u8)> = self.mg.legal_moves(board)
+            .into_iter()
+            .map(|mv| {
+                let check = self.mg.gives_check(board, mv);
+                let priority = (check as u8) * 2 + mv.is_capture() as u8;
+                (mv, priority)
+            })
+            .filter(|&(_, priority)| !checks_only || priority >= 2)
+            .collect();
+        moves.sort_by_key(|&(_, priority)| std::cmp::Reverse(priority));
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    #[test]
+    fn test_mate_in_one() {
+        // back rank mate
+        let mut board: Board = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").into();
+        let result = mate_search(&mut board, 3);
+        assert_eq!(result, MateResult::Mate { moves: 1, line: vec![
+            Move::Normal { from: crate::system::named::A1, to: crate::system::named::A8 },
+        ]});
+    }
+
+    #[test]
+    fn test_mate_in_two() {
+        // knight check opens the f-file for Bxf7 mate
+        let mut board: Board = parse_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 0").into();
+        let result = mate_search(&mut board, 2);
+        match result {
+            MateResult::Mate { moves, line } => {
+                assert_eq!(moves, 2);
+                assert_eq!(line.len(), 3);
+                assert_eq!(line[0].to_string(), "d5f6");
+            },
+            other => panic!("expected mate in 2, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_no_mate_and_stalemate() {
+        let mut board: Board = parse_fen(START_FEN).into();
+        assert_eq!(mate_search(&mut board, 2), MateResult::NoMate { max_moves: 2 });
+
+        // Qb6 would stalemate, only a real mate counts
+        let mut board: Board = parse_fen("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1").into();
+        let result = mate_search(&mut board, 1);
+        assert_eq!(result.best_move().map(|mv| mv.to_string()), Some("c1c8".to_string()));
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut board: Board = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").into();
+        let stop = Arc::new(AtomicBool::new(true));
+        let mut search = MateSearch::new(MoveGenerator::global()).with_stop(Arc::clone(&stop));
+        assert_eq!(search.search(&mut board, 20), MateResult::Stopped { searched: 0 });
+
+        stop.store(false, Ordering::Relaxed);
+        assert!(matches!(search.search(&mut board, 20), MateResult::Mate { moves: 1, .. }));
+    }
+
+    #[test]
+    fn test_checks_only_is_faster() {
+        let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 0";
+        let mg = MoveGenerator::global();
+
+        let mut board: Board = parse_fen(fen).into();
+        let mut full = MateSearch::new(mg);
+        let full_result = full.search(&mut board, 2);
+
+        let mut checks = MateSearch::new(mg).checks_only(true);
+        let checks_result = checks.search(&mut board, 2);
+
+        assert_eq!(full_result.best_move(), checks_result.best_move());
+        assert!(checks.nodes() <= full.nodes());
+    }
+}
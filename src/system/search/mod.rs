@@ -0,0 +1,13 @@
+use super::*;
+pub mod mate;
+pub mod pns;
+#[allow(clippy::module_inception)]
+pub mod search;
+pub mod time;
+pub mod tt;
+
+pub use mate::*;
+pub use pns::*;
+pub use search::*;
+pub use time::*;
+pub use tt::*;
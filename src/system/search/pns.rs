@@ -0,0 +1,403 @@
+//! Depth-first proof-number search (df-pn)
+//! 
+//! Proves or disproves a forced mate using proof/disproof numbers.
+//! Nodes are stored in a fixed size table keyed by the Zobrist key,
+//! so long forcing lines can be solved without a depth limit
+use std::mem::size_of;
+use super::{ Board, Color, Move, MoveGenerator };
+
+const INFINITY: u32 = u32::MAX;
+const DEFAULT_MEMORY: usize = 64 * 1024 * 1024;
+const MAX_LINE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofResult {
+    // attacker forces mate in `moves`; the main line ends in checkmate
+    Proven { moves: u32, line: Vec<Move> },
+    // attacker cannot force mate
+    Disproven,
+    // node budget ran out first
+    Unknown,
+}
+
+// phi/delta are proof/disproof numbers seen from the side to move:
+// at attacker nodes phi = pn and delta = dn, at defender nodes they swap
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    key: u64,
+    phi: u32,
+    delta: u32,
+    work: u32,
+    // plies to mate along the proof tree, once proven
+    distance: u32,
+}
+
+struct NodeTable {
+    entries: Vec<Entry>,
+    mask: usize,
+}
+
+impl NodeTable {
+    fn new(memory: usize) -> Self {
+        // round down to a power of two so the key can be masked
+        let count = (memory / size_of::<Entry>()).max(1024);
+        let count = 1usize << (usize::BITS - 1 - count.leading_zeros());
+        Self { entries: vec![Entry::default(); count], mask: count - 1 }
+    }
+
+    fn empty() -> Self {
+        Self { entries: Vec::new(), mask: 0 }
+    }
+
+    fn get(&self, key: u64) -> Option<&Entry> {
+        let entry = &self.entries[key as usize & self.mask];
+        (entry.key == key && entry.work > 0).then_some(entry)
+    }
+
+    fn store(&mut self, key: u64, phi: u32, delta: u32, work: u32, distance: u32) {
+        let entry = &mut self.entries[key as usize & self.mask];
+        let solved = phi == 0 || delta == 0;
+        let entry_solved = entry.work > 0 && (entry.phi == 0 || entry.delta == 0);
+        // keep the more expensive result, but never drop a solved node for an open one
+        if entry.key == key || entry.work == 0 || (solved && !entry_solved) || (solved == entry_solved && work >= entry.work) {
+            *entry = Entry { key, phi, delta, work: work.max(1), distance };
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.fill(Entry::default());
+    }
+}
+
+pub struct ProofNumberSearch<'a> {
+    mg: &'a MoveGenerator,
+    table: NodeTable,
+    memory: usize,
+    attacker: Color,
+    checks_only: bool,
+    max_nodes: u64,
+    nodes: u64,
+}
+
+impl<'a> ProofNumberSearch<'a> {
+    pub fn new(mg: &'a MoveGenerator) -> Self {
+        Self {
+            mg,
+            table: NodeTable::empty(),
+            memory: DEFAULT_MEMORY,
+            attacker: Color::White,
+            checks_only: false,
+            max_nodes: u64::MAX,
+            nodes: 0,
+        }
+    }
+
+    // caps the node table at roughly this many bytes
+    pub fn memory(mut self, bytes: usize) -> Self {
+        self.memory = bytes;
+        self
+    }
+
+    // restrict the attacker to checking moves
+    pub fn checks_only(mut self, checks_only: bool) -> Self {
+        self.checks_only = checks_only;
+        self
+    }
+
+    pub fn max_nodes(mut self, max_nodes: u64) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // can the side to move force mate?
+    pub fn prove_win(&mut self, board: &mut Board) -> ProofResult {
+        self.solve(board, board.side_to_move)
+    }
+
+    // can the opponent force mate against the side to move?
+    pub fn prove_loss(&mut self, board: &mut Board) -> ProofResult {
+        self.solve(board, !board.side_to_move)
+    }
+
+    pub fn solve(&mut self, board: &mut Board, attacker: Color) -> ProofResult {
+        self.attacker = attacker;
+        self.nodes = 0;
+        // allocated on first use so the memory cap can be set beforehand
+        if self.table.entries.is_empty() {
+            self.table = NodeTable::new(self.memory);
+        } else {
+            self.table.clear();
+        }
+
+        let (phi, delta) = self.mid(board, INFINITY, INFINITY);
+        let root_pn = if board.side_to_move == attacker { phi } else { delta };
+        let root_dn = if board.side_to_move == attacker { delta } else { phi };
+
+        if root_pn == 0 {
+            let line = self.main_line(board);
+            ProofResult::Proven { moves: line.len().div_ceil(2) as u32, line }
+        } else if root_dn == 0 {
+            ProofResult::Disproven
+        } else {
+            ProofResult::Unknown
+        }
+    }
+
+    fn is_attacker(&self, board: &Board) -> bool {
+        board.side_to_move == self.attacker
+    }
+
+    fn moves(&self, board: &mut Board) -> Vec<Move> {
+        let moves = self.mg.legal_moves(board);
+        if self.checks_only && self.is_attacker(board) {
+            return moves.into_iter().filter(|&mv| self.mg.gives_check(board, mv)).collect();
+        }
+        moves
+    }
+
+    // (phi, delta) of a node with no moves, or repeating
+    fn terminal(&self, board: &Board, has_moves: bool) -> (u32, u32) {
+        // a stalled attack (stalemate, repetition) is lost for the attacker,
+        // the defender only loses when mated
+        let mover_loses = self.is_attacker(board) || (!has_moves && self.mg.in_check(board, board.side_to_move));
+        if mover_loses {
+            (INFINITY, 0)
+        } else {
+            (0, INFINITY)
+        }
+    }
+
+    // table values where present, otherwise the last result this node saw for the
+    // child (repetitions are never stored), otherwise the (1, 1) of an unexplored node
+    fn child_values(&self, keys: &[u64], seen: &[Option<(u32, u32)>]) -> Vec<(u32, u32)> {
+        keys.iter()
+            .zip(seen)
+            .map(|(&key, &seen)| {
+                self.table.get(key)
+                    .map(|e| (e.phi, e.delta))
+                    .or(seen)
+                    .unwrap_or((1, 1))
+            })
+            .collect()
+    }
+
+    // plies to mate of a node proven for the attacker: the quickest proven move at
+    // attacker nodes, the slowest defence at defender nodes
+    fn proof_distance(&self, board: &Board, keys: &[u64]) -> u32 {
+        let attacker = self.is_attacker(board);
+        let distances = keys.iter().filter_map(|&key| {
+            let entry = self.table.get(key)?;
+            let proven = if attacker { entry.delta == 0 } else { entry.phi == 0 };
+            proven.then_some(entry.distance)
+        });
+        let distance = if attacker { distances.min() } else { distances.max() };
+        distance.unwrap_or(0) + 1
+    }
+
+    fn mid(&mut self, board: &mut Board, th_phi: u32, th_delta: u32) -> (u32, u32) {
+        self.nodes += 1;
+        let start_nodes = self.nodes;
+
+        if board.ply() > 0 && board.is_repetition() {
+            return self.terminal(board, true);
+        }
+
+        let moves = self.moves(board);
+        if moves.is_empty() {
+            // includes a checks_only attacker with no check left
+            let result = self.terminal(board, false);
+            self.table.store(board.zobrist_key, result.0, result.1, 1, 0);
+            return result;
+        }
+
+        let keys: Vec<u64> = moves.iter().map(|&mv| {
+            board.make_move(mv);
+            let key = board.zobrist_key;
+            board.unmake_move();
+            key
+        }).collect();
+
+        let mut seen = vec![None; keys.len()];
+        let (mut phi, mut delta);
+        loop {
+            let values = self.child_values(&keys, &seen);
+            phi = values.iter().map(|v| v.1).min().unwrap_or(INFINITY);
+            delta = values.iter().fold(0u32, |sum, v| sum.saturating_add(v.0));
+
+            if phi >= th_phi || delta >= th_delta || self.nodes >= self.max_nodes {
+                break;
+            }
+
+            // child with the smallest delta, and the runner-up's delta
+            let best = (0..values.len()).min_by_key(|&i| values[i].1).unwrap_or(0);
+            let second = values.iter()
+                .enumerate()
+                .filter(|&(i, _)| i != best)
+                .map(|(_, v)| v.1)
+                .min()
+                .unwrap_or(INFINITY);
+
+            let (child_phi, _) = values[best];
+            let child_th_phi = if th_delta == INFINITY {
+                INFINITY
+            } else {
+                th_delta - (delta - child_phi)
+            };
+            let child_th_delta = th_phi.min(second.saturating_add(1));
+
+            board.make_move(moves[best]);
+            seen[best] = Some(self.mid(board, child_th_phi, child_th_delta));
+            board.unmake_move();
+        }
+
+        let work = (self.nodes - start_nodes + 1).min(u32::MAX as u64) as u32;
+        let attacker = self.is_attacker(board);
+        let proven = if attacker { phi == 0 } else { delta == 0 };
+        let distance = if proven { self.proof_distance(board, &keys) } else { 0 };
+        self.table.store(board.zobrist_key, phi, delta, work, distance);
+        (phi, delta)
+    }
+
+    // follows the proof tree: the attacker takes the quickest proven move, the defender the slowest
+    fn main_line(&mut self, board: &mut Board) -> Vec<Move> {
+        let mut line = Vec::new();
+        while line.len() < MAX_LINE {
+            let moves = self.moves(board);
+            if moves.is_empty() {
+                break;
+            }
+
+            let mut choice = self.choose(board, &moves);
+            if choice.is_none() {
+                // the proof was evicted from the table, so prove this node again
+                self.mid(board, INFINITY, INFINITY);
+                choice = self.choose(board, &moves);
+            }
+
+            let Some(mv) = choice else { break };
+            line.push(mv);
+            board.make_move(mv);
+        }
+
+        for _ in 0..line.len() {
+            board.unmake_move();
+        }
+        line
+    }
+
+    fn choose(&mut self, board: &mut Board, moves: &[Move]) -> Option<Move> {
+        let attacker = self.is_attacker(board);
+        let mut choice: Option<(Move, u32)> = None;
+        for &mv in moves {
+            board.make_move(mv);
+            let entry = self.table.get(board.zobrist_key).copied();
+            board.unmake_move();
+
+            // proven for the attacker: attacker children have delta 0, defender children phi 0
+            let Some(entry) = entry else {
+                if attacker {
+                    continue;
+                }
+                // every defence has to be proven, one missing means the proof is gone
+                return None;
+            };
+            let proven = if attacker { entry.delta == 0 } else { entry.phi == 0 };
+            if !proven {
+                if attacker {
+                    continue;
+                }
+                return None;
+            }
+            let better = match choice {
+                None => true,
+                Some((_, best)) if attacker => entry.distance < best,
+                Some((_, best)) => entry.distance > best,
+            };
+            if better {
+                choice = Some((mv, entry.distance));
+            }
+        }
+        choice.map(|(mv, _)| mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    const LONG_MATES: &str = include_str!("../../../data/long_mates.txt");
+
+    // fen | shortest checks-only mate | proof length
+    fn problems() -> Vec<(&'static str, u32, u32)> {
+        LONG_MATES.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+                (fields[0], fields[1].parse().unwrap(), fields[2].parse().unwrap())
+            })
+            .collect()
+    }
+
+    fn assert_mating_line(board: &mut Board, line: &[Move]) {
+        let mg = MoveGenerator::global();
+        for (i, &mv) in line.iter().enumerate() {
+            assert!(mg.legal_moves(board).contains(&mv), "illegal move {} in line", mv);
+            if i % 2 == 0 {
+                assert!(mg.gives_check(board, mv), "attacker move {} is not a check", mv);
+            }
+            board.make_move(mv);
+        }
+        assert!(mg.legal_moves(board).is_empty());
+        assert!(mg.in_check(board, board.side_to_move));
+        for _ in line {
+            board.unmake_move();
+        }
+    }
+
+    #[test]
+    fn test_bundled_long_mates() {
+        let mg = MoveGenerator::global();
+        let problems = problems();
+        assert!(problems.iter().any(|&(_, mate_in, proven)| mate_in >= 8 && proven == mate_in));
+        for (fen, mate_in, proven) in problems {
+            let mut board: Board = parse_fen(fen).into();
+            let mut search = ProofNumberSearch::new(mg).memory(16 * 1024 * 1024).checks_only(true);
+            match search.prove_win(&mut board) {
+                ProofResult::Proven { moves, line } => {
+                    assert!(moves >= mate_in, "{}: proof shorter than the known mate", fen);
+                    assert_eq!(moves, proven, "{}: wrong mate distance", fen);
+                    assert_eq!(line.len() as u32, 2 * moves - 1);
+                    assert_mating_line(&mut board, &line);
+                },
+                other => panic!("{}: expected a proof, got {:?}", fen, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_disproof_and_loss() {
+        let mg = MoveGenerator::global();
+        // the luft on h6 means the back rank checks run out
+        let mut board: Board = parse_fen("6k1/5pp1/7p/8/8/8/8/R5K1 w - - 0 1").into();
+        let mut search = ProofNumberSearch::new(mg).memory(1024 * 1024).checks_only(true);
+        assert_eq!(search.prove_win(&mut board), ProofResult::Disproven);
+
+        // black's only move walks into a rook mate
+        let mut board: Board = parse_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").into();
+        let mut search = ProofNumberSearch::new(mg).memory(1024 * 1024).checks_only(true);
+        assert!(matches!(search.prove_loss(&mut board), ProofResult::Proven { .. }));
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let mut board: Board = parse_fen(START_FEN).into();
+        let mut search = ProofNumberSearch::new(MoveGenerator::global()).memory(1024 * 1024).max_nodes(500);
+        assert_eq!(search.prove_win(&mut board), ProofResult::Unknown);
+        assert!(search.nodes() <= 501);
+    }
+}
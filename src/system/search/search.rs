@@ -0,0 +1,939 @@
+//! Alpha-beta search
+//! 
+//! Iterative deepening principal variation search with a transposition table,
+//! aspiration windows, null move pruning, late move reductions and quiescence.
+//! Several threads can search the same root (Lazy SMP), and the root can be
+//! searched for several best lines (MultiPV)
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+use super::{ evaluate, see, Board, Bound, Color, GameClock, Move, MoveGenerator, Network, Nnue, Piece, PieceType, Side, TimeManager, TranspositionTable, DEFAULT_MOVE_OVERHEAD, MATE, MATE_BOUND, MAX_PLY };
+
+pub const INFINITY: i32 = 32_000;
+pub const DEFAULT_HASH_MB: usize = 16;
+const ASPIRATION_WINDOW: i32 = 25;
+const HISTORY_LIMIT: i32 = 50_000;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    // the side to move's clock, for the time manager
+    pub clock: Option<GameClock>,
+    // only these root moves are searched; empty means all of them
+    pub searchmoves: Vec<Move>,
+    // number of best lines to search; 0 and 1 both mean just the best
+    pub multipv: usize,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        Self { depth: Some(depth), ..Self::default() }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self { nodes: Some(nodes), ..Self::default() }
+    }
+
+    pub fn movetime(movetime: Duration) -> Self {
+        Self { movetime: Some(movetime), ..Self::default() }
+    }
+
+    pub fn multipv(mut self, lines: usize) -> Self {
+        self.multipv = lines;
+        self
+    }
+}
+
+// reported after every completed iteration
+#[derive(Debug, Clone)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub seldepth: u32,
+    // 1 for the best line, 2 for the next best and so on
+    pub multipv: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+    pub hashfull: usize,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    pub fn nps(&self) -> u64 {
+        let micros = self.time.as_micros().max(1) as u64;
+        self.nodes * 1_000_000 / micros
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    // every line searched, best first; just the main line without MultiPV
+    pub lines: Vec<PvLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+// UCI score: "cp 35", "mate 3" or "mate -2"
+pub fn format_score(score: i32) -> String {
+    if score > MATE_BOUND {
+        format!("mate {}", (MATE - score + 1) / 2)
+    } else if score < -MATE_BOUND {
+        format!("mate -{}", (MATE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+pub struct Searcher {
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    done: Arc<AtomicBool>,
+    helper_nodes: Arc<AtomicU64>,
+    network: Option<Arc<Network>>,
+    move_overhead: Duration,
+    // the main thread's worker first, then the helpers
+    workers: Vec<Worker>,
+    nodes: u64,
+}
+
+// one search thread with its own move ordering tables
+struct Worker {
+    id: usize,
+    mg: &'static MoveGenerator,
+    tt: Arc<TranspositionTable>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: [[i32; 64]; 12],
+    pv: Vec<Vec<Move>>,
+    nodes: u64,
+    seldepth: usize,
+    limits: SearchLimits,
+    start: Instant,
+    stop: Arc<AtomicBool>,
+    // set while thinking on the opponent's time; cleared on ponderhit
+    ponder: Arc<AtomicBool>,
+    // whether this search has not seen the ponderhit yet (main thread only)
+    pondering: bool,
+    // when the clock started, for movetime: the ponderhit when pondering
+    timer_start: Instant,
+    // set once the main thread has finished, to end the helpers
+    done: Arc<AtomicBool>,
+    // nodes searched by the helpers, so the main thread can apply node limits
+    helper_nodes: Arc<AtomicU64>,
+    flushed: u64,
+    stopped: bool,
+    // soft and hard limits when playing on a clock (main thread only)
+    time: Option<TimeManager>,
+    // nodes spent under each root move this iteration, by from and to square
+    root_nodes: Vec<u64>,
+    // root moves already taken by better MultiPV lines this iteration
+    excluded: Vec<Move>,
+    // hand-crafted evaluation when no network is loaded
+    nnue: Option<Nnue>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl Searcher {
+    pub fn new(hash_mb: usize) -> Self {
+        let mut searcher = Self {
+            tt: Arc::new(TranspositionTable::new(hash_mb)),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            done: Arc::new(AtomicBool::new(false)),
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            network: None,
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+            workers: Vec::new(),
+            nodes: 0,
+        };
+        searcher.set_threads(1);
+        searcher
+    }
+
+    // setting the returned flag ends the search at the next node
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Set before a search to think on the opponent's time: the clock is
+    /// ignored and the search does not return until the flag is cleared
+    /// (ponderhit), when it goes on as a normal timed search with its table,
+    /// depth and time manager state intact, or until it is stopped.
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.ponder)
+    }
+
+    pub fn resize_hash(&mut self, hash_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(hash_mb));
+        for worker in &mut self.workers {
+            worker.tt = Arc::clone(&self.tt);
+        }
+    }
+
+    // forget everything learned, e.g. for a new game
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        for worker in &mut self.workers {
+            worker.history = [[0; 64]; 12];
+            worker.killers = [[None; 2]; MAX_PLY];
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        let threads = threads.max(1);
+        self.workers.truncate(threads);
+        while self.workers.len() < threads {
+            let worker = Worker {
+                id: self.workers.len(),
+                mg: MoveGenerator::global(),
+                tt: Arc::clone(&self.tt),
+                killers: [[None; 2]; MAX_PLY],
+                history: [[0; 64]; 12],
+                pv: vec![Vec::new(); MAX_PLY + 1],
+                nodes: 0,
+                seldepth: 0,
+                limits: SearchLimits::default(),
+                start: Instant::now(),
+                stop: Arc::clone(&self.stop),
+                ponder: Arc::clone(&self.ponder),
+                pondering: false,
+                timer_start: Instant::now(),
+                done: Arc::clone(&self.done),
+                helper_nodes: Arc::clone(&self.helper_nodes),
+                flushed: 0,
+                stopped: false,
+                time: None,
+                root_nodes: vec![0; 64 * 64],
+                excluded: Vec::new(),
+                nnue: self.network.clone().map(Nnue::new),
+            };
+            self.workers.push(worker);
+        }
+    }
+
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        for worker in &mut self.workers {
+            worker.nnue = network.clone().map(Nnue::new);
+        }
+        self.network = network;
+    }
+
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        self.network.as_ref()
+    }
+
+    // time kept in reserve on every move for communication delays
+    pub fn set_move_overhead(&mut self, overhead: Duration) {
+        self.move_overhead = overhead;
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    pub fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        self.search_with(board, limits, |_| {})
+    }
+
+    /// Searches with every thread (Lazy SMP): the workers share only the
+    /// transposition table, and the main thread reports progress and decides
+    /// when the search ends.
+    pub fn search_with(&mut self, board: &mut Board, mut limits: SearchLimits, mut report: impl FnMut(&SearchInfo)) -> SearchResult {
+        let mg = MoveGenerator::global();
+        let mut root_moves = mg.legal_moves(board);
+        if !limits.searchmoves.is_empty() {
+            root_moves.retain(|mv| limits.searchmoves.contains(mv));
+        }
+        let Some(&first) = root_moves.first() else {
+            let score = if mg.in_check(board, board.side_to_move) { -MATE } else { 0 };
+            self.nodes = 0;
+            return SearchResult { best_move: None, score, depth: 0, nodes: 0, pv: Vec::new(), lines: Vec::new() };
+        };
+        limits.multipv = limits.multipv.clamp(1, root_moves.len());
+
+        self.done.store(false, Ordering::Relaxed);
+        self.helper_nodes.store(0, Ordering::Relaxed);
+        let start = Instant::now();
+        // helpers run until the main thread is done, and only look for the best line
+        let helper_limits = SearchLimits { searchmoves: limits.searchmoves.clone(), ..SearchLimits::default() };
+        let (main, helpers) = self.workers.split_first_mut().expect("at least one worker");
+        main.time = limits.clock.map(|clock| TimeManager::new(clock, self.move_overhead));
+        let (done, stop, ponder) = (&self.done, &self.stop, &self.ponder);
+
+        let results = thread::scope(|scope| {
+            let handles: Vec<_> = helpers.iter_mut().map(|worker| {
+                let mut board = board.clone();
+                let limits = helper_limits.clone();
+                scope.spawn(move || worker.iterate(&mut board, limits, start, first, &mut |_| {}))
+            }).collect();
+
+            let mut results = vec![main.iterate(board, limits, start, first, &mut report)];
+            // a finished ponder search still may not answer before the ponderhit
+            while ponder.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            done.store(true, Ordering::Relaxed);
+            results.extend(handles.into_iter().map(|handle| handle.join().expect("search thread panicked")));
+            results
+        });
+
+        self.nodes = results.iter().map(|r| r.nodes).sum();
+        let mut result = vote(&results);
+        result.nodes = self.nodes;
+        result
+    }
+}
+
+// every thread votes for its move, weighted by depth and by how much better
+// than the worst thread it scored; the deepest result for the winner is kept.
+// MultiPV lines only come from the main thread, so it decides alone then
+fn vote(results: &[SearchResult]) -> SearchResult {
+    let main = &results[0];
+    if results.len() == 1 || main.score.abs() > MATE_BOUND || main.lines.len() > 1 {
+        return main.clone();
+    }
+    let worst = results.iter().map(|r| r.score).min().unwrap_or(0);
+    let mut votes: Vec<(Move, i64)> = Vec::new();
+    for result in results {
+        let Some(mv) = result.best_move else { continue };
+        let weight = (result.score - worst + 10) as i64 * result.depth as i64;
+        match votes.iter_mut().find(|(voted, _)| *voted == mv) {
+            Some((_, total)) => *total += weight,
+            None => votes.push((mv, weight)),
+        }
+    }
+    // the main thread's move wins ties
+    let Some(&(winner, _)) = votes.iter().rev().max_by_key(|(_, total)| *total) else { return main.clone() };
+    results.iter()
+        .filter(|r| r.best_move == Some(winner))
+        .rev()
+        .max_by_key(|r| r.depth)
+        .unwrap_or(main)
+        .clone()
+}
+
+impl Worker {
+    // iterative deepening from depth 1; odd-numbered helpers search every
+    // iteration one ply deeper so the threads spread over depths
+    fn iterate(&mut self, board: &mut Board, limits: SearchLimits, start: Instant, first: Move, report: &mut dyn FnMut(&SearchInfo)) -> SearchResult {
+        self.limits = limits;
+        self.start = start;
+        self.timer_start = start;
+        self.pondering = self.id == 0 && self.ponder.load(Ordering::Relaxed);
+        self.nodes = 0;
+        self.flushed = 0;
+        self.stopped = false;
+        self.killers = [[None; 2]; MAX_PLY];
+        for row in self.history.iter_mut() {
+            row.iter_mut().for_each(|h| *h /= 8);
+        }
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.refresh(board);
+        }
+
+        let mut result = SearchResult {
+            best_move: Some(first),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: vec![first],
+            lines: vec![PvLine { score: 0, pv: vec![first] }],
+        };
+        let max_depth = self.limits.depth.unwrap_or(u32::MAX).min(MAX_PLY as u32 - 1);
+        let multipv = self.limits.multipv.max(1);
+
+        for iteration in 1..=max_depth {
+            let depth = if self.id % 2 == 1 && iteration > 1 { (iteration + 1).min(max_depth) } else { iteration };
+            self.seldepth = 0;
+            self.root_nodes.fill(0);
+            self.excluded.clear();
+
+            // each line is the best root move the lines before it left over,
+            // searched around that line's score from the previous iteration
+            let mut lines = Vec::with_capacity(multipv);
+            for index in 0..multipv {
+                let previous = result.lines.get(index).map_or(0, |line| line.score);
+                let score = self.aspiration(board, depth as i32, previous);
+
+                // an interrupted iteration is only trusted if nothing better exists
+                if self.stopped && (iteration > 1 || self.pv[0].is_empty()) {
+                    break;
+                }
+                let Some(&best) = self.pv[0].first() else { break };
+                lines.push(PvLine { score, pv: self.pv[0].clone() });
+                self.excluded.push(best);
+                if self.stopped {
+                    break;
+                }
+            }
+            self.excluded.clear();
+            if lines.is_empty() {
+                break;
+            }
+
+            // lines this iteration did not get to keep their previous result
+            for line in &result.lines {
+                if lines.len() < multipv && !lines.iter().any(|l| l.pv[0] == line.pv[0]) {
+                    lines.push(line.clone());
+                }
+            }
+            lines.sort_by_key(|line| -line.score);
+
+            result = SearchResult {
+                best_move: lines[0].pv.first().copied(),
+                score: lines[0].score,
+                depth,
+                nodes: self.nodes,
+                pv: lines[0].pv.clone(),
+                lines,
+            };
+            for (index, line) in result.lines.iter().enumerate() {
+                report(&SearchInfo {
+                    depth,
+                    seldepth: self.seldepth as u32,
+                    multipv: index + 1,
+                    score: line.score,
+                    nodes: self.nodes + self.helper_nodes.load(Ordering::Relaxed),
+                    time: self.start.elapsed(),
+                    hashfull: self.tt.hashfull(),
+                    pv: line.pv.clone(),
+                });
+            }
+
+            if self.stopped {
+                break;
+            }
+            // while pondering the time manager keeps learning but never stops the search
+            let pondering = self.pondering();
+            if let (Some(time), Some(best)) = (self.time.as_mut(), result.best_move) {
+                let iteration_nodes = self.root_nodes.iter().sum::<u64>().max(1);
+                let fraction = self.root_nodes[(best.pack() & 0xfff) as usize] as f64 / iteration_nodes as f64;
+                if time.stop_iterating(best.pack(), result.score, fraction) && !pondering {
+                    break;
+                }
+            }
+            // the next iteration would most likely not finish in time
+            if let Some(movetime) = self.limits.movetime {
+                if !pondering && self.timer_start.elapsed() > movetime / 2 {
+                    break;
+                }
+            }
+        }
+
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn aspiration(&mut self, board: &mut Board, depth: i32, previous: i32) -> i32 {
+        if depth < 4 || previous.abs() > MATE_BOUND {
+            return self.negamax(board, depth, -INFINITY, INFINITY, 0);
+        }
+
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = (previous - delta, previous + delta);
+        loop {
+            let score = self.negamax(board, depth, alpha, beta, 0);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                alpha = (alpha - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (beta + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+            if delta > 1000 {
+                alpha = -INFINITY;
+                beta = INFINITY;
+            }
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.id == 0 {
+            if let Some(nodes) = self.limits.nodes {
+                if self.nodes + self.helper_nodes.load(Ordering::Relaxed) >= nodes {
+                    self.stopped = true;
+                }
+            }
+            if self.nodes & 1023 == 0 && !self.pondering() {
+                if let Some(movetime) = self.limits.movetime {
+                    if self.timer_start.elapsed() >= movetime {
+                        self.stopped = true;
+                    }
+                }
+                if self.time.as_ref().is_some_and(TimeManager::hard_limit_reached) {
+                    self.stopped = true;
+                }
+            }
+        } else if self.nodes & 1023 == 0 {
+            self.helper_nodes.fetch_add(self.nodes - self.flushed, Ordering::Relaxed);
+            self.flushed = self.nodes;
+        }
+        if self.stop.load(Ordering::Relaxed) || self.done.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    // true until the ponderhit; from then on our own clock is running
+    fn pondering(&mut self) -> bool {
+        if self.pondering && !self.ponder.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.timer_start = Instant::now();
+            if let Some(time) = self.time.as_mut() {
+                time.restart();
+            }
+        }
+        self.pondering
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(mv);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+
+    // the board and the network's accumulators move together
+    fn make_move(&mut self, board: &mut Board, mv: Move) {
+        board.make_move(mv);
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.push(board);
+        }
+    }
+
+    fn unmake_move(&mut self, board: &mut Board) {
+        board.unmake_move();
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.pop();
+        }
+    }
+
+    fn make_null_move(&mut self, board: &mut Board) {
+        board.make_null_move();
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.push(board);
+        }
+    }
+
+    fn unmake_null_move(&mut self, board: &mut Board) {
+        board.unmake_null_move();
+        if let Some(nnue) = self.nnue.as_mut() {
+            nnue.pop();
+        }
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        match &self.nnue {
+            Some(nnue) => nnue.evaluate(board),
+            None => evaluate(board),
+        }
+    }
+
+    fn negamax(&mut self, board: &mut Board, mut depth: i32, mut alpha: i32, mut beta: i32, ply: usize) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        let pv_node = beta - alpha > 1;
+
+        if ply > 0 {
+            if board.half_move >= 100 || board.is_repetition() || insufficient_material(board) {
+                return 0;
+            }
+            // no line from here can beat a mate already found closer to the root
+            alpha = alpha.max(-MATE + ply as i32);
+            beta = beta.min(MATE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(board);
+        }
+
+        let us = board.side_to_move;
+        let in_check = self.mg.in_check(board, us);
+        if in_check {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+
+        let key = board.zobrist_key;
+        let tt_entry = self.tt.probe(key, ply);
+        let tt_move = tt_entry.map_or(0, |e| e.best_move);
+        if let Some(entry) = tt_entry {
+            if !pv_node && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {},
+                }
+            }
+        }
+
+        let static_eval = self.evaluate(board);
+        if !pv_node && !in_check && beta.abs() < MATE_BOUND {
+            // reverse futility: far enough above beta that a shallow search won't fall back
+            if depth <= 3 && static_eval - 120 * depth >= beta {
+                return static_eval;
+            }
+
+            // null move: passing still fails high, so a real move will too
+            if depth >= 3 && ply > 0 && static_eval >= beta && has_non_pawn_material(board, us) {
+                let reduction = 2 + depth / 4;
+                self.make_null_move(board);
+                let score = -self.negamax(board, depth - 1 - reduction, -beta, -beta + 1, ply + 1);
+                self.unmake_null_move(board);
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    return if score > MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+
+        let moves = self.ordered_moves(board, self.mg.pseudo_legal_moves(board), tt_move, ply);
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut legal = 0;
+
+        let restricted = ply == 0 && (!self.limits.searchmoves.is_empty() || !self.excluded.is_empty());
+        for mv in moves {
+            if restricted && (self.excluded.contains(&mv) || (!self.limits.searchmoves.is_empty() && !self.limits.searchmoves.contains(&mv))) {
+                continue;
+            }
+            let piece = board.pieces[mv.from().index()];
+            let nodes_before = self.nodes;
+            self.make_move(board, mv);
+            if self.mg.in_check(board, us) {
+                self.unmake_move(board);
+                continue;
+            }
+            legal += 1;
+
+            let quiet = !mv.is_capture() && mv.promotion().is_none();
+            let gives_check = self.mg.in_check(board, board.side_to_move);
+            let score = if legal == 1 {
+                -self.negamax(board, depth - 1, -beta, -alpha, ply + 1)
+            } else {
+                // late quiet moves are searched shallower first
+                let mut reduction = 0;
+                if depth >= 3 && legal > 3 && quiet && !in_check && !gives_check {
+                    reduction = (1 + (legal > 8) as i32 + (depth > 6) as i32).min(depth - 2);
+                }
+                let mut score = -self.negamax(board, depth - 1 - reduction, -alpha - 1, -alpha, ply + 1);
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(board, depth - 1, -alpha - 1, -alpha, ply + 1);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(board, depth - 1, -beta, -alpha, ply + 1);
+                }
+                score
+            };
+            self.unmake_move(board);
+            if ply == 0 {
+                self.root_nodes[(mv.pack() & 0xfff) as usize] += self.nodes - nodes_before;
+            }
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(mv);
+                    self.update_pv(ply, mv);
+                    if score >= beta {
+                        if quiet {
+                            self.update_quiet_stats(mv, piece.map_or(0, |p| p.to_index()), depth, ply);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        if legal == 0 {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        // the score only holds for the chosen root moves, so keep it out of the table
+        if restricted {
+            return best_score;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(key, best_move.map_or(0, |mv: Move| mv.pack()), best_score, depth, bound, ply);
+        best_score
+    }
+
+    fn quiescence(&mut self, board: &mut Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.pv[ply].clear();
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(board);
+        }
+
+        let us = board.side_to_move;
+        let in_check = self.mg.in_check(board, us);
+        let mut best_score = -INFINITY;
+        if !in_check {
+            let stand_pat = self.evaluate(board);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
+        }
+
+        // every evasion when in check, otherwise only captures and promotions
+        let mut moves = self.mg.pseudo_legal_moves(board);
+        if !in_check {
+            moves.retain(|mv| mv.is_capture() || mv.promotion().is_some());
+        }
+        let moves = self.ordered_moves(board, moves, 0, ply);
+
+        let mut legal = 0;
+        for mv in moves {
+            if !in_check && see(self.mg, board, mv) < 0 {
+                continue;
+            }
+            self.make_move(board, mv);
+            if self.mg.in_check(board, us) {
+                self.unmake_move(board);
+                continue;
+            }
+            legal += 1;
+            let score = -self.quiescence(board, -beta, -alpha, ply + 1);
+            self.unmake_move(board);
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if in_check && legal == 0 {
+            return -MATE + ply as i32;
+        }
+        best_score
+    }
+
+    fn update_quiet_stats(&mut self, mv: Move, piece: usize, depth: i32, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+        let entry = &mut self.history[piece][mv.to().index()];
+        *entry += depth * depth;
+        if *entry > HISTORY_LIMIT {
+            for row in self.history.iter_mut() {
+                row.iter_mut().for_each(|h| *h /= 2);
+            }
+        }
+    }
+
+    // hash move, then captures by MVV-LVA, promotions, killers and quiet history
+    fn ordered_moves(&self, board: &Board, moves: Vec<Move>, tt_move: u16, ply: usize) -> Vec<Move> {
+        let mut scored: Vec<(Move, i32)> = moves.into_iter().map(|mv| {
+            let piece = board.pieces[mv.from().index()];
+            let score = if mv.pack() == tt_move {
+                1_000_000
+            } else if let Some(captured) = mv.captured() {
+                100_000 + 10 * captured.value() - piece.map_or(0, Piece::value)
+                    + mv.promotion().map_or(0, Piece::value)
+            } else if let Some(promotion) = mv.promotion() {
+                90_000 + promotion.value()
+            } else if self.killers[ply][0] == Some(mv) {
+                80_000
+            } else if self.killers[ply][1] == Some(mv) {
+                79_000
+            } else {
+                piece.map_or(0, |p| self.history[p.to_index()][mv.to().index()])
+            };
+            (mv, score)
+        }).collect();
+        scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+        scored.into_iter().map(|(mv, _)| mv).collect()
+    }
+}
+
+fn has_non_pawn_material(board: &Board, side: Color) -> bool {
+    let pawns_and_king = board.type_pieces(PieceType::Pawn) | board.type_pieces(PieceType::King);
+    !(board.side_pieces(Side(side)) & !pawns_and_king).is_empty()
+}
+
+// neither side can possibly mate: bare kings or a single minor piece
+pub fn insufficient_material(board: &Board) -> bool {
+    let heavy_or_pawns = board.type_pieces(PieceType::Pawn) | board.type_pieces(PieceType::Rook) | board.type_pieces(PieceType::Queen);
+    let minors = board.type_pieces(PieceType::Knight) | board.type_pieces(PieceType::Bishop);
+    heavy_or_pawns.is_empty() && !minors.more_than_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    fn best_move(fen: &str, limits: SearchLimits) -> SearchResult {
+        let mut board: Board = parse_fen(fen).into();
+        let mut searcher = Searcher::new(4);
+        searcher.search(&mut board, limits)
+    }
+
+    #[test]
+    fn test_finds_mate() {
+        let result = best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", SearchLimits::depth(4));
+        assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+        assert_eq!(format_score(result.score), "mate 1");
+
+        let result = best_move("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 0", SearchLimits::depth(5));
+        assert_eq!(result.best_move.unwrap().to_string(), "d5f6");
+        assert_eq!(format_score(result.score), "mate 2");
+    }
+
+    #[test]
+    fn test_wins_material() {
+        // knight fork of king and queen
+        let result = best_move("q3k3/8/8/1N6/8/8/P7/4K3 w - - 0 1", SearchLimits::depth(4));
+        assert_eq!(result.best_move.unwrap().to_string(), "b5c7");
+        assert!(result.score > 300);
+    }
+
+    #[test]
+    fn test_limits_and_draws() {
+        let fen = START_FEN;
+        let result = best_move(fen, SearchLimits::nodes(2000));
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 2100);
+
+        // stalemated side has no move
+        let result = best_move("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", SearchLimits::depth(2));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut searcher = Searcher::new(4);
+        searcher.set_threads(4);
+        assert_eq!(searcher.threads(), 4);
+        let mut board: Board = parse_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 0").into();
+        let result = searcher.search(&mut board, SearchLimits::depth(5));
+        assert_eq!(result.best_move.unwrap().to_string(), "d5f6");
+        assert_eq!(format_score(result.score), "mate 2");
+
+        // node limits count every thread's nodes
+        let mut board: Board = parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").into();
+        let result = searcher.search(&mut board, SearchLimits::nodes(20_000));
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 40_000, "{}", result.nodes);
+
+        // a stop raised beforehand ends every worker at once
+        let stop = searcher.stop_handle();
+        stop.store(true, Ordering::Relaxed);
+        let result = searcher.search(&mut board, SearchLimits::default());
+        stop.store(false, Ordering::Relaxed);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes < 1000);
+    }
+
+    #[test]
+    fn test_multipv() {
+        let fen = "q3k3/8/8/1N6/8/8/P7/4K3 w - - 0 1";
+        let single = best_move(fen, SearchLimits::depth(5));
+        let result = best_move(fen, SearchLimits::depth(5).multipv(4));
+        assert_eq!(result.lines.len(), 4);
+        assert_eq!(result.best_move, single.best_move);
+        assert_eq!((result.score, &result.pv), (result.lines[0].score, &result.lines[0].pv));
+        // distinct root moves, best first; only the fork wins the queen
+        for (index, line) in result.lines.iter().enumerate().skip(1) {
+            assert!(result.lines[..index].iter().all(|l| l.pv[0] != line.pv[0]));
+            assert!(line.score <= result.lines[index - 1].score);
+            assert!(line.score < 300);
+        }
+
+        // never more lines than legal root moves
+        let result = best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", SearchLimits::depth(3).multipv(100));
+        assert!(result.lines.len() < 100);
+        assert_eq!(format_score(result.score), "mate 1");
+    }
+
+    #[test]
+    fn test_vote() {
+        let result = |mv: &str, score, depth| {
+            let mut board: Board = parse_fen(START_FEN).into();
+            let mv = MoveGenerator::global().parse_move(&mut board, mv).unwrap();
+            SearchResult { best_move: Some(mv), score, depth, nodes: 0, pv: vec![mv], lines: vec![PvLine { score, pv: vec![mv] }] }
+        };
+        // two helpers outvote the main thread
+        let results = [result("e2e4", 20, 10), result("d2d4", 30, 10), result("d2d4", 25, 11)];
+        let chosen = vote(&results);
+        assert_eq!((chosen.best_move.unwrap().to_string(), chosen.depth), ("d2d4".to_string(), 11));
+        // a mate found by the main thread stands
+        let results = [result("e2e4", MATE - 3, 4), result("d2d4", 30, 10)];
+        assert_eq!(vote(&results).best_move.unwrap().to_string(), "e2e4");
+    }
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!(format_score(35), "cp 35");
+        assert_eq!(format_score(MATE - 1), "mate 1");
+        assert_eq!(format_score(MATE - 3), "mate 2");
+        assert_eq!(format_score(-MATE + 2), "mate -1");
+    }
+}
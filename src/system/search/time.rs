@@ -0,0 +1,236 @@
+//! Time management
+//!
+//! Turns the clock state of `go wtime/btime/winc/binc/movestogo` into a soft
+//! limit, checked between iterations, and a hard limit that interrupts the
+//! search. The soft limit stretches while the best move keeps changing, the
+//! score drops or the best move has not absorbed most of the effort, and
+//! shrinks when the search is settled.
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
+
+pub const DEFAULT_MOVE_OVERHEAD: Duration = Duration::from_millis(10);
+// moves still to play when the time control does not say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MIN_TIME: Duration = Duration::from_millis(1);
+
+/// Time source, so tests can control the passage of time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock(Instant);
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Default)]
+pub struct MockClock(AtomicU64);
+
+impl MockClock {
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        Duration::from_micros(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// The side to move's clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameClock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>,
+}
+
+#[derive(Clone)]
+pub struct TimeManager {
+    clock: Arc<dyn Clock>,
+    start: Duration,
+    soft: Duration,
+    hard: Duration,
+    best_move: Option<u16>,
+    // iterations in a row with the same best move
+    stability: u32,
+    previous_score: Option<i32>,
+}
+
+impl fmt::Debug for TimeManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeManager")
+            .field("soft", &self.soft)
+            .field("hard", &self.hard)
+            .field("elapsed", &self.elapsed())
+            .finish()
+    }
+}
+
+impl TimeManager {
+    pub fn new(game: GameClock, overhead: Duration) -> Self {
+        Self::with_clock(game, overhead, Arc::new(SystemClock::new()))
+    }
+
+    pub fn with_clock(game: GameClock, overhead: Duration, clock: Arc<dyn Clock>) -> Self {
+        // keep the overhead in reserve for communication and scheduling delays
+        let available = game.remaining.saturating_sub(overhead);
+        let moves_to_go = game.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, 50);
+        let soft = available / moves_to_go + game.increment * 3 / 4;
+        // the most one move may take, leaving something for the next ones
+        let cap = if moves_to_go == 1 { available * 4 / 5 } else { available / 2 };
+        let hard = (soft * 3).min(cap).max(MIN_TIME);
+        let soft = soft.min(hard).max(MIN_TIME);
+        let start = clock.now();
+        Self { clock, start, soft, hard, best_move: None, stability: 0, previous_score: None }
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard
+    }
+
+    /// Starts the limits over from now, keeping what was learned about the
+    /// best move (used on ponderhit).
+    pub fn restart(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.start)
+    }
+
+    // checked during the search
+    pub fn hard_limit_reached(&self) -> bool {
+        self.elapsed() >= self.hard
+    }
+
+    /// Called after each completed iteration with its best move (as
+    /// `Move::pack`), score and the share of the iteration's nodes spent on
+    /// the best move; returns true when no new iteration should start.
+    pub fn stop_iterating(&mut self, best_move: u16, score: i32, best_move_nodes: f64) -> bool {
+        if self.best_move == Some(best_move) {
+            self.stability = (self.stability + 1).min(8);
+        } else {
+            self.stability = 0;
+        }
+        self.best_move = Some(best_move);
+        let drop = self.previous_score.map_or(0, |previous| previous - score);
+        self.previous_score = Some(score);
+
+        self.elapsed() >= self.scaled_soft_limit(drop, best_move_nodes)
+    }
+
+    fn scaled_soft_limit(&self, drop: i32, best_move_nodes: f64) -> Duration {
+        let stability = 1.8 - 0.12 * self.stability as f64;
+        let falling = 1.0 + drop.clamp(-20, 100) as f64 / 100.0;
+        let effort = (1.5 - best_move_nodes.clamp(0.0, 1.0)) * 1.3;
+        self.soft.mul_f64(stability * falling * effort).min(self.hard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(remaining_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> (TimeManager, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::default());
+        let game = GameClock {
+            remaining: Duration::from_millis(remaining_ms),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+        };
+        (TimeManager::with_clock(game, DEFAULT_MOVE_OVERHEAD, clock.clone()), clock)
+    }
+
+    #[test]
+    fn test_limits() {
+        let (tm, _) = manager(60_010, 0, None);
+        assert_eq!(tm.soft_limit(), Duration::from_millis(2000));
+        assert_eq!(tm.hard_limit(), Duration::from_millis(6000));
+
+        let (tm, _) = manager(60_010, 1000, Some(10));
+        assert_eq!(tm.soft_limit(), Duration::from_millis(6750));
+        assert_eq!(tm.hard_limit(), Duration::from_millis(20_250));
+
+        // the last move before the control may use most of the clock
+        let (tm, _) = manager(10_010, 0, Some(1));
+        assert_eq!(tm.hard_limit(), Duration::from_millis(8000));
+
+        // a big increment never spends more than half the clock
+        let (tm, _) = manager(1010, 5000, None);
+        assert_eq!(tm.hard_limit(), Duration::from_millis(500));
+        assert!(tm.soft_limit() <= tm.hard_limit());
+
+        // less than the overhead left: move at once instead of flagging
+        let (tm, _) = manager(5, 0, None);
+        assert_eq!(tm.hard_limit(), MIN_TIME);
+    }
+
+    #[test]
+    fn test_hard_limit() {
+        let (tm, clock) = manager(60_010, 0, None);
+        clock.advance(Duration::from_millis(5999));
+        assert!(!tm.hard_limit_reached());
+        clock.advance(Duration::from_millis(1));
+        assert!(tm.hard_limit_reached());
+
+        // pondered time doesn't count once the opponent has moved
+        let (mut tm, clock) = manager(60_010, 0, None);
+        clock.advance(Duration::from_secs(30));
+        assert!(tm.hard_limit_reached());
+        tm.restart();
+        assert!(!tm.hard_limit_reached());
+        clock.advance(Duration::from_millis(6000));
+        assert!(tm.hard_limit_reached());
+    }
+
+    #[test]
+    fn test_soft_limit_scaling() {
+        // soft limit 2s: a stable best move that took all the effort stops early
+        let (mut tm, clock) = manager(60_010, 0, None);
+        for _ in 0..8 {
+            assert!(!tm.stop_iterating(1, 20, 0.9));
+        }
+        clock.advance(Duration::from_millis(1400));
+        assert!(tm.stop_iterating(1, 20, 0.9));
+
+        // a changing best move keeps searching at the same elapsed time
+        let (mut tm, clock) = manager(60_010, 0, None);
+        clock.advance(Duration::from_millis(1200));
+        assert!(!tm.stop_iterating(1, 20, 0.9));
+        assert!(!tm.stop_iterating(2, 20, 0.5));
+
+        // so does a falling score, up to the hard limit
+        let (mut tm, clock) = manager(60_010, 0, None);
+        for _ in 0..8 {
+            tm.stop_iterating(1, 50, 0.9);
+        }
+        clock.advance(Duration::from_millis(2500));
+        assert!(!tm.stop_iterating(1, -50, 0.9));
+        clock.advance(Duration::from_millis(3500));
+        assert!(tm.stop_iterating(2, -300, 0.1));
+    }
+}
@@ -0,0 +1,175 @@
+//! Transposition table
+//!
+//! Fixed size hash table of search results keyed by Zobrist key, shared by
+//! every search thread without locks. Each slot is two atomic words, the
+//! packed entry and the key XORed with it, so a slot torn by concurrent
+//! writes fails verification instead of returning a mixed entry.
+//! Mate scores are stored relative to the node, not the root
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+pub const MATE: i32 = 30_000;
+pub const MAX_PLY: usize = 128;
+// scores beyond this are mates
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // failed high: score is at least this
+    Lower,
+    // failed low: score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+    // `Move::pack` of the best move, 0 for none
+    pub best_move: u16,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+impl TtEntry {
+    // move (16 bits), score (16), depth (8) and bound (2); never 0
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        self.best_move as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.depth.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8 as u64) << 32
+            | bound << 40
+    }
+
+    fn unpack(data: u64) -> Option<Self> {
+        let bound = match (data >> 40) & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        Some(Self {
+            best_move: data as u16,
+            score: (data >> 16) as u16 as i16 as i32,
+            depth: (data >> 32) as u8 as i8 as i32,
+            bound,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    // key ^ data
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let count = (megabytes * 1024 * 1024 / size_of::<Slot>()).max(1024);
+        // round down to a power of two so the key can be masked
+        let count = 1usize << (usize::BITS - 1 - count.leading_zeros());
+        Self { slots: (0..count).map(|_| Slot::default()).collect(), mask: count - 1 }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn read(&self, key: u64) -> Option<TtEntry> {
+        let slot = &self.slots[key as usize & self.mask];
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.check.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        TtEntry::unpack(data)
+    }
+
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        let mut entry = self.read(key)?;
+        entry.score = score_from_tt(entry.score, ply);
+        Some(entry)
+    }
+
+    pub fn store(&self, key: u64, best_move: u16, score: i32, depth: i32, bound: Bound, ply: usize) {
+        let old = self.read(key);
+        // keep the old move if this result has none for the same position
+        let best_move = if best_move == 0 { old.map_or(0, |e| e.best_move) } else { best_move };
+        // prefer deeper results for the same position, always replace others
+        if old.is_some_and(|old| old.depth > depth && bound != Bound::Exact) {
+            return;
+        }
+        let data = TtEntry { best_move, score: score_to_tt(score, ply), depth, bound }.pack();
+        let slot = &self.slots[key as usize & self.mask];
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // permille of sampled slots in use, for UCI hashfull
+    pub fn hashfull(&self) -> usize {
+        let sample = self.slots.len().min(1000);
+        self.slots[..sample].iter().filter(|s| s.data.load(Ordering::Relaxed) != 0).count() * 1000 / sample
+    }
+}
+
+// mate scores count plies from the root; stored they count from the node
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score + ply as i32
+    } else if score < -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score - ply as i32
+    } else if score < -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_verify() {
+        let tt = TranspositionTable::new(1);
+        let key = 0x1234_5678_9abc_def0;
+        tt.store(key, 0x0c1c, -(MATE - 5), 7, Bound::Lower, 3);
+        let entry = tt.probe(key, 3).unwrap();
+        assert_eq!(entry, TtEntry { best_move: 0x0c1c, score: -(MATE - 5), depth: 7, bound: Bound::Lower });
+        // same slot, different key
+        assert_eq!(tt.probe(key ^ 1 << 63, 3), None);
+
+        // a shallower non-exact result keeps the entry, but not its move
+        tt.store(key, 0, 100, 2, Bound::Upper, 0);
+        assert_eq!(tt.probe(key, 0).unwrap().depth, 7);
+        tt.store(key, 0, 100, 2, Bound::Exact, 0);
+        assert_eq!(tt.probe(key, 0).unwrap(), TtEntry { best_move: 0x0c1c, score: 100, depth: 2, bound: Bound::Exact });
+
+        // a torn write fails the check
+        let slot = &tt.slots[key as usize & tt.mask];
+        slot.data.store(TtEntry { best_move: 1, score: 5, depth: 1, bound: Bound::Exact }.pack(), Ordering::Relaxed);
+        assert_eq!(tt.probe(key, 0), None);
+
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+    }
+}
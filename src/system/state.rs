@@ -2,7 +2,8 @@
 //! 
 //! Maintains game phase, evaluation metric, and move history
 //! Separate from board representation for evaluation
-use super::{ parse_fen, Zobrist, CastlingRights, Square, Piece, BitBoard };
+use std::fmt;
+use super::{ game_phase, parse_fen, tapered_psqt, Zobrist, CastlingRights, Square, Piece, BitBoard };
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct State {
@@ -23,12 +24,78 @@ pub enum GamePhase {
     EndGame,
 }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum Move {
     Normal { from: Square, to: Square },
     Capture { from: Square, to: Square, captured: Piece },
     Promotion { from: Square, to: Square, piece: Piece },
     CapturePromotion { from: Square, to: Square, captured: Piece, promotion: Piece },
+    // king's from/to squares; the rook is moved alongside it
+    Castle { from: Square, to: Square },
+    // captured pawn sits behind the target square
+    EnPassant { from: Square, to: Square },
+}
+
+impl Move {
+    pub fn from(&self) -> Square {
+        match *self {
+            Move::Normal { from, .. }
+            | Move::Capture { from, .. }
+            | Move::Promotion { from, .. }
+            | Move::CapturePromotion { from, .. }
+            | Move::Castle { from, .. }
+            | Move::EnPassant { from, .. } => from,
+        }
+    }
+
+    pub fn to(&self) -> Square {
+        match *self {
+            Move::Normal { to, .. }
+            | Move::Capture { to, .. }
+            | Move::Promotion { to, .. }
+            | Move::CapturePromotion { to, .. }
+            | Move::Castle { to, .. }
+            | Move::EnPassant { to, .. } => to,
+        }
+    }
+
+    pub fn captured(&self) -> Option<Piece> {
+        match *self {
+            Move::Capture { captured, .. } | Move::CapturePromotion { captured, .. } => Some(captured),
+            Move::EnPassant { from, .. } => Some(if from.0 < 32 { Piece::WhitePawn } else { Piece::BlackPawn }),
+            _ => None,
+        }
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        match *self {
+            Move::Promotion { piece, .. } => Some(piece),
+            Move::CapturePromotion { promotion, .. } => Some(promotion),
+            _ => None,
+        }
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.captured().is_some()
+    }
+
+    // from, to and promotion piece type in 16 bits; never 0 for a real move
+    pub fn pack(&self) -> u16 {
+        let promotion = self.promotion().map_or(0, |piece| piece.kind().index());
+        self.from().0 as u16 | (self.to().0 as u16) << 6 | (promotion as u16) << 12
+    }
+}
+
+// long algebraic (UCI) notation, e.g. e2e4 or e7e8q
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (from, to) = (self.from(), self.to());
+        write!(f, "{}{}", from, to)?;
+        if let Some(piece) = self.promotion() {
+            write!(f, "{}", piece.kind().to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
 }
 
 impl State {
@@ -47,16 +114,20 @@ impl State {
         }
     }
 
-    pub fn make_move(&mut self, mv: Move, zobrist: &Zobrist) {
+    pub fn make_move(&mut self, mv: Move, _zobrist: &Zobrist) {
         self.last_move = Some(mv);
         //self.zobrist_key ^= zobrist.hash(&self.into());
     }
 
     pub fn evaluate_phase(&self, material: &[BitBoard; 12]) -> GamePhase {
-        todo!()
+        match game_phase(material) {
+            phase if phase >= 20 => GamePhase::Opening,
+            phase if phase >= 8 => GamePhase::MiddleGame,
+            _ => GamePhase::EndGame,
+        }
     }
 
     pub fn calculate_psqt(&self, piece_boards: &[BitBoard; 12]) -> i32 {
-        todo!()
+        tapered_psqt(piece_boards)
     }
 }
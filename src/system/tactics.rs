@@ -0,0 +1,390 @@
+//! Tactical motif detection
+//!
+//! Tags a solution line with the tactics it contains, using the move
+//! generator's attack tables and static exchange evaluation
+use std::collections::BTreeSet;
+use std::fmt;
+use super::{ see, BitBoard, Board, Color, Move, MoveGenerator, Piece, PieceType, Rank, Side, Square };
+
+// a king outranks everything it can be lined up with
+const KING_VALUE: i32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Motif {
+    Fork,
+    AbsolutePin,
+    RelativePin,
+    Skewer,
+    DiscoveredAttack,
+    DiscoveredCheck,
+    DoubleCheck,
+    BackRankMate,
+    Deflection,
+    RemovalOfDefender,
+    OverloadedPiece,
+}
+
+impl Motif {
+    pub const ALL: [Motif; 11] = [
+        Motif::Fork,
+        Motif::AbsolutePin,
+        Motif::RelativePin,
+        Motif::Skewer,
+        Motif::DiscoveredAttack,
+        Motif::DiscoveredCheck,
+        Motif::DoubleCheck,
+        Motif::BackRankMate,
+        Motif::Deflection,
+        Motif::RemovalOfDefender,
+        Motif::OverloadedPiece,
+    ];
+
+    // lichess puzzle theme; lichess has no tag for overloading, so that one
+    // is only reported by the classifier itself
+    pub fn theme(self) -> Option<&'static str> {
+        match self {
+            Motif::Fork => Some("fork"),
+            Motif::AbsolutePin | Motif::RelativePin => Some("pin"),
+            Motif::Skewer => Some("skewer"),
+            Motif::DiscoveredAttack | Motif::DiscoveredCheck => Some("discoveredAttack"),
+            Motif::DoubleCheck => Some("doubleCheck"),
+            Motif::BackRankMate => Some("backRankMate"),
+            Motif::Deflection => Some("deflection"),
+            Motif::RemovalOfDefender => Some("capturingDefender"),
+            Motif::OverloadedPiece => None,
+        }
+    }
+}
+
+impl fmt::Display for Motif {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Motif::Fork => "fork",
+            Motif::AbsolutePin => "absolute pin",
+            Motif::RelativePin => "relative pin",
+            Motif::Skewer => "skewer",
+            Motif::DiscoveredAttack => "discovered attack",
+            Motif::DiscoveredCheck => "discovered check",
+            Motif::DoubleCheck => "double check",
+            Motif::BackRankMate => "back rank mate",
+            Motif::Deflection => "deflection",
+            Motif::RemovalOfDefender => "removal of the defender",
+            Motif::OverloadedPiece => "overloaded piece",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Motifs found in `line`, played from `board`. Moves at even indices belong
+/// to the side solving the puzzle, odd ones are the opponent's replies.
+pub fn classify(board: &Board, line: &[Move]) -> Vec<Motif> {
+    let mg = MoveGenerator::global();
+    let mut board = board.clone();
+    let mut motifs = BTreeSet::new();
+
+    for (i, &mv) in line.iter().enumerate() {
+        if i % 2 == 0 {
+            let mut after = board.clone();
+            after.make_move(mv);
+
+            forks(mg, &mut after, mv, &mut motifs);
+            pins(mg, &mut after, mv, &mut motifs);
+            discoveries(mg, &board, &mut after, mv, &mut motifs);
+            back_rank_mate(mg, &mut after, &mut motifs);
+            if let (Some(&reply), Some(&next)) = (line.get(i + 1), line.get(i + 2)) {
+                deflections(mg, &mut after, mv, reply, next, &mut motifs);
+                removals(mg, &board, mv, next, &mut motifs);
+            }
+        }
+        board.make_move(mv);
+    }
+    motifs.into_iter().collect()
+}
+
+fn value(piece: Piece) -> i32 {
+    match piece.kind() {
+        PieceType::King => KING_VALUE,
+        _ => piece.value(),
+    }
+}
+
+fn is_king(piece: Piece) -> bool {
+    piece.kind() == PieceType::King
+}
+
+fn is_slider(piece: Piece) -> bool {
+    piece.kind().is_slider()
+}
+
+fn capture(board: &Board, from: Square, to: Square) -> Option<Move> {
+    board.piece_at(to).map(|captured| Move::Capture { from, to, captured })
+}
+
+// would the piece on `from` win material taking on `to`, were it the mover's turn
+fn wins_material(mg: &MoveGenerator, board: &mut Board, from: Square, to: Square) -> bool {
+    let Some(mv) = capture(board, from, to) else { return false };
+    if mv.captured().is_some_and(is_king) {
+        return true;
+    }
+
+    let owner = board.piece_at(from).map(Piece::color);
+    if owner == Some(board.get_side_to_move()) {
+        return see(mg, board, mv) > 0;
+    }
+    board.make_null_move();
+    let gain = see(mg, board, mv);
+    board.unmake_null_move();
+    gain > 0
+}
+
+// can the side to move win material by taking on sq
+fn en_prise(mg: &MoveGenerator, board: &Board, sq: Square) -> bool {
+    let attackers = mg.attackers_to(board, sq, board.occupied()) & board.side_pieces(Side(board.get_side_to_move()));
+    attackers.squares().any(|from| capture(board, from, sq).is_some_and(|mv| see(mg, board, mv) > 0))
+}
+
+// the moved piece attacks two things it could win, and cannot simply be taken
+fn forks(mg: &MoveGenerator, after: &mut Board, mv: Move, motifs: &mut BTreeSet<Motif>) {
+    let to = mv.to();
+    let Some(piece) = after.piece_at(to) else { return };
+    let them = after.get_side_to_move();
+    let occ = after.occupied();
+
+    let attacked = mg.piece_attacks(piece, to, occ) & after.side_pieces(Side(them));
+    let targets = attacked
+        .squares()
+        .filter(|&sq| wins_material(mg, after, to, sq))
+        .count();
+    if targets < 2 {
+        return;
+    }
+
+    if !en_prise(mg, after, to) {
+        motifs.insert(Motif::Fork);
+    }
+}
+
+// a safe slider of ours lined up on two enemy pieces, where the move set it up or hits the front one
+fn pins(mg: &MoveGenerator, after: &mut Board, mv: Move, motifs: &mut BTreeSet<Motif>) {
+    let them = after.get_side_to_move();
+    let us = !them;
+    let occ = after.occupied();
+    let theirs = after.side_pieces(Side(them));
+    let moved = after.piece_at(mv.to());
+    let moved_attacks = moved.map_or(BitBoard::EMPTY, |p| mg.piece_attacks(p, mv.to(), occ));
+
+    for slider_sq in after.side_pieces(Side(us)) {
+        let Some(slider) = after.piece_at(slider_sq).filter(|&p| is_slider(p)) else { continue };
+        if en_prise(mg, after, slider_sq) {
+            continue;
+        }
+        let attacks = mg.piece_attacks(slider, slider_sq, occ);
+
+        for front_sq in attacks & theirs {
+            let relevant = slider_sq == mv.to() || moved_attacks.contains(front_sq);
+            if !relevant {
+                continue;
+            }
+
+            let xray = mg.piece_attacks(slider, slider_sq, occ & !BitBoard::from(front_sq)) & !attacks;
+            let Some(back_sq) = (xray & theirs).squares().next() else { continue };
+            let (Some(front), Some(back)) = (after.piece_at(front_sq), after.piece_at(back_sq)) else { continue };
+
+            if is_king(back) {
+                motifs.insert(Motif::AbsolutePin);
+            } else if value(back) > value(front) && value(back) >= PieceType::Rook.value() {
+                motifs.insert(Motif::RelativePin);
+            } else if value(front) > value(back) && value(back) > PieceType::Pawn.value() {
+                motifs.insert(Motif::Skewer);
+            }
+        }
+    }
+}
+
+// moving a piece out of a slider's way opens an attack on the king or on something worth winning
+fn discoveries(mg: &MoveGenerator, before: &Board, after: &mut Board, mv: Move, motifs: &mut BTreeSet<Motif>) {
+    let them = after.get_side_to_move();
+    let us = !them;
+    let (occ_before, occ_after) = (before.occupied(), after.occupied());
+    let theirs = after.side_pieces(Side(them));
+
+    for slider_sq in after.side_pieces(Side(us)) {
+        if slider_sq == mv.to() {
+            continue;
+        }
+        let Some(slider) = after.piece_at(slider_sq).filter(|&p| is_slider(p)) else { continue };
+        let revealed = mg.piece_attacks(slider, slider_sq, occ_after)
+            & !mg.piece_attacks(slider, slider_sq, occ_before)
+            & theirs;
+
+        for target in revealed {
+            if after.piece_at(target).is_some_and(is_king) {
+                motifs.insert(Motif::DiscoveredCheck);
+            } else if wins_material(mg, after, slider_sq, target) {
+                motifs.insert(Motif::DiscoveredAttack);
+            }
+        }
+    }
+
+    let king = after.king_square(them);
+    let checkers = mg.attackers_to(after, king, occ_after) & after.side_pieces(Side(us));
+    if checkers.more_than_one() {
+        motifs.insert(Motif::DoubleCheck);
+    }
+}
+
+// mate by a rook or queen along the back rank, the king walled in by its own pieces
+fn back_rank_mate(mg: &MoveGenerator, after: &mut Board, motifs: &mut BTreeSet<Motif>) {
+    let them = after.get_side_to_move();
+    if !mg.in_check(after, them) || !mg.legal_moves(after).is_empty() {
+        return;
+    }
+
+    let king = after.king_square(them);
+    let (back_rank, forward) = match them {
+        Color::White => (Rank::First, Rank::Second),
+        Color::Black => (Rank::Eighth, Rank::Seventh),
+    };
+    if king.rank() != back_rank {
+        return;
+    }
+
+    let checkers = mg.attackers_to(after, king, after.occupied()) & after.side_pieces(Side(!them));
+    let along_rank = checkers.squares().all(|sq| {
+        sq.rank() == back_rank
+            && after.piece_at(sq).is_some_and(|p| matches!(p.kind(), PieceType::Rook | PieceType::Queen))
+    });
+
+    let escape = mg.king_attacks(king) & forward.mask();
+    let walled_in = (escape & !after.side_pieces(Side(them))).is_empty();
+    if along_rank && walled_in {
+        motifs.insert(Motif::BackRankMate);
+    }
+}
+
+// the reply pulls a piece off a square it guarded, and our next move lands there
+fn deflections(mg: &MoveGenerator, after: &mut Board, mv: Move, reply: Move, next: Move, motifs: &mut BTreeSet<Motif>) {
+    let Some(defender) = after.piece_at(reply.from()) else { return };
+    let target = next.to();
+    if reply.to() == target {
+        return;
+    }
+
+    let guarded_before = mg.piece_attacks(defender, reply.from(), after.occupied()).contains(target);
+    let forcing = mv.is_capture() || mg.in_check(after, after.get_side_to_move());
+    if !guarded_before || !forcing {
+        return;
+    }
+
+    after.make_move(reply);
+    let guarded_after = mg.piece_attacks(defender, reply.to(), after.occupied()).contains(target);
+    let exploited = next.is_capture() || mg.gives_check(after, next);
+    after.unmake_move();
+
+    if guarded_after || !exploited {
+        return;
+    }
+    // it had to recapture on one of the squares it guarded, so it could not hold both
+    if mv.is_capture() && reply.to() == mv.to() {
+        motifs.insert(Motif::OverloadedPiece);
+    } else {
+        motifs.insert(Motif::Deflection);
+    }
+}
+
+// we capture a piece, then win something it was defending
+fn removals(mg: &MoveGenerator, before: &Board, mv: Move, next: Move, motifs: &mut BTreeSet<Motif>) {
+    let Some(defender) = mv.captured() else { return };
+    let target = next.to();
+    if !next.is_capture() || target == mv.to() {
+        return;
+    }
+
+    let defended = mg.piece_attacks(defender, mv.to(), before.occupied()).contains(target);
+    let same_side = before.side_pieces(Side(!before.get_side_to_move())).contains(target);
+    if defended && same_side {
+        motifs.insert(Motif::RemovalOfDefender);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{ parse_fen, START_FEN };
+
+    const FIXTURE: &str = include_str!("../../data/puzzles.csv");
+
+    // position after the setup move, and the solution from there
+    fn puzzle(fen: &str, moves: &str) -> (Board, Vec<Move>) {
+        let mg = MoveGenerator::global();
+        let mut board: Board = parse_fen(fen).into();
+        let mut texts = moves.split_whitespace();
+        let setup = mg.parse_move(&mut board, texts.next().unwrap()).unwrap();
+        board.make_move(setup);
+
+        let start = board.clone();
+        let line = texts.map(|text| {
+            let mv = mg.parse_move(&mut board, text).unwrap();
+            board.make_move(mv);
+            mv
+        }).collect();
+        (start, line)
+    }
+
+    fn classify_fixture(id: &str) -> (Vec<Motif>, Vec<String>) {
+        let line = FIXTURE.lines().find(|l| l.starts_with(id)).unwrap();
+        let fields: Vec<&str> = line.split(',').collect();
+        let (board, moves) = puzzle(fields[1], fields[2]);
+        (classify(&board, &moves), fields[7].split_whitespace().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_fixture_themes() {
+        let tactical: BTreeSet<&str> = Motif::ALL.iter().filter_map(|m| m.theme()).collect();
+        for line in FIXTURE.lines().skip(1) {
+            let id = line.split(',').next().unwrap();
+            let (motifs, themes) = classify_fixture(id);
+            for theme in themes.iter().filter(|t| tactical.contains(t.as_str())) {
+                assert!(
+                    motifs.iter().any(|m| m.theme() == Some(theme.as_str())),
+                    "{}: expected {} in {:?}",
+                    id,
+                    theme,
+                    motifs,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixture_motifs() {
+        // everything the classifier finds, not only the themes lichess lists
+        let expected: [(&str, &[Motif]); 15] = [
+            ("rr001", &[Motif::BackRankMate]),
+            ("rr002", &[]),
+            ("rr003", &[]),
+            ("rr004", &[Motif::DiscoveredCheck, Motif::DoubleCheck]),
+            ("rr005", &[Motif::BackRankMate]),
+            ("rr006", &[Motif::Fork]),
+            ("rr007", &[Motif::Skewer]),
+            ("rr008", &[Motif::AbsolutePin]),
+            ("rr009", &[Motif::DiscoveredAttack]),
+            ("rr010", &[Motif::Deflection]),
+            ("rr011", &[Motif::DiscoveredAttack]),
+            ("rr012", &[Motif::RemovalOfDefender]),
+            ("rr013", &[Motif::Fork, Motif::Deflection]),
+            ("rr014", &[Motif::BackRankMate, Motif::OverloadedPiece]),
+            ("rr015", &[Motif::RelativePin]),
+        ];
+        assert_eq!(FIXTURE.lines().skip(1).count(), expected.len());
+        for (id, motifs) in expected {
+            assert_eq!(classify_fixture(id).0, motifs, "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_quiet_line() {
+        let (board, moves) = puzzle(START_FEN, "e2e4 e7e5 g1f3 b8c6");
+        assert!(classify(&board, &moves).is_empty());
+    }
+}
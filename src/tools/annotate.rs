@@ -0,0 +1,300 @@
+//! `annotate` subcommand
+//!
+//! Analyses every main line move of the games in a PGN file at a fixed node
+//! budget. Each move gets an `[%eval]`, moves that throw away winning chances
+//! are marked `?!`, `?` or `??` with the engine's line as a variation, and
+//! per-player accuracy and average centipawn loss go into the headers.
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, BufReader, Write };
+use crate::system::{ Board, Color, Evaluation, Game, Move, MoveGenerator, NodeId, PgnReader, SearchLimits, Searcher, DEFAULT_HASH_MB, MATE, MATE_BOUND };
+
+const USAGE: &str = "usage: rush-rs annotate <file.pgn> [--out <file.pgn>] [--nodes <n>]";
+const DEFAULT_NODES: u64 = 100_000;
+
+// scores beyond this count as won, for both win chances and centipawn loss
+const CP_CAP: i32 = 1000;
+// longest engine line added as a variation, in plies
+const MAX_VARIATION: usize = 10;
+
+/// Chance of winning for the side with `cp`, as used by Lichess.
+pub fn win_probability(cp: i32) -> f64 {
+    1.0 / (1.0 + (-0.00368208 * cp.clamp(-CP_CAP, CP_CAP) as f64).exp())
+}
+
+/// Accuracy of a single move from the win chances before and after it, 0 to 100.
+pub fn move_accuracy(before: f64, after: f64) -> f64 {
+    let drop = (before - after).max(0.0) * 100.0;
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+// side-to-move score as centipawns, with mates pushed out to the cap
+fn capped_cp(score: i32) -> i32 {
+    match score {
+        s if s > MATE_BOUND => CP_CAP,
+        s if s < -MATE_BOUND => -CP_CAP,
+        s => s.clamp(-CP_CAP, CP_CAP),
+    }
+}
+
+// side-to-move score as a PGN evaluation from white's point of view
+fn evaluation(score: i32, side: Color) -> Evaluation {
+    let white = if side == Color::White { score } else { -score };
+    if white.abs() > MATE_BOUND {
+        let moves = (MATE - white.abs() + 1) / 2;
+        Evaluation::Mate(if white > 0 { moves } else { -moves })
+    } else {
+        Evaluation::Centipawns(white)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// Judgement for a drop in win chances, thresholds as on Lichess.
+    pub fn from_drop(drop: f64) -> Option<Self> {
+        match drop {
+            d if d >= 0.15 => Some(Judgement::Blunder),
+            d if d >= 0.10 => Some(Judgement::Mistake),
+            d if d >= 0.05 => Some(Judgement::Inaccuracy),
+            _ => None,
+        }
+    }
+
+    pub fn nag(self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+}
+
+impl fmt::Display for Judgement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayerStats {
+    pub moves: u32,
+    pub accuracy_sum: f64,
+    pub loss_sum: u32,
+    pub inaccuracies: u32,
+    pub mistakes: u32,
+    pub blunders: u32,
+}
+
+impl PlayerStats {
+    pub fn accuracy(&self) -> f64 {
+        if self.moves == 0 { 100.0 } else { self.accuracy_sum / self.moves as f64 }
+    }
+
+    // average centipawn loss
+    pub fn acpl(&self) -> u32 {
+        if self.moves == 0 { 0 } else { (self.loss_sum as f64 / self.moves as f64).round() as u32 }
+    }
+
+    fn add(&mut self, accuracy: f64, loss: i32, judgement: Option<Judgement>) {
+        self.moves += 1;
+        self.accuracy_sum += accuracy;
+        self.loss_sum += loss.max(0) as u32;
+        match judgement {
+            Some(Judgement::Inaccuracy) => self.inaccuracies += 1,
+            Some(Judgement::Mistake) => self.mistakes += 1,
+            Some(Judgement::Blunder) => self.blunders += 1,
+            None => {},
+        }
+    }
+}
+
+pub struct Annotator {
+    mg: &'static MoveGenerator,
+    searcher: Searcher,
+    nodes: u64,
+}
+
+impl Annotator {
+    pub fn new(nodes: u64) -> Self {
+        Self { mg: MoveGenerator::global(), searcher: Searcher::new(DEFAULT_HASH_MB), nodes }
+    }
+
+    /// Annotates the main line of `game` in place and returns the white and
+    /// black statistics, which are also written to the headers.
+    pub fn annotate(&mut self, game: &mut Game) -> [PlayerStats; 2] {
+        self.searcher.clear();
+        let mainline: Vec<NodeId> = game.mainline().collect();
+        let mut stats = [PlayerStats::default(); 2];
+        let mut board = game.start.clone();
+        let mut analysis = self.searcher.search(&mut board, SearchLimits::nodes(self.nodes));
+        let mut parent = Game::ROOT;
+
+        for id in mainline {
+            let mv = game.node(id).mv.expect("main line nodes hold moves");
+            let mover = board.side_to_move;
+            let before = analysis.score;
+            let best = analysis.best_move;
+            let best_line = analysis.pv.clone();
+            let best_san = best.map(|best| self.mg.to_san(&mut board, best));
+
+            board.make_move(mv);
+            analysis = self.searcher.search(&mut board, SearchLimits::nodes(self.nodes));
+            let after = -analysis.score;
+
+            let (win_before, win_after) = (win_probability(capped_cp(before)), win_probability(capped_cp(after)));
+            // the engine's own choice is never marked, whatever the search noise says
+            let judgement = Judgement::from_drop(win_before - win_after).filter(|_| best != Some(mv));
+            let loss = capped_cp(before) - capped_cp(after);
+            stats[mover as usize].add(move_accuracy(win_before, win_after), loss, judgement);
+
+            let node = game.node_mut(id);
+            node.eval = Some(evaluation(analysis.score, board.side_to_move));
+            node.eval_depth = Some(analysis.depth);
+            if let (Some(judgement), Some(best_san)) = (judgement, best_san) {
+                node.nags.retain(|nag| !(1..=6).contains(nag));
+                node.nags.push(judgement.nag());
+                let note = format!("{}. {} was best.", judgement, best_san);
+                node.comment = Some(match node.comment.take() {
+                    Some(comment) => format!("{} {}", comment, note),
+                    None => note,
+                });
+                self.add_variation(game, parent, &board, &best_line);
+            }
+            parent = id;
+        }
+
+        for (color, player) in [(Color::White, "White"), (Color::Black, "Black")] {
+            let player_stats = &stats[color as usize];
+            game.set_tag(&format!("{}Accuracy", player), &format!("{:.1}", player_stats.accuracy()));
+            game.set_tag(&format!("{}ACPL", player), &player_stats.acpl().to_string());
+        }
+        game.set_tag("Annotator", "rush-rs");
+        stats
+    }
+
+    // engine line from the position before the move just played on `after`
+    fn add_variation(&self, game: &mut Game, parent: NodeId, after: &Board, line: &[Move]) {
+        let mut board = after.clone();
+        board.unmake_move();
+        let mut node = parent;
+        for &mv in line.iter().take(MAX_VARIATION) {
+            board.make_move(mv);
+            node = game.add_move(node, mv, board.zobrist_key);
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut out = None;
+    let mut nodes = DEFAULT_NODES;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--out" => out = Some(value()?.clone()),
+            "--nodes" => nodes = value()?.parse().map_err(|_| USAGE)?,
+            flag if flag.starts_with("--") => return Err(USAGE.to_string()),
+            file if path.is_none() => path = Some(file.to_string()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let file = File::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut writer: Box<dyn Write> = match &out {
+        Some(out) => Box::new(File::create(out).map_err(|e| format!("{}: {}", out, e))?),
+        None => Box::new(io::stdout()),
+    };
+
+    let mut annotator = Annotator::new(nodes);
+    for game in PgnReader::new(BufReader::new(file)) {
+        let mut game = match game {
+            Ok(game) => game,
+            Err(e) => {
+                eprintln!("{}: {}, skipping game", path, e);
+                continue;
+            },
+        };
+        let [white, black] = annotator.annotate(&mut game);
+        write!(writer, "{}", game).map_err(|e| e.to_string())?;
+        eprintln!(
+            "{} - {}: accuracy {:.1} / {:.1}, acpl {} / {}, blunders {} / {}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            white.accuracy(),
+            black.accuracy(),
+            white.acpl(),
+            black.acpl(),
+            white.blunders,
+            black.blunders,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::parse_game;
+
+    #[test]
+    fn test_win_probability() {
+        assert!((win_probability(0) - 0.5).abs() < 1e-9);
+        assert!((win_probability(200) + win_probability(-200) - 1.0).abs() < 1e-9);
+        assert_eq!(win_probability(5000), win_probability(CP_CAP));
+        assert!((move_accuracy(0.6, 0.6) - 100.0).abs() < 0.01);
+        assert!(move_accuracy(0.9, 0.1) < 5.0);
+
+        assert_eq!(Judgement::from_drop(0.04), None);
+        assert_eq!(Judgement::from_drop(0.07), Some(Judgement::Inaccuracy));
+        assert_eq!(Judgement::from_drop(0.12), Some(Judgement::Mistake));
+        assert_eq!(Judgement::from_drop(0.5), Some(Judgement::Blunder));
+    }
+
+    #[test]
+    fn test_evaluation() {
+        assert_eq!(evaluation(35, Color::White), Evaluation::Centipawns(35));
+        assert_eq!(evaluation(35, Color::Black), Evaluation::Centipawns(-35));
+        assert_eq!(evaluation(MATE - 3, Color::Black), Evaluation::Mate(-2));
+        assert_eq!(evaluation(-MATE, Color::Black), Evaluation::Mate(0));
+    }
+
+    #[test]
+    fn test_marks_blunder() {
+        let mut game = parse_game("[White \"A\"]\n[Black \"B\"]\n\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n", 1).unwrap();
+        let [white, black] = Annotator::new(20_000).annotate(&mut game);
+        let mainline: Vec<NodeId> = game.mainline().collect();
+
+        assert!(mainline.iter().all(|&id| game.node(id).eval.is_some() && game.node(id).eval_depth.is_some()));
+        let nf6 = game.node(mainline[5]);
+        assert_eq!(nf6.nags, vec![4]);
+        assert!(nf6.comment.as_deref().unwrap().starts_with("Blunder."));
+        assert_eq!(nf6.eval, Some(Evaluation::Mate(1)));
+
+        // the engine's defence hangs off the move before the blunder
+        assert_eq!(game.node(mainline[4]).children.len(), 2);
+        assert_eq!(black.blunders, 1);
+        assert_eq!(white.blunders, 0);
+        assert!(white.accuracy() > black.accuracy());
+        assert!(black.acpl() > white.acpl());
+        assert_eq!(game.tag("BlackACPL"), Some(black.acpl().to_string().as_str()));
+
+        // the annotated game still reads back
+        let written = game.to_pgn();
+        assert!(written.contains("[%eval #1,"));
+        assert_eq!(parse_game(&written, 1).unwrap().nodes.len(), game.nodes.len());
+    }
+}
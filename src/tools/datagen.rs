@@ -0,0 +1,310 @@
+//! `datagen` subcommand
+//!
+//! Generates training data for Texel tuning and NNUE by self-play. Each game
+//! starts from a few random legal plies and is played at a fixed node count;
+//! quiet positions are kept with the search score and labelled with the game
+//! result once it is known. Games are spread over threads, and samples are
+//! written as packed 32-byte records and/or `fen | score | result` lines.
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+use crate::system::{ game_over, parse_fen, BitBoard, Board, CastlingRights, Color, LcgRng, MoveGenerator, ParsedFen, SearchLimits, Searcher, Square, MATE_BOUND, START_FEN };
+use super::engine_match::{ Adjudication, Adjudicator };
+
+const USAGE: &str = "usage: rush-rs datagen [--games <n>] [--threads <n>] [--nodes <n>] [--random-plies <n>] [--seed <n>] [--bin <file>] [--text <file>]";
+pub const RECORD_LEN: usize = 32;
+const HASH_MB: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagenConfig {
+    pub games: usize,
+    pub threads: usize,
+    pub nodes: u64,
+    pub random_plies: usize,
+    pub seed: u64,
+}
+
+impl Default for DatagenConfig {
+    fn default() -> Self {
+        Self { games: 1000, threads: 1, nodes: 5000, random_plies: 8, seed: 1 }
+    }
+}
+
+/// A labelled position: search score and game result, both from white's
+/// point of view.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub board: Board,
+    pub score: i16,
+    // 1.0 white win, 0.5 draw, 0.0 black win
+    pub result: f64,
+}
+
+impl Sample {
+    pub fn to_text(&self) -> String {
+        format!("{} | {} | {:.1}", self.board.to_fen(), self.score, self.result)
+    }
+
+    /// Packs the sample into 32 bytes: occupancy (u64), one nibble per
+    /// occupied square in square order (16 bytes), side to move and castling
+    /// rights, en passant square (255 for none), half-move clock, full-move
+    /// number (u16), score (i16) and result (0, 1 or 2).
+    pub fn pack(&self) -> [u8; RECORD_LEN] {
+        let board = &self.board;
+        let mut record = [0u8; RECORD_LEN];
+        let occupied = board.occupied();
+        record[..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+        for (i, square) in occupied.squares().enumerate() {
+            let piece = board.pieces[square.index()].map_or(0, |p| p.to_index() as u8);
+            record[8 + i / 2] |= piece << (4 * (i % 2));
+        }
+
+        record[24] = (board.side_to_move == Color::Black) as u8 | board.castling_rights.0 << 1;
+        record[25] = board.en_passant.map_or(255, |sq| sq.0);
+        record[26] = board.half_move;
+        record[27..29].copy_from_slice(&board.full_move.to_le_bytes());
+        record[29..31].copy_from_slice(&self.score.to_le_bytes());
+        record[31] = (self.result * 2.0).round() as u8;
+        record
+    }
+
+    pub fn unpack(record: &[u8; RECORD_LEN]) -> Result<Self, String> {
+        let mut piece_boards = [BitBoard::EMPTY; 12];
+        let occupied = BitBoard(u64::from_le_bytes(record[..8].try_into().unwrap()));
+        if occupied.pop_count() > 32 {
+            return Err("more than 32 pieces".to_string());
+        }
+        for (i, square) in occupied.squares().enumerate() {
+            let piece = (record[8 + i / 2] >> (4 * (i % 2))) & 0xF;
+            if piece >= 12 {
+                return Err(format!("invalid piece {}", piece));
+            }
+            piece_boards[piece as usize].set_bit(square.index());
+        }
+        if record[31] > 2 {
+            return Err(format!("invalid result {}", record[31]));
+        }
+
+        let fen = ParsedFen {
+            piece_boards,
+            side_to_move: if record[24] & 1 == 1 { Color::Black } else { Color::White },
+            castling_rights: CastlingRights(record[24] >> 1),
+            en_passant: (record[25] < 64).then_some(Square(record[25])),
+            half_move: record[26],
+            full_move: u16::from_le_bytes([record[27], record[28]]),
+        };
+        let board = Board::from(fen);
+        if let Err(errors) = board.validate() {
+            let reasons: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(format!("Illegal position: {}", reasons.join(", ")));
+        }
+        Ok(Self {
+            board,
+            score: i16::from_le_bytes([record[29], record[30]]),
+            result: record[31] as f64 / 2.0,
+        })
+    }
+}
+
+// plays `plies` random legal moves; None if the game ended on the way
+fn random_opening(rng: &mut LcgRng, plies: usize) -> Option<Board> {
+    let mg = MoveGenerator::global();
+    let mut board: Board = parse_fen(START_FEN).into();
+    for _ in 0..plies {
+        let moves = mg.legal_moves(&mut board);
+        if moves.is_empty() {
+            return None;
+        }
+        board.make_move(moves[(rng.next_u64() >> 32) as usize % moves.len()]);
+    }
+    (!mg.legal_moves(&mut board).is_empty()).then_some(board)
+}
+
+/// Plays one self-play game and returns its quiet positions labelled with
+/// the result.
+pub fn play_game(searcher: &mut Searcher, rng: &mut LcgRng, config: &DatagenConfig) -> Vec<Sample> {
+    let mg = MoveGenerator::global();
+    let mut board = loop {
+        if let Some(board) = random_opening(rng, config.random_plies) {
+            break board;
+        }
+    };
+    // decided games are called early; the decisive positions are still kept
+    let mut adjudicator = Adjudicator::new(Adjudication { resign_moves: 4, resign_score: 1500, ..Adjudication::default() });
+    let mut samples = Vec::new();
+    searcher.clear();
+
+    let result = loop {
+        if let Some((result, _)) = game_over(&mut board) {
+            break result;
+        }
+        let search = searcher.search(&mut board, SearchLimits::nodes(config.nodes));
+        let Some(best) = search.best_move else { break "1/2-1/2" };
+        let white_score = if board.side_to_move == Color::White { search.score } else { -search.score };
+
+        let quiet = !best.is_capture() && best.promotion().is_none();
+        if quiet && !mg.in_check(&board, board.side_to_move) && search.score.abs() < MATE_BOUND {
+            samples.push(Sample { board: board.clone(), score: white_score as i16, result: 0.5 });
+        }
+
+        board.make_move(best);
+        if let Some((result, _)) = adjudicator.update(Some(white_score), board.full_move) {
+            break result;
+        }
+    };
+
+    let result = match result {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        _ => 0.5,
+    };
+    samples.iter_mut().for_each(|sample| sample.result = result);
+    samples
+}
+
+/// Plays `config.games` games over `config.threads` threads, handing each
+/// finished game's samples to `sink` on the calling thread.
+pub fn generate(config: &DatagenConfig, mut sink: impl FnMut(Vec<Sample>) -> Result<(), String>) -> Result<(), String> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || {
+                let mut searcher = Searcher::new(HASH_MB);
+                loop {
+                    let game = next.fetch_add(1, Ordering::Relaxed);
+                    if game >= config.games {
+                        break;
+                    }
+                    // one stream of openings per game, so the data doesn't depend on the thread count
+                    let mut rng = LcgRng::new(config.seed ^ (game as u64 + 1).wrapping_mul(0x9E3779B97F4A7C15));
+                    if sender.send(play_game(&mut searcher, &mut rng, config)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // stops the workers at their next game if writing fails
+        let mut error = Ok(());
+        for samples in receiver {
+            if error.is_ok() {
+                error = sink(samples);
+            }
+            if error.is_err() {
+                next.store(config.games, Ordering::Relaxed);
+            }
+        }
+        error
+    })
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut config = DatagenConfig::default();
+    let mut bin = None;
+    let mut text = None;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--games" => config.games = value()?.parse().map_err(|_| USAGE)?,
+            "--threads" => config.threads = value()?.parse().map_err(|_| USAGE)?,
+            "--nodes" => config.nodes = value()?.parse().map_err(|_| USAGE)?,
+            "--random-plies" => config.random_plies = value()?.parse().map_err(|_| USAGE)?,
+            "--seed" => config.seed = value()?.parse().map_err(|_| USAGE)?,
+            "--bin" => bin = Some(value()?.clone()),
+            "--text" => text = Some(value()?.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if bin.is_none() && text.is_none() {
+        return Err(format!("expected --bin and/or --text\n{}", USAGE));
+    }
+
+    let create = |path: &Option<String>| -> Result<Option<BufWriter<File>>, String> {
+        path.as_ref()
+            .map(|path| File::create(path).map(BufWriter::new).map_err(|e| format!("{}: {}", path, e)))
+            .transpose()
+    };
+    let (mut bin_out, mut text_out) = (create(&bin)?, create(&text)?);
+
+    let start = Instant::now();
+    let (mut games, mut positions) = (0, 0);
+    generate(&config, |samples| {
+        for sample in &samples {
+            if let Some(out) = bin_out.as_mut() {
+                out.write_all(&sample.pack()).map_err(|e| e.to_string())?;
+            }
+            if let Some(out) = text_out.as_mut() {
+                writeln!(out, "{}", sample.to_text()).map_err(|e| e.to_string())?;
+            }
+        }
+        games += 1;
+        positions += samples.len();
+        if games % 10 == 0 || games == config.games {
+            eprintln!("{}/{} games, {} positions, {:.0} positions/s",
+                games, config.games, positions, positions as f64 / start.elapsed().as_secs_f64().max(1e-3));
+        }
+        Ok(())
+    })?;
+
+    for out in [bin_out.as_mut(), text_out.as_mut()].into_iter().flatten() {
+        out.flush().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tune;
+
+    #[test]
+    fn test_pack_round_trip() {
+        for (fen, score, result) in [
+            (START_FEN, 15, 0.5),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq - 3 17", -250, 0.0),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 32000, 1.0),
+        ] {
+            let sample = Sample { board: parse_fen(fen).into(), score, result };
+            let unpacked = Sample::unpack(&sample.pack()).unwrap();
+            assert_eq!(unpacked.board.to_fen(), fen);
+            assert_eq!(unpacked.board.zobrist_key, sample.board.zobrist_key);
+            assert_eq!((unpacked.score, unpacked.result), (score, result));
+        }
+        let mut bad = [0u8; RECORD_LEN];
+        bad[31] = 3;
+        assert!(Sample::unpack(&bad).is_err());
+        // well formed, but an empty board has no kings
+        bad[31] = 1;
+        assert!(Sample::unpack(&bad).unwrap_err().starts_with("Illegal position"));
+    }
+
+    #[test]
+    fn test_generate() {
+        let config = DatagenConfig { games: 2, threads: 2, nodes: 300, random_plies: 8, seed: 7 };
+        let mg = MoveGenerator::global();
+        let mut games = 0;
+        generate(&config, |samples| {
+            games += 1;
+            assert!(!samples.is_empty());
+            let result = samples[0].result;
+            for sample in samples {
+                assert_eq!(sample.result, result);
+                assert!(!mg.in_check(&sample.board, sample.board.side_to_move));
+                // the text lines are what the tuner reads
+                let (board, parsed) = tune::parse_line(&sample.to_text()).unwrap();
+                assert_eq!((board.to_fen(), parsed), (sample.board.to_fen(), result));
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(games, 2);
+    }
+}
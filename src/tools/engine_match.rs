@@ -0,0 +1,573 @@
+//! `match` subcommand
+//!
+//! Plays two UCI engines against each other as subprocesses. Every opening
+//! is played twice with colours swapped, clocks are enforced with
+//! increments, long decided or dead drawn games are adjudicated, and the
+//! games are written as PGN. Results feed an Elo estimate and an SPRT.
+use std::fs::{ self, File };
+use std::io::{ BufRead, BufReader, Write };
+use std::process::{ Child, ChildStdin, Command, Stdio };
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError };
+use std::thread;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+use crate::system::{ game_over, parse_epd, try_parse_fen, win_for, Board, Color, Evaluation, Game, Move, MoveGenerator, PgnReader, Termination, MATE, MATE_BOUND, START_FEN };
+use super::sprt::{ Decision, Sprt, Tally };
+
+const USAGE: &str = "usage: rush-rs match <engine1> <engine2> [--games <n>] [--tc <secs>+<inc>] [--openings <file.epd|file.pgn>] \
+[--pgn <out.pgn>] [--option <name>=<value>] [--sprt] [--elo0 <elo>] [--elo1 <elo>] [--alpha <a>] [--beta <b>] \
+[--resign-moves <n>] [--resign-score <cp>] [--draw-after <move>] [--draw-moves <n>] [--draw-score <cp>] [--max-moves <n>]";
+
+// startup and isready replies have to come within this
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// slack on top of the clock for process and pipe latency
+const TIME_MARGIN: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    /// Parses "60", "10+0.1" or "180+2", all in seconds.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+        let seconds = |s: &str| s.parse::<f64>().ok().filter(|s| *s >= 0.0).map(Duration::from_secs_f64);
+        Some(Self { base: seconds(base)?, increment: seconds(increment)? })
+    }
+
+    // the PGN TimeControl tag value
+    fn tag(&self) -> String {
+        format!("{}+{}", self.base.as_secs_f64(), self.increment.as_secs_f64())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adjudication {
+    // both engines agree one side is ahead by resign_score for resign_moves moves each
+    pub resign_moves: usize,
+    pub resign_score: i32,
+    // from move draw_after, draw_moves plies in a row within draw_score of zero
+    pub draw_after: u16,
+    pub draw_moves: usize,
+    pub draw_score: i32,
+    // a draw once this many full moves have been played; 0 disables it
+    pub max_moves: u16,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self { resign_moves: 3, resign_score: 600, draw_after: 40, draw_moves: 8, draw_score: 10, max_moves: 250 }
+    }
+}
+
+/// Watches the engines' scores and calls the game early.
+#[derive(Debug, Clone)]
+pub struct Adjudicator {
+    config: Adjudication,
+    // engine scores from white's point of view, one per ply
+    scores: Vec<i32>,
+}
+
+impl Adjudicator {
+    pub fn new(config: Adjudication) -> Self {
+        Self { config, scores: Vec::new() }
+    }
+
+    /// Records the score reported for the move just played and returns a
+    /// result if the game can be decided. `full_move` is the number of the
+    /// move about to be played.
+    pub fn update(&mut self, white_score: Option<i32>, full_move: u16) -> Option<(&'static str, Termination)> {
+        match white_score {
+            Some(score) => self.scores.push(score),
+            // an engine that sends no score breaks any streak
+            None => self.scores.clear(),
+        }
+        let recent = |plies: usize| (plies > 0 && self.scores.len() >= plies).then(|| &self.scores[self.scores.len() - plies..]);
+        let config = &self.config;
+
+        if let Some(recent) = recent(config.resign_moves * 2) {
+            if recent.iter().all(|&s| s >= config.resign_score) {
+                return Some(("1-0", Termination::ResignAdjudication));
+            }
+            if recent.iter().all(|&s| s <= -config.resign_score) {
+                return Some(("0-1", Termination::ResignAdjudication));
+            }
+        }
+        if let Some(recent) = recent(config.draw_moves).filter(|_| full_move > config.draw_after) {
+            if recent.iter().all(|s| s.abs() <= config.draw_score) {
+                return Some(("1/2-1/2", Termination::DrawAdjudication));
+            }
+        }
+        if config.max_moves > 0 && full_move > config.max_moves {
+            return Some(("1/2-1/2", Termination::MoveLimit));
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Opening {
+    pub start: Board,
+    pub moves: Vec<Move>,
+}
+
+/// Openings from EPD text (one position per line) or PGN games
+/// (starting position plus main line).
+pub fn parse_openings(text: &str, pgn: bool) -> Result<Vec<Opening>, String> {
+    if pgn {
+        return PgnReader::new(text.as_bytes()).map(|game| {
+            let game = game.map_err(|e| e.to_string())?;
+            Ok(Opening { moves: game.mainline_moves(), start: game.start })
+        }).collect();
+    }
+
+    text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(index, line)| {
+        let epd = parse_epd(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        Ok(Opening { start: epd.board, moves: Vec::new() })
+    }).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub bestmove: String,
+    // last reported score, side-to-move relative
+    pub score: Option<i32>,
+    pub elapsed: Duration,
+}
+
+// "info depth 12 score cp -35 nodes ..." -> -35, mates as MATE scores
+fn parse_score(line: &str) -> Option<i32> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let at = tokens.iter().position(|&t| t == "score")?;
+    let value: i32 = tokens.get(at + 2)?.parse().ok()?;
+    match *tokens.get(at + 1)? {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - (2 * value - 1)),
+        "mate" => Some(-MATE - 2 * value),
+        _ => None,
+    }
+}
+
+/// A UCI engine running as a child process. Its output is read on a
+/// separate thread so every wait can time out.
+pub struct UciEngine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    /// Starts `command` (a program followed by its arguments) and completes
+    /// the UCI handshake.
+    pub fn start(command: &str) -> Result<Self, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("empty engine command")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{}: {}", program, e))?;
+
+        let stdin = child.stdin.take().ok_or("no engine stdin")?;
+        let stdout = child.stdout.take().ok_or("no engine stdout")?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self { name: program.to_string(), child, stdin, lines };
+        engine.send("uci")?;
+        for line in engine.wait_for("uciok", HANDSHAKE_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command).and_then(|_| self.stdin.flush()).map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    // lines up to and including the first one starting with `prefix`
+    fn wait_for(&mut self, prefix: &str, timeout: Duration) -> Result<Vec<String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) => {
+                    let done = line.starts_with(prefix);
+                    lines.push(line);
+                    if done {
+                        return Ok(lines);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => return Err(format!("{}: no '{}' in time", self.name, prefix)),
+                Err(RecvTimeoutError::Disconnected) => return Err(format!("{}: engine exited", self.name)),
+            }
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    pub fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", HANDSHAKE_TIMEOUT).map(|_| ())
+    }
+
+    /// Searches `position` (a full UCI position command) on the given clocks.
+    /// Returns Ok(None) when the engine overstepped `limit`.
+    pub fn go(&mut self, position: &str, clocks: [Duration; 2], increment: Duration, limit: Duration) -> Result<Option<Reply>, String> {
+        self.send(position)?;
+        let inc = increment.as_millis();
+        self.send(&format!(
+            "go wtime {} btime {} winc {} binc {}",
+            clocks[Color::White as usize].as_millis(), clocks[Color::Black as usize].as_millis(), inc, inc,
+        ))?;
+
+        let start = Instant::now();
+        let lines = match self.wait_for("bestmove", limit + TIME_MARGIN) {
+            Ok(lines) => lines,
+            Err(_) if !self.exited() => {
+                // out of time: stop it so the next game starts in sync
+                self.send("stop")?;
+                self.wait_for("bestmove", HANDSHAKE_TIMEOUT)?;
+                return Ok(None);
+            },
+            Err(e) => return Err(e),
+        };
+        let elapsed = start.elapsed();
+        if elapsed > limit + TIME_MARGIN {
+            return Ok(None);
+        }
+
+        let score = lines.iter().rev().find_map(|line| parse_score(line));
+        let bestmove = lines.last().and_then(|line| line.split_whitespace().nth(1)).unwrap_or("").to_string();
+        Ok(Some(Reply { bestmove, score, elapsed }))
+    }
+
+    fn exited(&mut self) -> bool {
+        !matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while !self.exited() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MatchConfig {
+    pub time_control: Option<TimeControl>,
+    pub adjudication: Adjudication,
+}
+
+impl MatchConfig {
+    fn time_control(&self) -> TimeControl {
+        self.time_control.unwrap_or(TimeControl { base: Duration::from_secs(10), increment: Duration::from_millis(100) })
+    }
+}
+
+// white's point of view, as PGN and the adjudicator want it
+fn white_score(score: i32, side: Color) -> i32 {
+    if side == Color::White { score } else { -score }
+}
+
+fn evaluation(white: i32) -> Evaluation {
+    if white.abs() > MATE_BOUND {
+        let moves = (MATE - white.abs() + 1) / 2;
+        Evaluation::Mate(if white > 0 { moves } else { -moves })
+    } else {
+        Evaluation::Centipawns(white)
+    }
+}
+
+/// Plays one game and returns it with its termination.
+pub fn play_game(white: &mut UciEngine, black: &mut UciEngine, opening: &Opening, config: &MatchConfig) -> Result<(Game, Termination), String> {
+    let mg = MoveGenerator::global();
+    let tc = config.time_control();
+    white.new_game()?;
+    black.new_game()?;
+
+    let mut game = Game::new(opening.start.clone());
+    let mut board = opening.start.clone();
+    let mut node = Game::ROOT;
+    let mut history: Vec<String> = Vec::new();
+    for &mv in &opening.moves {
+        board.make_move(mv);
+        node = game.add_move(node, mv, board.zobrist_key);
+        history.push(mv.to_string());
+    }
+    if !opening.moves.is_empty() {
+        game.node_mut(node).comment = Some("book".to_string());
+    }
+
+    let start_fen = opening.start.to_fen();
+    let mut clocks = [tc.base; 2];
+    let mut adjudicator = Adjudicator::new(config.adjudication);
+
+    let (result, termination) = loop {
+        if let Some(over) = game_over(&mut board) {
+            break over;
+        }
+        let side = board.side_to_move;
+        let engine = if side == Color::White { &mut *white } else { &mut *black };
+        let position = if history.is_empty() {
+            format!("position fen {}", start_fen)
+        } else {
+            format!("position fen {} moves {}", start_fen, history.join(" "))
+        };
+
+        let reply = match engine.go(&position, clocks, tc.increment, clocks[side as usize]) {
+            Ok(Some(reply)) => reply,
+            Ok(None) => break (win_for(!side), Termination::TimeForfeit),
+            Err(_) => break (win_for(!side), Termination::Disconnected),
+        };
+        let Some(mv) = mg.parse_move(&mut board, &reply.bestmove) else {
+            break (win_for(!side), Termination::IllegalMove);
+        };
+        clocks[side as usize] = clocks[side as usize].saturating_sub(reply.elapsed) + tc.increment;
+
+        board.make_move(mv);
+        node = game.add_move(node, mv, board.zobrist_key);
+        history.push(reply.bestmove);
+        let score = reply.score.map(|s| white_score(s, side));
+        let annotated = game.node_mut(node);
+        annotated.eval = score.map(evaluation);
+        annotated.clock = Some(clocks[side as usize]);
+
+        if let Some(adjudicated) = adjudicator.update(score, board.full_move) {
+            // the rules still win over the engines' opinion
+            break game_over(&mut board).unwrap_or(adjudicated);
+        }
+    };
+
+    game.result = result.to_string();
+    game.set_tag("Result", result);
+    game.set_tag("TimeControl", &tc.tag());
+    game.set_tag("Termination", termination.tag());
+    let note = termination.describe().to_string();
+    let last = game.node_mut(node);
+    last.comment = Some(match last.comment.take() {
+        Some(comment) => format!("{} {}", comment, note),
+        None => note,
+    });
+    Ok((game, termination))
+}
+
+// yyyy.mm.dd for the PGN Date tag, from days since 1970-01-01
+fn pgn_date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    pgn_date((secs / 86_400) as i64)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut engines = Vec::new();
+    let mut games: usize = 100;
+    let mut openings_path = None;
+    let mut pgn_path = None;
+    let mut options = Vec::new();
+    let mut config = MatchConfig::default();
+    let mut sprt = Sprt::default();
+    let mut stop_on_sprt = false;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        let adjudication = &mut config.adjudication;
+        match arg.as_str() {
+            "--games" => games = value()?.parse().map_err(|_| USAGE)?,
+            "--tc" => config.time_control = Some(TimeControl::parse(value()?).ok_or(USAGE)?),
+            "--openings" => openings_path = Some(value()?.clone()),
+            "--pgn" => pgn_path = Some(value()?.clone()),
+            "--option" => options.push(value()?.split_once('=').map(|(n, v)| (n.to_string(), v.to_string())).ok_or(USAGE)?),
+            "--sprt" => stop_on_sprt = true,
+            "--elo0" => sprt.elo0 = value()?.parse().map_err(|_| USAGE)?,
+            "--elo1" => sprt.elo1 = value()?.parse().map_err(|_| USAGE)?,
+            "--alpha" => sprt.alpha = value()?.parse().map_err(|_| USAGE)?,
+            "--beta" => sprt.beta = value()?.parse().map_err(|_| USAGE)?,
+            "--resign-moves" => adjudication.resign_moves = value()?.parse().map_err(|_| USAGE)?,
+            "--resign-score" => adjudication.resign_score = value()?.parse().map_err(|_| USAGE)?,
+            "--draw-after" => adjudication.draw_after = value()?.parse().map_err(|_| USAGE)?,
+            "--draw-moves" => adjudication.draw_moves = value()?.parse().map_err(|_| USAGE)?,
+            "--draw-score" => adjudication.draw_score = value()?.parse().map_err(|_| USAGE)?,
+            "--max-moves" => adjudication.max_moves = value()?.parse().map_err(|_| USAGE)?,
+            flag if flag.starts_with("--") => return Err(USAGE.to_string()),
+            engine => engines.push(engine.to_string()),
+        }
+    }
+    if engines.len() != 2 || games == 0 {
+        return Err(USAGE.to_string());
+    }
+
+    let openings = match &openings_path {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            parse_openings(&text, path.ends_with(".pgn"))?
+        },
+        None => vec![Opening { start: try_parse_fen(START_FEN)?.into(), moves: Vec::new() }],
+    };
+    if openings.is_empty() {
+        return Err("no openings".to_string());
+    }
+
+    let mut first = UciEngine::start(&engines[0])?;
+    let mut second = UciEngine::start(&engines[1])?;
+    for engine in [&mut first, &mut second] {
+        for (name, value) in &options {
+            engine.set_option(name, value)?;
+        }
+    }
+    let mut pgn: Option<File> = match &pgn_path {
+        Some(path) => Some(File::create(path).map_err(|e| format!("{}: {}", path, e))?),
+        None => None,
+    };
+
+    let (first_name, second_name) = (first.name.clone(), second.name.clone());
+    let mut tally = Tally::default();
+    let date = today();
+    let (lower, upper) = sprt.bounds();
+
+    'pairs: for round in 0..games.div_ceil(2) {
+        let opening = &openings[round % openings.len()];
+        for swapped in [false, true] {
+            let number = round * 2 + usize::from(swapped) + 1;
+            let (white, black) = if swapped { (&mut second, &mut first) } else { (&mut first, &mut second) };
+            let (mut game, termination) = play_game(white, black, opening, &config)?;
+
+            game.set_tag("Event", "rush-rs match");
+            game.set_tag("Date", &date);
+            game.set_tag("Round", &number.to_string());
+            game.set_tag("White", &white.name);
+            game.set_tag("Black", &black.name);
+            if let Some(file) = pgn.as_mut() {
+                write!(file, "{}", game).map_err(|e| e.to_string())?;
+            }
+
+            // the first engine's point of view
+            let first_white = !swapped;
+            match (game.result.as_str(), first_white) {
+                ("1-0", true) | ("0-1", false) => tally.wins += 1,
+                ("1-0", false) | ("0-1", true) => tally.losses += 1,
+                _ => tally.draws += 1,
+            }
+            println!(
+                "game {}: {} - {} {} ({}) | {} vs {}: {} | LLR {:.2} ({:.2}, {:.2})",
+                number, white.name, black.name, game.result, termination.describe(),
+                first_name, second_name, tally, sprt.llr(&tally), lower, upper,
+            );
+
+            if stop_on_sprt {
+                if let Some(decision) = sprt.decision(&tally) {
+                    let accepted = match decision {
+                        Decision::H0 => format!("H0 accepted (elo <= {})", sprt.elo0),
+                        Decision::H1 => format!("H1 accepted (elo >= {})", sprt.elo1),
+                    };
+                    println!("SPRT [{}, {}] {}", sprt.elo0, sprt.elo1, accepted);
+                    break 'pairs;
+                }
+            }
+            if number == games {
+                break 'pairs;
+            }
+        }
+    }
+
+    println!("{} vs {}: {}", first_name, second_name, tally);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_control() {
+        assert_eq!(TimeControl::parse("10+0.1"), Some(TimeControl { base: Duration::from_secs(10), increment: Duration::from_millis(100) }));
+        assert_eq!(TimeControl::parse("60").unwrap().increment, Duration::ZERO);
+        assert_eq!(TimeControl::parse("abc"), None);
+        assert_eq!(TimeControl::parse("180+2").unwrap().tag(), "180+2");
+    }
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("info depth 10 score cp -35 nodes 100 pv e2e4"), Some(-35));
+        assert_eq!(parse_score("info depth 10 score mate 2 pv e2e4"), Some(MATE - 3));
+        assert_eq!(parse_score("info depth 10 score mate -1 pv e2e4"), Some(-MATE + 2));
+        assert_eq!(parse_score("info string hello"), None);
+        assert_eq!(evaluation(MATE - 3), Evaluation::Mate(2));
+    }
+
+    #[test]
+    fn test_adjudication() {
+        let config = Adjudication { resign_moves: 2, resign_score: 500, draw_after: 30, draw_moves: 4, draw_score: 10, max_moves: 100 };
+        let mut resign = Adjudicator::new(config);
+        assert_eq!(resign.update(Some(600), 20), None);
+        assert_eq!(resign.update(Some(550), 20), None);
+        assert_eq!(resign.update(None, 21), None);
+        for _ in 0..3 {
+            assert_eq!(resign.update(Some(-700), 22), None);
+        }
+        assert_eq!(resign.update(Some(-700), 23), Some(("0-1", Termination::ResignAdjudication)));
+
+        // quiet scores only count once the move number is reached
+        let mut draw = Adjudicator::new(config);
+        for _ in 0..4 {
+            assert_eq!(draw.update(Some(5), 10), None);
+        }
+        assert_eq!(draw.update(Some(0), 31), Some(("1/2-1/2", Termination::DrawAdjudication)));
+
+        assert_eq!(Adjudicator::new(config).update(Some(100), 101), Some(("1/2-1/2", Termination::MoveLimit)));
+    }
+
+    #[test]
+    fn test_openings() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n\n6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - -\n";
+        let openings = parse_openings(epd, false).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[1].start.to_fen(), "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+        assert!(parse_openings("not a position", false).unwrap_err().starts_with("line 1"));
+
+        let openings = parse_openings("1. d4 d5 2. c4 *\n", true).unwrap();
+        assert_eq!(openings[0].moves.len(), 3);
+    }
+
+    #[test]
+    fn test_pgn_date() {
+        assert_eq!(pgn_date(0), "1970.01.01");
+        assert_eq!(pgn_date(19_723), "2024.01.01");
+        assert_eq!(pgn_date(11_016), "2000.02.29");
+    }
+}
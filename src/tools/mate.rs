@@ -0,0 +1,39 @@
+//! `mate` subcommand
+//! 
+//! Usage: rush-rs mate <max moves> [--checks] <fen | startpos>
+use crate::system::{ try_parse_fen, Board, MateResult, MateSearch, MoveGenerator, START_FEN };
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let usage = "usage: rush-rs mate <max moves> [--checks] <fen | startpos>";
+    let max_moves = args.first()
+        .and_then(|n| n.parse::<u32>().ok())
+        .ok_or(usage)?;
+
+    let checks_only = args.iter().any(|a| a == "--checks");
+    let fen = args[1..].iter()
+        .filter(|a| a.as_str() != "--checks")
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let fen = match fen.as_str() {
+        "" => return Err(usage.to_string()),
+        "startpos" => START_FEN,
+        fen => fen,
+    };
+
+    let mut board: Board = try_parse_fen(fen)?.into();
+    let mut search = MateSearch::new(MoveGenerator::global()).checks_only(checks_only);
+    match search.search(&mut board, max_moves) {
+        MateResult::Mate { moves, line } => {
+            let line: Vec<String> = line.iter().map(|mv| mv.to_string()).collect();
+            println!("mate in {}: {}", moves, line.join(" "));
+        },
+        // quiet attacking moves were never tried, so a mate may still exist
+        MateResult::NoMate { max_moves } if checks_only => println!("no mate in {} by checks only", max_moves),
+        MateResult::NoMate { max_moves } => println!("no mate in {}", max_moves),
+        // nothing sets the stop flag here
+        MateResult::Stopped { searched } => println!("stopped, no mate in {}", searched),
+    }
+    println!("nodes {}", search.nodes());
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! `mine-puzzles` subcommand
+//!
+//! Replays the games in PGN files and looks for blunders that hand the
+//! opponent a win with only one good move at every step. Each find is
+//! written as a row in the Lichess puzzle CSV layout.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{ self, BufReader, Write };
+use crate::system::{ tactics, Board, Move, MoveGenerator, PgnReader, SearchLimits, Searcher, DEFAULT_HASH_MB, MATE_BOUND };
+
+const USAGE: &str = "usage: rush-rs mine-puzzles <file.pgn>... [--out <file.csv>] [--nodes <n>] [--gap <cp>] [--max-moves <n>]";
+const HEADER: &str = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags";
+
+// the side to move must stand at least this well for the position to count as won
+const WINNING: i32 = 300;
+// and the blunderer must not have been lost already
+const NOT_LOST: i32 = -150;
+const CRUSHING: i32 = 600;
+
+#[derive(Debug, Clone)]
+pub struct MinedPuzzle {
+    pub key: u64,
+    pub fen: String,
+    // setup move followed by the solution
+    pub moves: Vec<Move>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+    pub url: String,
+}
+
+impl MinedPuzzle {
+    pub fn id(&self) -> String {
+        format!("{:010x}", self.key >> 24)
+    }
+
+    pub fn csv_row(&self) -> String {
+        let moves: Vec<String> = self.moves.iter().map(|mv| mv.to_string()).collect();
+        format!(
+            "{},{},{},{},500,0,0,{},{},",
+            self.id(),
+            self.fen,
+            moves.join(" "),
+            self.rating,
+            self.themes.join(" "),
+            self.url,
+        )
+    }
+}
+
+pub struct Miner {
+    mg: &'static MoveGenerator,
+    searcher: Searcher,
+    nodes: u64,
+    gap: i32,
+    max_moves: usize,
+    seen: HashSet<u64>,
+}
+
+impl Miner {
+    pub fn new(nodes: u64, gap: i32) -> Self {
+        Self {
+            mg: MoveGenerator::global(),
+            searcher: Searcher::new(DEFAULT_HASH_MB),
+            nodes,
+            gap,
+            max_moves: 4,
+            seen: HashSet::new(),
+        }
+    }
+
+    // longest solution, counted in the solver's moves
+    pub fn max_moves(mut self, max_moves: usize) -> Self {
+        self.max_moves = max_moves.max(1);
+        self
+    }
+
+    fn score(&mut self, board: &mut Board) -> i32 {
+        self.searcher.search(board, SearchLimits::nodes(self.nodes)).score
+    }
+
+    /// Puzzles found in one game, played from `start`. Positions already
+    /// mined from earlier games are skipped.
+    pub fn mine_game(&mut self, start: &Board, moves: &[Move], url: &str) -> Vec<MinedPuzzle> {
+        self.searcher.clear();
+        let mut board = start.clone();
+        let mut found = Vec::new();
+        let mut before = self.score(&mut board);
+
+        for (ply, &mv) in moves.iter().enumerate() {
+            let fen = board.to_fen();
+            board.make_move(mv);
+            if self.mg.legal_moves(&mut board).is_empty() {
+                break;
+            }
+            let after = self.score(&mut board);
+
+            // the mover was fine, and now the opponent is winning
+            let blunder = before >= NOT_LOST && after >= WINNING;
+            if blunder && !self.seen.contains(&board.zobrist_key) {
+                if let Some(puzzle) = self.puzzle(&mut board, fen, mv, ply, url) {
+                    self.seen.insert(puzzle.key);
+                    found.push(puzzle);
+                }
+            }
+            before = after;
+        }
+        found
+    }
+
+    // best move, its score, and the score of the best alternative
+    fn best_two(&mut self, board: &mut Board) -> Option<(Move, i32, i32)> {
+        let result = self.searcher.search(board, SearchLimits::nodes(self.nodes).multipv(2));
+        let best_move = result.best_move?;
+        // a forced move has no alternative at all
+        let second = result.lines.get(1).map_or(-MATE_BOUND, |line| line.score);
+        Some((best_move, result.score, second))
+    }
+
+    fn is_only_move(&self, best: i32, second: i32) -> bool {
+        let mate = best > MATE_BOUND;
+        best >= WINNING && best - second >= self.gap && (second < WINNING || mate)
+    }
+
+    fn puzzle(&mut self, board: &mut Board, fen: String, blunder: Move, ply: usize, url: &str) -> Option<MinedPuzzle> {
+        let key = board.zobrist_key;
+        let start = board.clone();
+        let mut line = Vec::new();
+        let mut first_score = 0;
+        let mut mated = false;
+
+        for step in 0..self.max_moves {
+            let Some((best, score, second)) = self.best_two(board) else { break };
+            if !self.is_only_move(score, second) {
+                break;
+            }
+            if step == 0 {
+                first_score = score;
+            }
+            line.push(best);
+            board.make_move(best);
+
+            if self.mg.legal_moves(board).is_empty() {
+                mated = self.mg.in_check(board, board.side_to_move);
+                break;
+            }
+            if step + 1 == self.max_moves {
+                break;
+            }
+            let Some(reply) = self.searcher.search(board, SearchLimits::nodes(self.nodes)).best_move else { break };
+            line.push(reply);
+            board.make_move(reply);
+        }
+
+        // the line has to end on the solver's move
+        if line.len() % 2 == 0 {
+            line.pop();
+        }
+        while board.ply() > start.ply() {
+            board.unmake_move();
+        }
+        if line.is_empty() {
+            return None;
+        }
+
+        let solver_moves = line.len().div_ceil(2);
+        let mut themes: Vec<String> = tactics::classify(&start, &line).iter().filter_map(|m| m.theme()).map(String::from).collect();
+        if mated {
+            themes.push("mate".to_string());
+            themes.push(format!("mateIn{}", solver_moves));
+        } else if first_score >= CRUSHING {
+            themes.push("crushing".to_string());
+        } else {
+            themes.push("advantage".to_string());
+        }
+        themes.push(match solver_moves {
+            1 => "oneMove",
+            2 => "short",
+            3 => "long",
+            _ => "veryLong",
+        }.to_string());
+        themes.sort();
+        themes.dedup();
+
+        // a rough guess until the puzzle has been played: longer lines are harder
+        let rating = 1000 + 250 * (solver_moves as u32 - 1);
+        let url = if url.starts_with("http") { format!("{}#{}", url, ply + 1) } else { String::new() };
+
+        let mut moves = vec![blunder];
+        moves.extend(line);
+        Some(MinedPuzzle { key, fen, moves, rating, themes, url })
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut files = Vec::new();
+    let mut out = None;
+    let mut nodes = 100_000;
+    let mut gap = 200;
+    let mut max_moves = 4;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--out" => out = Some(value()?.clone()),
+            "--nodes" => nodes = value()?.parse().map_err(|_| USAGE)?,
+            "--gap" => gap = value()?.parse().map_err(|_| USAGE)?,
+            "--max-moves" => max_moves = value()?.parse().map_err(|_| USAGE)?,
+            flag if flag.starts_with("--") => return Err(USAGE.to_string()),
+            path => files.push(path.to_string()),
+        }
+    }
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut writer: Box<dyn Write> = match &out {
+        Some(path) => Box::new(File::create(path).map_err(|e| format!("{}: {}", path, e))?),
+        None => Box::new(io::stdout()),
+    };
+    writeln!(writer, "{}", HEADER).map_err(|e| e.to_string())?;
+
+    let mut miner = Miner::new(nodes, gap).max_moves(max_moves);
+    let (mut games, mut puzzles) = (0, 0);
+
+    for path in &files {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        for game in PgnReader::new(BufReader::new(file)) {
+            let game = match game {
+                Ok(game) => game,
+                Err(e) => {
+                    eprintln!("{}: {}, skipping game", path, e);
+                    continue;
+                },
+            };
+            games += 1;
+            for puzzle in miner.mine_game(&game.start, &game.mainline_moves(), game.tag("Site").unwrap_or("")) {
+                writeln!(writer, "{}", puzzle.csv_row()).map_err(|e| e.to_string())?;
+                puzzles += 1;
+            }
+        }
+    }
+
+    eprintln!("{} puzzles from {} games", puzzles, games);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::Game;
+
+    const GAMES: &str = r#"[Event "Casual"]
+[Site "https://lichess.org/abcdefgh"]
+[Result "1-0"]
+
+1. e4 e5 2. Bc4 {aiming at f7} Nc6 3. Qh5 (3. Nf3 Nf6) Nf6?? $4 4. Qxf7# 1-0
+
+[Event "Casual"]
+[FEN "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"]
+[SetUp "1"]
+[Result "*"]
+
+1. Kf1 ; quiet
+Kf8 2. Ke2 Ke7 *
+"#;
+
+    fn games() -> Vec<Game> {
+        PgnReader::new(GAMES.as_bytes()).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_mines_blunder() {
+        let games = games();
+        let mut miner = Miner::new(20_000, 200);
+
+        let (start, moves) = (&games[0].start, games[0].mainline_moves());
+        let puzzles = miner.mine_game(start, &moves, "https://lichess.org/abcdefgh");
+        assert_eq!(puzzles.len(), 1);
+
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.fen, "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3");
+        let line: Vec<String> = puzzle.moves.iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(line, vec!["g8f6", "h5f7"]);
+        assert!(puzzle.themes.contains(&"mateIn1".to_string()));
+        assert!(puzzle.csv_row().ends_with(",https://lichess.org/abcdefgh#6,"));
+
+        // the same position is not mined twice
+        assert!(miner.mine_game(start, &moves, "").is_empty());
+        assert!(miner.mine_game(&games[1].start, &games[1].mainline_moves(), "").is_empty());
+    }
+}
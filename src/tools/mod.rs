@@ -0,0 +1,11 @@
+pub mod annotate;
+pub mod datagen;
+pub mod engine_match;
+pub mod mate;
+pub mod mine;
+pub mod play;
+pub mod puzzles;
+pub mod rush;
+pub mod sprt;
+pub mod testsuite;
+pub mod tune;
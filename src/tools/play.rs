@@ -0,0 +1,273 @@
+//! `play` subcommand
+//!
+//! Plays and analyses at the terminal without a GUI. Moves are typed in SAN
+//! or coordinate notation and the engine answers for the other side; the
+//! other commands inspect the position, take moves back or let the engine
+//! think.
+use std::io::{ self, BufRead, Write };
+use std::time::{ Duration, Instant };
+use crate::system::{ evaluate, format_score, game_over, render, try_parse_fen, Board, Color, MoveGenerator, RenderOptions, SearchLimits, Searcher, DEFAULT_HASH_MB, MATE_BOUND, START_FEN };
+
+const USAGE: &str = "usage: rush-rs play [--fen <fen>] [--engine white|black|none] [--depth <n> | --nodes <n> | --time <ms>] [--unicode] [--flip]";
+const HELP: &str = "moves: SAN (Nf3, exd5, O-O, e8=Q) or coordinates (g1f3, e7e8q)
+commands:
+  board             show the board
+  flip              turn the board around
+  undo              take back your last move and the engine's reply
+  fen               print the position as FEN
+  setfen <fen>      set up a position
+  eval              static evaluation
+  go [depth <n>]    let the engine move for the side to move
+  hint              suggest a move without playing it
+  perft <n>         count the leaf nodes n plies deep
+  legal             list the legal moves
+  help              show this text
+  quit              leave";
+const DEFAULT_MOVETIME: Duration = Duration::from_secs(1);
+
+/// The REPL state: the game, the engine and the terminal.
+pub struct Session<R, W> {
+    board: Board,
+    searcher: Searcher,
+    limits: SearchLimits,
+    // the side the engine plays; None to move only when asked with go
+    engine: Option<Color>,
+    options: RenderOptions,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Session<R, W> {
+    pub fn new(board: Board, limits: SearchLimits, engine: Option<Color>, options: RenderOptions, input: R, output: W) -> Self {
+        Self { board, searcher: Searcher::new(DEFAULT_HASH_MB), limits, engine, options, input, output }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        writeln!(self.output, "type 'help' for the commands")?;
+        self.show()?;
+        if self.engine == Some(self.board.side_to_move) {
+            self.engine_move(self.limits.clone())?;
+        }
+
+        loop {
+            let side = if self.board.side_to_move == Color::White { "white" } else { "black" };
+            write!(self.output, "{}> ", side)?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let Some(&command) = tokens.first() else { continue };
+            if !self.handle(command, &tokens[1..])? {
+                return Ok(());
+            }
+        }
+    }
+
+    // runs one command; false once the user wants to leave
+    fn handle(&mut self, command: &str, args: &[&str]) -> io::Result<bool> {
+        let mg = MoveGenerator::global();
+        match command {
+            "quit" | "exit" => return Ok(false),
+            "help" => writeln!(self.output, "{}", HELP)?,
+            "board" => self.show()?,
+            "flip" => {
+                self.options.flipped = !self.options.flipped;
+                self.show()?;
+            },
+            "undo" => {
+                // playing the engine, the engine's reply goes too
+                let plies = if self.engine.is_some_and(|engine| engine != self.board.side_to_move) && self.board.ply() >= 2 { 2 } else { 1 };
+                if self.board.ply() == 0 {
+                    writeln!(self.output, "nothing to undo")?;
+                } else {
+                    for _ in 0..plies {
+                        self.board.unmake_move();
+                    }
+                    self.show()?;
+                }
+            },
+            "fen" => writeln!(self.output, "{}", self.board.to_fen())?,
+            "setfen" => match try_parse_fen(&args.join(" ")) {
+                Ok(fen) => {
+                    self.board = fen.into();
+                    self.show()?;
+                },
+                Err(e) => writeln!(self.output, "invalid FEN: {}", e)?,
+            },
+            "eval" => {
+                let score = evaluate(&self.board);
+                let white = if self.board.side_to_move == Color::White { score } else { -score };
+                writeln!(self.output, "static eval {} for the side to move ({} for white)", pawns(score), pawns(white))?;
+            },
+            "go" => {
+                let mut limits = self.limits.clone();
+                if let ["depth", depth] = args {
+                    match depth.parse::<u32>() {
+                        Ok(depth) => limits = SearchLimits::depth(depth.max(1)),
+                        Err(_) => {
+                            writeln!(self.output, "usage: go [depth <n>]")?;
+                            return Ok(true);
+                        },
+                    }
+                }
+                // from now on the engine plays this side
+                self.engine = Some(self.board.side_to_move);
+                self.engine_move(limits)?;
+            },
+            "hint" => {
+                let result = self.searcher.search(&mut self.board, self.limits.clone());
+                match result.best_move {
+                    Some(mv) => {
+                        let san = mg.to_san(&mut self.board, mv);
+                        writeln!(self.output, "hint: {} ({})", san, describe_score(result.score))?;
+                    },
+                    None => writeln!(self.output, "no legal moves")?,
+                }
+            },
+            "perft" => match args.first().and_then(|d| d.parse::<u32>().ok()) {
+                Some(depth) => {
+                    let start = Instant::now();
+                    let nodes = mg.perft(&mut self.board, depth);
+                    writeln!(self.output, "perft {}: {} nodes in {}ms", depth, nodes, start.elapsed().as_millis())?;
+                },
+                None => writeln!(self.output, "usage: perft <n>")?,
+            },
+            "legal" => {
+                let mut moves: Vec<String> = mg.legal_moves(&mut self.board).into_iter()
+                    .map(|mv| mg.to_san(&mut self.board, mv))
+                    .collect();
+                moves.sort();
+                writeln!(self.output, "{} legal moves: {}", moves.len(), moves.join(" "))?;
+            },
+            text => {
+                let mv = mg.parse_move(&mut self.board, text).or_else(|| mg.parse_san(&mut self.board, text));
+                let Some(mv) = mv else {
+                    writeln!(self.output, "unknown command or illegal move '{}' (try 'help')", text)?;
+                    return Ok(true);
+                };
+                self.board.make_move(mv);
+                self.show()?;
+                if !self.announce_result()? && self.engine == Some(self.board.side_to_move) {
+                    self.engine_move(self.limits.clone())?;
+                }
+            },
+        }
+        Ok(true)
+    }
+
+    fn engine_move(&mut self, limits: SearchLimits) -> io::Result<()> {
+        let mg = MoveGenerator::global();
+        let result = self.searcher.search(&mut self.board, limits);
+        let Some(mv) = result.best_move else {
+            return self.announce_result().map(|_| ());
+        };
+        let san = mg.to_san(&mut self.board, mv);
+        writeln!(self.output, "rush-rs plays {} ({}, depth {}, {} nodes)", san, describe_score(result.score), result.depth, result.nodes)?;
+        self.board.make_move(mv);
+        self.show()?;
+        self.announce_result()?;
+        Ok(())
+    }
+
+    // true if the game is over
+    fn announce_result(&mut self) -> io::Result<bool> {
+        match game_over(&mut self.board) {
+            Some((result, termination)) => {
+                writeln!(self.output, "game over: {} ({})", result, termination.describe())?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn show(&mut self) -> io::Result<()> {
+        writeln!(self.output, "{}", render(self.board.piece_boards, self.options))
+    }
+}
+
+// "+0.35" in pawns
+fn pawns(cp: i32) -> String {
+    format!("{:+.2}", cp as f64 / 100.0)
+}
+
+// pawns, or "mate 3" / "mate -2"
+fn describe_score(score: i32) -> String {
+    if score.abs() > MATE_BOUND { format_score(score) } else { pawns(score) }
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut fen = START_FEN.to_string();
+    let mut engine = Some(Color::Black);
+    let mut limits = SearchLimits::movetime(DEFAULT_MOVETIME);
+    let mut options = RenderOptions::default();
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--fen" => fen = value()?.clone(),
+            "--engine" => engine = match value()?.as_str() {
+                "white" => Some(Color::White),
+                "black" => Some(Color::Black),
+                "none" => None,
+                _ => return Err(USAGE.to_string()),
+            },
+            "--depth" => limits = SearchLimits::depth(value()?.parse().map_err(|_| USAGE)?),
+            "--nodes" => limits = SearchLimits::nodes(value()?.parse().map_err(|_| USAGE)?),
+            "--time" => limits = SearchLimits::movetime(Duration::from_millis(value()?.parse().map_err(|_| USAGE)?)),
+            "--unicode" => options.unicode = true,
+            "--flip" => options.flipped = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let board: Board = try_parse_fen(&fen)?.into();
+
+    let stdin = io::stdin();
+    let mut session = Session::new(board, limits, engine, options, stdin.lock(), io::stdout());
+    session.run().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::parse_fen;
+
+    fn play(fen: &str, engine: Option<Color>, input: &str) -> (Board, String) {
+        let mut session = Session::new(parse_fen(fen).into(), SearchLimits::depth(2), engine, RenderOptions::default(), input.as_bytes(), Vec::new());
+        session.run().unwrap();
+        (session.board, String::from_utf8(session.output).unwrap())
+    }
+
+    #[test]
+    fn test_plays_against_the_engine() {
+        // SAN and coordinates both work; the engine answers each move
+        let (board, output) = play(START_FEN, Some(Color::Black), "e4\nNf3\nundo\nfen\nquit\n");
+        assert_eq!(output.matches("rush-rs plays").count(), 2);
+        assert_eq!(board.ply(), 2);
+        assert!(output.contains("\n1  R N B Q K B N R\n"));
+
+        let (board, output) = play(START_FEN, Some(Color::White), "flip\ne2e5\n");
+        assert!(output.contains("8  r n b k q b n r\n   h g f e d c b a"), "{}", output);
+        assert!(output.contains("illegal move 'e2e5'"));
+        assert_eq!(board.ply(), 1);
+    }
+
+    #[test]
+    fn test_commands() {
+        let (board, output) = play(START_FEN, None, "perft 2\nlegal\neval\nsetfen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\nhint\ngo depth 3\n");
+        assert!(output.contains("perft 2: 400 nodes"));
+        assert!(output.contains("20 legal moves: Na3 Nc3 Nf3 Nh3 a3 a4"));
+        assert!(output.contains("static eval"));
+        assert!(output.contains("hint: Ra8# (mate 1)"));
+        assert!(output.contains("game over: 1-0 (checkmate)"));
+        assert_eq!(board.to_fen(), "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1");
+
+        let (_, output) = play(START_FEN, None, "setfen bogus\ngo depth x\nundo\n");
+        assert!(output.contains("invalid FEN"));
+        assert!(output.contains("usage: go [depth <n>]"));
+        assert!(output.contains("nothing to undo"));
+    }
+}
@@ -0,0 +1,509 @@
+//! `puzzles` subcommand
+//!
+//! Runs the engine over a Lichess puzzle database export
+//! (PuzzleId,FEN,Moves,Rating,...,Themes,...). The first move of every
+//! puzzle is the opponent's blunder and is played as setup; the engine then
+//! has to find each of the remaining solution moves on its own. Unless
+//! `--no-ambiguity` is given, every search also looks for a second line
+//! (MultiPV 2), so puzzles where another move wins as well are flagged as
+//! ambiguous.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{ BufRead, BufReader };
+use std::time::{ Duration, Instant };
+use crate::system::{ try_parse_fen, Board, Move, MoveGenerator, SearchLimits, Searcher, DEFAULT_HASH_MB, MATE };
+
+const USAGE: &str = "usage: rush-rs puzzles <file.csv> [--nodes <n> | --time <ms>] [--limit <n>] [--min-rating <r>] [--max-rating <r>] [--no-ambiguity] [--verbose]";
+const DEFAULT_NODES: u64 = 200_000;
+const BUCKET_WIDTH: u32 = 200;
+// a second move only counts as another solution if it keeps at least this score
+const WINNING_SCORE: i32 = 300;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    pub moves: Vec<String>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+}
+
+// column positions taken from the header, defaulting to the lichess layout
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    id: usize,
+    fen: usize,
+    moves: usize,
+    rating: usize,
+    themes: usize,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self { id: 0, fen: 1, moves: 2, rating: 3, themes: 7 }
+    }
+}
+
+impl Columns {
+    fn from_header(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let find = |name: &str| fields.iter().position(|f| f.eq_ignore_ascii_case(name));
+        let id = find("PuzzleId")?;
+
+        let default = Self::default();
+        Some(Self {
+            id,
+            fen: find("FEN").unwrap_or(default.fen),
+            moves: find("Moves").unwrap_or(default.moves),
+            rating: find("Rating").unwrap_or(default.rating),
+            themes: find("Themes").unwrap_or(default.themes),
+        })
+    }
+}
+
+/// Streams puzzles from a CSV reader. A header line is optional; malformed
+/// lines are reported with their line number and do not stop the stream.
+pub fn read_puzzles<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Puzzle, String>> {
+    let mut columns = Columns::default();
+    reader.lines().enumerate().filter_map(move |(index, line)| {
+        let number = index + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(format!("line {}: {}", number, e))),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        if index == 0 {
+            if let Some(header) = Columns::from_header(line) {
+                columns = header;
+                return None;
+            }
+        }
+
+        Some(parse_puzzle(line, columns).map_err(|e| format!("line {}: {}", number, e)))
+    })
+}
+
+fn parse_puzzle(line: &str, columns: Columns) -> Result<Puzzle, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let field = |index: usize| fields.get(index).copied().unwrap_or("");
+
+    let id = field(columns.id).to_string();
+    let fen = field(columns.fen).to_string();
+    try_parse_fen(&fen).map_err(|e| format!("puzzle {}: invalid FEN '{}': {}", id, fen, e))?;
+
+    let moves: Vec<String> = field(columns.moves).split_whitespace().map(String::from).collect();
+    if moves.len() < 2 {
+        return Err(format!("puzzle {}: expected a setup move and a solution", id));
+    }
+
+    let rating = field(columns.rating).parse::<u32>()
+        .map_err(|_| format!("puzzle {}: invalid rating '{}'", id, field(columns.rating)))?;
+    let themes = field(columns.themes).split_whitespace().map(String::from).collect();
+
+    Ok(Puzzle { id, fen, moves, rating, themes })
+}
+
+/// Search budget for a whole puzzle, split evenly across the engine's moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Nodes(u64),
+    Time(Duration),
+}
+
+impl Budget {
+    pub fn per_move(self, moves: usize) -> SearchLimits {
+        let moves = moves.max(1);
+        match self {
+            Budget::Nodes(nodes) => SearchLimits::nodes((nodes / moves as u64).max(1)),
+            Budget::Time(time) => SearchLimits::movetime(time / moves as u32),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub solved: bool,
+    // index into `Puzzle::moves` of the first move the solver got wrong
+    pub failed_at: Option<usize>,
+    pub played: Option<String>,
+    // index into `Puzzle::moves` of the first solver move with a second good answer
+    pub ambiguous_at: Option<usize>,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/// Plays through one puzzle with the engine as the solver. With
+/// `find_ambiguity` every search keeps a second line to spot other winning
+/// moves, which roughly doubles its cost.
+pub fn solve(puzzle: &Puzzle, searcher: &mut Searcher, budget: Budget, find_ambiguity: bool) -> Result<Outcome, String> {
+    let lines = if find_ambiguity { 2 } else { 1 };
+    let limits = budget.per_move(puzzle.moves.len() / 2).multipv(lines);
+    let mut nodes = 0;
+    let mut index = 1;
+    let mut ambiguous_at = None;
+
+    searcher.clear();
+    let mut outcome = play_through(puzzle, |board| {
+        let result = searcher.search(board, limits.clone());
+        nodes += result.nodes;
+        if let [best, second, ..] = result.lines.as_slice() {
+            // any mate in one is accepted, so a second one is no ambiguity
+            if best.score != MATE - 1 && also_wins(best.score, second.score) {
+                ambiguous_at = ambiguous_at.or(Some(index));
+            }
+        }
+        index += 2;
+        result.best_move
+    })?;
+    outcome.nodes = nodes;
+    outcome.ambiguous_at = ambiguous_at;
+    Ok(outcome)
+}
+
+// whether the second best line is good enough to count as another solution
+fn also_wins(best: i32, second: i32) -> bool {
+    best >= WINNING_SCORE && second >= WINNING_SCORE
+}
+
+/// Plays the setup move, then asks `answer` for a move every time the solver
+/// is to move, replying with the recorded line. Any checkmating move is
+/// accepted in place of the expected one, as on lichess.
+pub fn play_through(puzzle: &Puzzle, mut answer: impl FnMut(&mut Board) -> Option<Move>) -> Result<Outcome, String> {
+    let mg = MoveGenerator::global();
+    let mut board: Board = try_parse_fen(&puzzle.fen)
+        .map_err(|e| format!("puzzle {}: {}", puzzle.id, e))?
+        .into();
+    let start = Instant::now();
+
+    let play = |board: &mut Board, text: &str| -> Result<(), String> {
+        let mv = mg.parse_move(board, text)
+            .ok_or_else(|| format!("puzzle {}: illegal move '{}'", puzzle.id, text))?;
+        board.make_move(mv);
+        Ok(())
+    };
+
+    play(&mut board, &puzzle.moves[0])?;
+    let failed = |index: usize, played: Option<Move>| Outcome {
+        solved: false,
+        failed_at: Some(index),
+        played: played.map(|mv| mv.to_string()),
+        ambiguous_at: None,
+        nodes: 0,
+        time: start.elapsed(),
+    };
+
+    for index in (1..puzzle.moves.len()).step_by(2) {
+        let expected = mg.parse_move(&mut board, &puzzle.moves[index])
+            .ok_or_else(|| format!("puzzle {}: illegal move '{}'", puzzle.id, puzzle.moves[index]))?;
+
+        let Some(played) = answer(&mut board) else {
+            return Ok(failed(index, None));
+        };
+
+        if is_mate(&mut board, played) {
+            break;
+        }
+        if played != expected {
+            return Ok(failed(index, Some(played)));
+        }
+
+        board.make_move(expected);
+        if let Some(reply) = puzzle.moves.get(index + 1) {
+            play(&mut board, reply)?;
+        }
+    }
+
+    Ok(Outcome { solved: true, failed_at: None, played: None, ambiguous_at: None, nodes: 0, time: start.elapsed() })
+}
+
+fn is_mate(board: &mut Board, mv: Move) -> bool {
+    let mg = MoveGenerator::global();
+    board.make_move(mv);
+    let mate = mg.legal_moves(board).is_empty() && mg.in_check(board, board.get_side_to_move());
+    board.unmake_move();
+    mate
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub solved: u32,
+    pub total: u32,
+}
+
+impl Tally {
+    fn add(&mut self, solved: bool) {
+        self.total += 1;
+        self.solved += solved as u32;
+    }
+
+    pub fn rate(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        100.0 * self.solved as f64 / self.total as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub overall: Tally,
+    pub buckets: BTreeMap<u32, Tally>,
+    pub themes: BTreeMap<String, Tally>,
+    // puzzles where the engine found a second winning move
+    pub ambiguous: u32,
+    pub nodes: u64,
+    pub time: Duration,
+    results: Vec<(u32, bool)>,
+}
+
+impl Report {
+    pub fn add(&mut self, puzzle: &Puzzle, outcome: &Outcome) {
+        self.overall.add(outcome.solved);
+        self.buckets.entry(puzzle.rating / BUCKET_WIDTH * BUCKET_WIDTH).or_default().add(outcome.solved);
+        for theme in &puzzle.themes {
+            self.themes.entry(theme.clone()).or_default().add(outcome.solved);
+        }
+        self.ambiguous += outcome.ambiguous_at.is_some() as u32;
+        self.nodes += outcome.nodes;
+        self.time += outcome.time;
+        self.results.push((puzzle.rating, outcome.solved));
+    }
+
+    /// Rating at which the observed results are most likely, treating each
+    /// puzzle as a game against an opponent of the puzzle's rating. The
+    /// estimate is clamped to 800 points beyond the rating range seen, which
+    /// is where it ends up when every puzzle is solved (or none are).
+    pub fn estimated_rating(&self) -> Option<f64> {
+        let min = self.results.iter().map(|&(r, _)| r).min()? as f64;
+        let max = self.results.iter().map(|&(r, _)| r).max()? as f64;
+        let solved = self.overall.solved as f64;
+
+        // expected solves rise with rating, so bisect on the difference
+        let expected = |rating: f64| -> f64 {
+            self.results.iter()
+                .map(|&(r, _)| 1.0 / (1.0 + 10f64.powf((r as f64 - rating) / 400.0)))
+                .sum()
+        };
+
+        let (mut low, mut high) = (min - 800.0, max + 800.0);
+        for _ in 0..64 {
+            let mid = (low + high) / 2.0;
+            if expected(mid) < solved {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Some((low + high) / 2.0)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "solved {}/{} ({:.1}%)", self.overall.solved, self.overall.total, self.overall.rate())?;
+        if let Some(rating) = self.estimated_rating() {
+            writeln!(f, "estimated rating {:.0}", rating)?;
+        }
+        writeln!(f, "ambiguous {}", self.ambiguous)?;
+        let nps = (self.nodes as f64 / self.time.as_secs_f64().max(1e-9)) as u64;
+        writeln!(f, "nodes {} time {}ms nps {}", self.nodes, self.time.as_millis(), nps)?;
+
+        writeln!(f, "\nby rating")?;
+        for (bucket, tally) in &self.buckets {
+            writeln!(f, "  {:>4}-{:<4} {:>5}/{:<5} {:>5.1}%", bucket, bucket + BUCKET_WIDTH - 1, tally.solved, tally.total, tally.rate())?;
+        }
+
+        writeln!(f, "\nby theme")?;
+        for (theme, tally) in &self.themes {
+            writeln!(f, "  {:<20} {:>5}/{:<5} {:>5.1}%", theme, tally.solved, tally.total, tally.rate())?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args.first().filter(|a| !a.starts_with("--")).ok_or(USAGE)?;
+    let mut budget = Budget::Nodes(DEFAULT_NODES);
+    let mut limit = usize::MAX;
+    let mut min_rating = 0;
+    let mut max_rating = u32::MAX;
+    let mut verbose = false;
+    let mut find_ambiguity = true;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().and_then(|v| v.parse::<u64>().ok()).ok_or(USAGE);
+        match arg.as_str() {
+            "--nodes" => budget = Budget::Nodes(value()?),
+            "--time" => budget = Budget::Time(Duration::from_millis(value()?)),
+            "--limit" => limit = value()? as usize,
+            "--min-rating" => min_rating = value()? as u32,
+            "--max-rating" => max_rating = value()? as u32,
+            "--no-ambiguity" => find_ambiguity = false,
+            "--verbose" => verbose = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut searcher = Searcher::new(DEFAULT_HASH_MB);
+    let mut report = Report::default();
+
+    let puzzles = read_puzzles(BufReader::new(file))
+        .filter(|p| p.as_ref().map_or(true, |p| (min_rating..=max_rating).contains(&p.rating)))
+        .take(limit);
+
+    for puzzle in puzzles {
+        let puzzle = match puzzle {
+            Ok(puzzle) => puzzle,
+            Err(e) => {
+                eprintln!("skipping {}", e);
+                continue;
+            },
+        };
+        let outcome = match solve(&puzzle, &mut searcher, budget, find_ambiguity) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("skipping {}", e);
+                continue;
+            },
+        };
+
+        if verbose {
+            if let Some(index) = outcome.ambiguous_at {
+                println!("{} {} ambiguous: more than one move wins at {}", puzzle.id, puzzle.rating, puzzle.moves[index]);
+            }
+            match (outcome.failed_at, &outcome.played) {
+                (None, _) => println!("{} {} solved", puzzle.id, puzzle.rating),
+                (Some(index), played) => println!(
+                    "{} {} failed: expected {} played {}",
+                    puzzle.id,
+                    puzzle.rating,
+                    puzzle.moves[index],
+                    played.as_deref().unwrap_or("none"),
+                ),
+            }
+        }
+        report.add(&puzzle, &outcome);
+    }
+
+    print!("{}", report);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../data/puzzles.csv");
+
+    fn fixture() -> Vec<Puzzle> {
+        read_puzzles(FIXTURE.as_bytes()).collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_read_puzzles() {
+        let puzzles = fixture();
+        assert!(puzzles.len() >= 10);
+        assert_eq!(puzzles[0].id, "rr001");
+        assert_eq!(puzzles[0].moves, vec!["b7b2", "d1d8"]);
+        assert!(puzzles[0].themes.contains(&"backRankMate".to_string()));
+
+        // headerless lines use the lichess column order
+        let line = "abc,8/8/8/8/8/8/8/K1k5 w - - 0 1,a1a2 c1c2,1500,0,0,0,endgame,,";
+        let parsed: Vec<_> = read_puzzles(line.as_bytes()).collect();
+        assert_eq!(parsed[0].as_ref().unwrap().rating, 1500);
+
+        let bad = "PuzzleId,FEN,Moves,Rating\nxyz,not a fen,e2e4,1000\nbad,8/8/8 w - - 0 1,a1a2 c1c2,1000\nok,8/8/8/8/8/8/8/K1k5 w - - 0 1,a1a2 c1c2,1500";
+        let parsed: Vec<_> = read_puzzles(bad.as_bytes()).collect();
+        assert!(parsed[0].as_ref().unwrap_err().starts_with("line 2"));
+        assert!(parsed[1].as_ref().unwrap_err().starts_with("line 3"));
+        assert_eq!(parsed[2].as_ref().unwrap().id, "ok");
+    }
+
+    #[test]
+    fn test_solves_fixture() {
+        let mut searcher = Searcher::new(4);
+        let mut report = Report::default();
+        for puzzle in fixture() {
+            let outcome = solve(&puzzle, &mut searcher, Budget::Nodes(DEFAULT_NODES), true).unwrap();
+            assert!(outcome.solved, "{} failed at {:?} with {:?}", puzzle.id, outcome.failed_at, outcome.played);
+            report.add(&puzzle, &outcome);
+        }
+        assert_eq!(report.overall.solved, report.overall.total);
+        assert_eq!(report.themes["mateIn2"].total, 4);
+    }
+
+    #[test]
+    fn test_accepts_any_mate() {
+        // both rooks mate on the back rank; the recorded move is the other one
+        let puzzle = Puzzle {
+            id: "alt".to_string(),
+            fen: "6k1/5ppp/8/8/8/8/R4PPP/1R4K1 b - - 0 1".to_string(),
+            moves: vec!["g8h8".to_string(), "a2a8".to_string()],
+            rating: 600,
+            themes: vec![],
+        };
+        let mut searcher = Searcher::new(1);
+        assert!(solve(&puzzle, &mut searcher, Budget::Nodes(10_000), true).unwrap().solved);
+
+        // a puzzle built by hand skips read_puzzles, so its FEN is checked again
+        let broken = Puzzle { fen: "6k1/5ppp/8 b - - 0 1".to_string(), ..puzzle };
+        assert!(play_through(&broken, |_| None).is_err());
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        // after the queen blunders onto d5 both the rook and the knight take it
+        let mut puzzle = Puzzle {
+            id: "two".to_string(),
+            fen: "6k1/5ppp/8/q7/5N2/8/5PPP/3R2K1 b - - 0 1".to_string(),
+            moves: vec!["a5d5".to_string(), "d1d5".to_string()],
+            rating: 800,
+            themes: vec![],
+        };
+        let mut searcher = Searcher::new(1);
+        assert_eq!(solve(&puzzle, &mut searcher, Budget::Nodes(20_000), true).unwrap().ambiguous_at, Some(1));
+
+        // without the knight only the rook wins it
+        puzzle.fen = "6k1/5ppp/8/q7/8/8/5PPP/3R2K1 b - - 0 1".to_string();
+        let outcome = solve(&puzzle, &mut searcher, Budget::Nodes(20_000), true).unwrap();
+        assert!(outcome.solved);
+        assert_eq!(outcome.ambiguous_at, None);
+
+        assert!(also_wins(900, 850));
+        assert!(also_wins(MATE - 3, 400));
+        assert!(!also_wins(MATE - 3, 150));
+        assert!(!also_wins(0, -300));
+
+        // in an equal position a slightly worse move is no second solution
+        assert!(!also_wins(0, -150));
+        assert!(!also_wins(20, -100));
+        assert!(!also_wins(250, 200));
+
+        // with the second line switched off nothing is flagged
+        puzzle.fen = "6k1/5ppp/8/q7/5N2/8/5PPP/3R2K1 b - - 0 1".to_string();
+        let outcome = solve(&puzzle, &mut searcher, Budget::Nodes(20_000), false).unwrap();
+        assert_eq!(outcome.ambiguous_at, None);
+    }
+
+    #[test]
+    fn test_estimated_rating() {
+        let puzzle = |rating| Puzzle { id: String::new(), fen: String::new(), moves: vec![], rating, themes: vec![] };
+        let outcome = |solved| Outcome { solved, failed_at: None, played: None, ambiguous_at: None, nodes: 0, time: Duration::ZERO };
+
+        let mut report = Report::default();
+        report.add(&puzzle(1400), &outcome(true));
+        report.add(&puzzle(1600), &outcome(false));
+        let rating = report.estimated_rating().unwrap();
+        assert!((rating - 1500.0).abs() < 1.0);
+
+        report.add(&puzzle(1000), &outcome(false));
+        assert!(report.estimated_rating().unwrap() < 1500.0);
+        assert_eq!(report.buckets[&1400], Tally { solved: 1, total: 1 });
+    }
+}
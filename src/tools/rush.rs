@@ -0,0 +1,381 @@
+//! `rush` subcommand
+//!
+//! Puzzle Rush against a local puzzle file: puzzles are served in rising
+//! rating order under a global clock, and the run ends after a fixed number
+//! of mistakes. Either the engine or a human at the terminal can solve.
+use std::fmt;
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader, Write };
+use std::time::{ Duration, Instant };
+use crate::system::{ render, Board, Color, LcgRng, Move, MoveGenerator, RenderOptions, SearchLimits, Searcher, DEFAULT_HASH_MB };
+use super::puzzles::{ play_through, read_puzzles, Budget, Outcome, Puzzle };
+
+const USAGE: &str = "usage: rush-rs rush <file.csv> [--human] [--seed <n>] [--clock <secs>] [--strikes <n>] [--nodes <n> | --time <ms>]";
+const TIER_WIDTH: u32 = 100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RushConfig {
+    pub seed: u64,
+    pub clock: Duration,
+    pub strikes: u32,
+    // puzzles drawn from each rating tier before moving up
+    pub per_tier: usize,
+}
+
+impl Default for RushConfig {
+    fn default() -> Self {
+        Self { seed: 1, clock: Duration::from_secs(180), strikes: 3, per_tier: 2 }
+    }
+}
+
+/// Picks the puzzles for a run: a seeded shuffle, then `per_tier` puzzles
+/// from every 100 point rating tier in ascending order.
+pub fn ladder(mut puzzles: Vec<Puzzle>, config: &RushConfig) -> Vec<Puzzle> {
+    let mut rng = LcgRng::new(config.seed);
+    for i in (1..puzzles.len()).rev() {
+        // the high bits of an lcg are the well mixed ones
+        let j = ((rng.next_u64() >> 33) % (i as u64 + 1)) as usize;
+        puzzles.swap(i, j);
+    }
+    puzzles.sort_by_key(|p| p.rating / TIER_WIDTH);
+
+    let mut ladder = Vec::new();
+    let mut tier = None;
+    let mut taken = 0;
+    for puzzle in puzzles {
+        if tier != Some(puzzle.rating / TIER_WIDTH) {
+            tier = Some(puzzle.rating / TIER_WIDTH);
+            taken = 0;
+        }
+        if taken < config.per_tier {
+            taken += 1;
+            ladder.push(puzzle);
+        }
+    }
+    ladder.sort_by_key(|p| p.rating);
+    ladder
+}
+
+pub trait Solver {
+    /// Move to play in `board`, or `None` to give up on the puzzle.
+    fn answer(&mut self, board: &mut Board, deadline: Instant) -> Option<Move>;
+
+    /// Called once a puzzle is finished.
+    fn feedback(&mut self, _puzzle: &Puzzle, _outcome: &Outcome) {}
+
+    /// Set when the solver wants to end the run early.
+    fn quit(&self) -> bool {
+        false
+    }
+}
+
+pub struct EngineSolver {
+    searcher: Searcher,
+    limits: SearchLimits,
+}
+
+impl EngineSolver {
+    pub fn new(budget: Budget) -> Self {
+        let limits = match budget {
+            Budget::Nodes(nodes) => SearchLimits::nodes(nodes),
+            Budget::Time(time) => SearchLimits::movetime(time),
+        };
+        Self { searcher: Searcher::new(DEFAULT_HASH_MB), limits }
+    }
+}
+
+impl Solver for EngineSolver {
+    fn answer(&mut self, board: &mut Board, deadline: Instant) -> Option<Move> {
+        let mut limits = self.limits.clone();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        limits.movetime = Some(limits.movetime.map_or(remaining, |time| time.min(remaining)));
+        self.searcher.search(board, limits).best_move
+    }
+}
+
+/// Reads moves in coordinate notation from the terminal. The engine's move
+/// generator checks each answer, so illegal input is simply asked again.
+pub struct HumanSolver<R, W> {
+    input: R,
+    output: W,
+    quit: bool,
+}
+
+impl<R: BufRead, W: Write> HumanSolver<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output, quit: false }
+    }
+
+    fn prompt(&mut self, board: &mut Board, deadline: Instant) -> io::Result<Option<Move>> {
+        let mg = MoveGenerator::global();
+        let side = board.get_side_to_move();
+        // board from the solver's point of view
+        writeln!(self.output, "{}", render(board.piece_boards, RenderOptions { flipped: side == Color::Black, unicode: false }))?;
+        if let Some(last) = board.move_history().last() {
+            writeln!(self.output, "opponent played {}", last)?;
+        }
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            write!(self.output, "[{}s] {} to move: ", remaining.as_secs(), if side == Color::White { "white" } else { "black" })?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.quit = true;
+                return Ok(None);
+            }
+            match line.trim() {
+                "quit" => {
+                    self.quit = true;
+                    return Ok(None);
+                },
+                "skip" => return Ok(None),
+                text => match mg.parse_move(board, text) {
+                    Some(mv) => return Ok(Some(mv)),
+                    None => writeln!(self.output, "illegal move '{}'", text)?,
+                },
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Solver for HumanSolver<R, W> {
+    fn answer(&mut self, board: &mut Board, deadline: Instant) -> Option<Move> {
+        self.prompt(board, deadline).unwrap_or_else(|_| {
+            self.quit = true;
+            None
+        })
+    }
+
+    fn feedback(&mut self, puzzle: &Puzzle, outcome: &Outcome) {
+        let _ = match outcome.failed_at {
+            None => writeln!(self.output, "correct\n"),
+            Some(index) => writeln!(self.output, "wrong, the solution was {}\n", puzzle.moves[index..].join(" ")),
+        };
+    }
+
+    fn quit(&self) -> bool {
+        self.quit
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub id: String,
+    pub rating: u32,
+    pub solved: bool,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RushResult {
+    pub attempts: Vec<Attempt>,
+    // puzzles left out because their FEN or recorded moves are broken
+    pub unplayable: Vec<String>,
+    pub elapsed: Duration,
+    pub timed_out: bool,
+}
+
+impl RushResult {
+    pub fn score(&self) -> usize {
+        self.attempts.iter().filter(|a| a.solved).count()
+    }
+
+    pub fn mistakes(&self) -> usize {
+        self.attempts.len() - self.score()
+    }
+
+    /// Highest rated puzzle solved during the run.
+    pub fn rating_reached(&self) -> Option<u32> {
+        self.attempts.iter().filter(|a| a.solved).map(|a| a.rating).max()
+    }
+
+    pub fn average_time(&self) -> Duration {
+        let total: Duration = self.attempts.iter().map(|a| a.time).sum();
+        total / self.attempts.len().max(1) as u32
+    }
+}
+
+impl fmt::Display for RushResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, attempt) in self.attempts.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>3}. {:<8} {:>4} {:<7} {:>6}ms",
+                i + 1,
+                attempt.id,
+                attempt.rating,
+                if attempt.solved { "solved" } else { "missed" },
+                attempt.time.as_millis(),
+            )?;
+        }
+        for reason in &self.unplayable {
+            writeln!(f, "     skipped {}", reason)?;
+        }
+        writeln!(f, "score {} ({} mistakes){}", self.score(), self.mistakes(), if self.timed_out { ", out of time" } else { "" })?;
+        match self.rating_reached() {
+            Some(rating) => writeln!(f, "rating reached {}", rating)?,
+            None => writeln!(f, "rating reached -")?,
+        }
+        writeln!(f, "time {}ms, {}ms per puzzle", self.elapsed.as_millis(), self.average_time().as_millis())
+    }
+}
+
+/// Runs the ladder until the clock runs out, the strikes are used up, the
+/// puzzles run out or the solver quits. A puzzle finished after the flag
+/// fell does not count, and a broken one is skipped without a strike.
+pub fn rush(puzzles: &[Puzzle], config: &RushConfig, solver: &mut impl Solver) -> RushResult {
+    let start = Instant::now();
+    let deadline = start + config.clock;
+    let mut result = RushResult::default();
+
+    for puzzle in puzzles {
+        if Instant::now() >= deadline {
+            result.timed_out = true;
+            break;
+        }
+
+        let outcome = play_through(puzzle, |board| solver.answer(board, deadline));
+        if solver.quit() {
+            break;
+        }
+        if Instant::now() >= deadline {
+            result.timed_out = true;
+            break;
+        }
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                result.unplayable.push(e);
+                continue;
+            },
+        };
+
+        solver.feedback(puzzle, &outcome);
+        result.attempts.push(Attempt {
+            id: puzzle.id.clone(),
+            rating: puzzle.rating,
+            solved: outcome.solved,
+            time: outcome.time,
+        });
+        if result.mistakes() >= config.strikes as usize {
+            break;
+        }
+    }
+
+    result.elapsed = start.elapsed();
+    result
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args.first().filter(|a| !a.starts_with("--")).ok_or(USAGE)?;
+    let mut config = RushConfig::default();
+    let mut budget = Budget::Time(Duration::from_millis(500));
+    let mut human = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().and_then(|v| v.parse::<u64>().ok()).ok_or(USAGE);
+        match arg.as_str() {
+            "--human" => human = true,
+            "--seed" => config.seed = value()?,
+            "--clock" => config.clock = Duration::from_secs(value()?),
+            "--strikes" => config.strikes = value()? as u32,
+            "--nodes" => budget = Budget::Nodes(value()?),
+            "--time" => budget = Budget::Time(Duration::from_millis(value()?)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let puzzles: Vec<Puzzle> = read_puzzles(BufReader::new(file))
+        .filter_map(|p| p.map_err(|e| eprintln!("skipping {}", e)).ok())
+        .collect();
+    let puzzles = ladder(puzzles, &config);
+
+    let result = if human {
+        let stdin = io::stdin();
+        rush(&puzzles, &config, &mut HumanSolver::new(stdin.lock(), io::stdout()))
+    } else {
+        rush(&puzzles, &config, &mut EngineSolver::new(budget))
+    };
+    print!("{}", result);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Vec<Puzzle> {
+        read_puzzles(include_str!("../../data/puzzles.csv").as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ladder() {
+        let config = RushConfig { per_tier: 1, ..RushConfig::default() };
+        let ladder = ladder(fixture(), &config);
+        assert!(ladder.windows(2).all(|w| w[0].rating / TIER_WIDTH < w[1].rating / TIER_WIDTH));
+
+        // the same seed always serves the same run
+        let again = super::ladder(fixture(), &config);
+        let ids = |l: &[Puzzle]| l.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&ladder), ids(&again));
+    }
+
+    #[test]
+    fn test_engine_rush() {
+        let config = RushConfig::default();
+        let puzzles = ladder(fixture(), &config);
+        let result = rush(&puzzles, &config, &mut EngineSolver::new(Budget::Nodes(100_000)));
+        assert_eq!(result.score(), puzzles.len());
+        assert_eq!(result.rating_reached(), puzzles.last().map(|p| p.rating));
+    }
+
+    #[test]
+    fn test_strikes_end_run() {
+        let config = RushConfig { strikes: 2, ..RushConfig::default() };
+        let puzzles = ladder(fixture(), &config);
+
+        // skipping every puzzle
+        let input = "skip\n".repeat(puzzles.len());
+        let mut solver = HumanSolver::new(input.as_bytes(), Vec::new());
+        let result = rush(&puzzles, &config, &mut solver);
+        assert_eq!(result.attempts.len(), 2);
+        assert_eq!(result.score(), 0);
+        assert_eq!(result.rating_reached(), None);
+    }
+
+    #[test]
+    fn test_broken_puzzle_is_skipped() {
+        let mut puzzles = fixture();
+        puzzles.truncate(2);
+        // a recorded move that isn't legal in the position
+        puzzles[0].moves[0] = "a1a1".to_string();
+        let config = RushConfig::default();
+        let result = rush(&puzzles, &config, &mut EngineSolver::new(Budget::Nodes(20_000)));
+        assert_eq!(result.unplayable.len(), 1);
+        assert_eq!(result.attempts.len(), 1);
+        assert_eq!(result.mistakes(), 0);
+        assert!(result.to_string().contains("skipped puzzle"));
+    }
+
+    #[test]
+    fn test_human_solver() {
+        let puzzle = fixture().into_iter().find(|p| p.id == "rr005").unwrap();
+        let config = RushConfig::default();
+
+        // an illegal move is asked again, then the back rank line is played
+        let mut solver = HumanSolver::new("e1e8\nd1d8\nd8e8\n".as_bytes(), Vec::new());
+        let result = rush(&[puzzle], &config, &mut solver);
+        assert_eq!(result.score(), 1);
+
+        let output = String::from_utf8(solver.output).unwrap();
+        assert!(output.contains("illegal move 'e1e8'"));
+        assert!(output.contains("opponent played e2e8"));
+        assert!(output.contains("correct"));
+    }
+}
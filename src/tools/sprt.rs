@@ -0,0 +1,152 @@
+//! Match statistics
+//!
+//! Elo difference with a 95% confidence interval from win/draw/loss counts,
+//! and a sequential probability ratio test (GSPRT with the normal
+//! approximation, as used by fishtest and cutechess) between two Elo hypotheses.
+use std::fmt;
+
+/// Expected score for an Elo advantage.
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo advantage for an expected score in (0, 1).
+pub fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // variance of a single game's score
+    fn variance(&self) -> f64 {
+        let n = self.games().max(1) as f64;
+        let s = self.score();
+        (self.wins as f64 * (1.0 - s).powi(2) + self.draws as f64 * (0.5 - s).powi(2) + self.losses as f64 * s.powi(2)) / n
+    }
+
+    /// Elo difference and the half-width of its 95% confidence interval, or
+    /// None while one side has every point.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let s = self.score();
+        if self.games() == 0 || s <= 0.0 || s >= 1.0 {
+            return None;
+        }
+        let margin = 1.959964 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo_from_score((s - margin).max(1e-6));
+        let high = elo_from_score((s + margin).min(1.0 - 1e-6));
+        Some((elo_from_score(s), (high - low) / 2.0))
+    }
+}
+
+impl fmt::Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{} ={} -{} ({:.1}%)", self.wins, self.draws, self.losses, self.score() * 100.0)?;
+        match self.elo() {
+            Some((elo, margin)) => write!(f, ", Elo {:+.1} +/- {:.1}", elo, margin),
+            None => write!(f, ", Elo n/a"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    // elo0 is accepted: the change is not an improvement of elo1
+    H0,
+    // elo1 is accepted
+    H1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }
+    }
+}
+
+impl Sprt {
+    /// Log-likelihood ratio of elo1 against elo0 for the results so far.
+    pub fn llr(&self, tally: &Tally) -> f64 {
+        let variance = tally.variance();
+        if tally.games() == 0 || variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        (s1 - s0) * (2.0 * tally.score() - s0 - s1) * tally.games() as f64 / (2.0 * variance)
+    }
+
+    /// Lower and upper LLR bounds; crossing one ends the test.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    pub fn decision(&self, tally: &Tally) -> Option<Decision> {
+        let llr = self.llr(tally);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(Decision::H1)
+        } else if llr <= lower {
+            Some(Decision::H0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elo() {
+        assert!(elo_from_score(0.5).abs() < 1e-9);
+        assert!((elo_from_score(expected_score(100.0)) - 100.0).abs() < 1e-9);
+
+        let even = Tally { wins: 30, draws: 40, losses: 30 };
+        let (elo, margin) = even.elo().unwrap();
+        assert!(elo.abs() < 1e-9);
+        assert!(margin > 30.0 && margin < 70.0, "{}", margin);
+
+        // more games, tighter interval
+        let more = Tally { wins: 300, draws: 400, losses: 300 };
+        assert!(more.elo().unwrap().1 < margin / 3.0);
+
+        let strong = Tally { wins: 64, draws: 0, losses: 36 };
+        assert!((strong.elo().unwrap().0 - 100.0).abs() < 3.0);
+        assert_eq!(Tally { wins: 5, draws: 0, losses: 0 }.elo(), None);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+
+        assert_eq!(sprt.llr(&Tally::default()), 0.0);
+        assert_eq!(sprt.decision(&Tally { wins: 10, draws: 10, losses: 10 }), None);
+        assert_eq!(sprt.decision(&Tally { wins: 800, draws: 400, losses: 500 }), Some(Decision::H1));
+        assert_eq!(sprt.decision(&Tally { wins: 500, draws: 400, losses: 800 }), Some(Decision::H0));
+        assert!(sprt.llr(&Tally { wins: 60, draws: 20, losses: 40 }) > 0.0);
+    }
+}
@@ -0,0 +1,300 @@
+//! `testsuite` subcommand
+//!
+//! Runs EPD test suites such as WAC or STS. Every position is searched for a
+//! fixed budget and the engine's move is checked against the `bm` (best
+//! move), `am` (avoid move) and `dm` (mate in n) operations. Results are
+//! summarised per suite and can be written as a JSON report.
+use std::fmt;
+use std::fs::{ self, File };
+use std::io::{ BufRead, BufReader };
+use std::path::Path;
+use std::time::{ Duration, Instant };
+use crate::system::{ parse_epd, Board, Epd, Move, MoveGenerator, Searcher, DEFAULT_HASH_MB, MATE, MATE_BOUND };
+use super::puzzles::Budget;
+
+const USAGE: &str = "usage: rush-rs testsuite <file.epd>... [--nodes <n> | --time <ms>] [--json <file.json>] [--verbose]";
+const DEFAULT_TIME_MS: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionResult {
+    pub id: String,
+    // expected and avoided moves in SAN
+    pub best: Vec<String>,
+    pub avoid: Vec<String>,
+    pub mate: Option<u32>,
+    pub answer: Option<String>,
+    pub passed: bool,
+    pub score: i32,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/// Whether `answer` (found with `score`) satisfies the position's
+/// operations. Positions without `bm`, `am` or `dm` can't be checked.
+pub fn check(epd: &Epd, answer: Option<Move>, score: i32) -> Result<bool, String> {
+    let best = epd.best_moves()?;
+    let avoid = epd.avoid_moves()?;
+    let mate = epd.direct_mate();
+    if best.is_empty() && avoid.is_empty() && mate.is_none() {
+        return Err("no bm, am or dm operation".to_string());
+    }
+    let Some(answer) = answer else { return Ok(false) };
+
+    let mated_in = (score > MATE_BOUND).then(|| (MATE - score + 1) as u32 / 2);
+    Ok((best.is_empty() || best.contains(&answer))
+        && !avoid.contains(&answer)
+        && mate.is_none_or(|n| mated_in.is_some_and(|m| m <= n)))
+}
+
+/// Searches one position and checks the answer.
+pub fn run_position(epd: &Epd, searcher: &mut Searcher, budget: Budget) -> Result<PositionResult, String> {
+    // don't spend a search on a position that can't be checked
+    check(epd, None, 0)?;
+    let mg = MoveGenerator::global();
+    let mut board = epd.board.clone();
+    let san = |board: &mut Board, moves: Vec<Move>| moves.into_iter().map(|mv| mg.to_san(board, mv)).collect();
+    let best = san(&mut board, epd.best_moves()?);
+    let avoid = san(&mut board, epd.avoid_moves()?);
+
+    searcher.clear();
+    let start = Instant::now();
+    let result = searcher.search(&mut board, budget.per_move(1));
+    let time = start.elapsed();
+
+    Ok(PositionResult {
+        id: epd.id().unwrap_or("?").to_string(),
+        best,
+        avoid,
+        mate: epd.direct_mate(),
+        answer: result.best_move.map(|mv| mg.to_san(&mut board, mv)),
+        passed: check(epd, result.best_move, result.score)?,
+        score: result.score,
+        nodes: result.nodes,
+        time,
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SuiteReport {
+    pub name: String,
+    pub results: Vec<PositionResult>,
+    // lines that could not be parsed or checked
+    pub errors: Vec<String>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    pub fn rate(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        100.0 * self.passed() as f64 / self.results.len() as f64
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}/{} passed ({:.1}%)", self.name, self.passed(), self.results.len(), self.rate())?;
+        if !self.errors.is_empty() {
+            write!(f, ", {} skipped", self.errors.len())?;
+        }
+        let failed: Vec<&str> = self.results.iter().filter(|r| !r.passed).map(|r| r.id.as_str()).collect();
+        if !failed.is_empty() {
+            write!(f, "\n  failed: {}", failed.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every EPD record read from `reader`. Blank lines and lines starting
+/// with '#' are ignored; bad records are reported and skipped.
+pub fn run_suite<R: BufRead>(name: &str, reader: R, searcher: &mut Searcher, budget: Budget, verbose: bool) -> SuiteReport {
+    let mut report = SuiteReport { name: name.to_string(), ..SuiteReport::default() };
+    for (index, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                report.errors.push(format!("line {}: {}", index + 1, e));
+                break;
+            },
+        };
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        match parse_epd(&line).and_then(|epd| run_position(&epd, searcher, budget)) {
+            Ok(result) => {
+                if verbose {
+                    println!(
+                        "{} {} {:<8} expected {}{}",
+                        if result.passed { "pass" } else { "FAIL" },
+                        result.id,
+                        result.answer.as_deref().unwrap_or("none"),
+                        if result.best.is_empty() { "-".to_string() } else { result.best.join(" ") },
+                        if result.avoid.is_empty() { String::new() } else { format!(" avoid {}", result.avoid.join(" ")) },
+                    );
+                }
+                report.results.push(result);
+            },
+            Err(e) => report.errors.push(format!("line {}: {}", index + 1, e)),
+        }
+    }
+    report
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|s| json_string(s)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Machine-readable report of a whole run.
+pub fn to_json(reports: &[SuiteReport], budget: Budget) -> String {
+    let budget = match budget {
+        Budget::Nodes(nodes) => format!("{{\"nodes\": {}}}", nodes),
+        Budget::Time(time) => format!("{{\"time_ms\": {}}}", time.as_millis()),
+    };
+    let suites: Vec<String> = reports.iter().map(|report| {
+        let positions: Vec<String> = report.results.iter().map(|r| {
+            format!(
+                "        {{\"id\": {}, \"best\": {}, \"avoid\": {}, \"mate\": {}, \"answer\": {}, \"passed\": {}, \"score\": {}, \"nodes\": {}, \"time_ms\": {}}}",
+                json_string(&r.id),
+                json_list(&r.best),
+                json_list(&r.avoid),
+                r.mate.map_or("null".to_string(), |m| m.to_string()),
+                r.answer.as_deref().map_or("null".to_string(), json_string),
+                r.passed,
+                r.score,
+                r.nodes,
+                r.time.as_millis(),
+            )
+        }).collect();
+        format!(
+            "    {{\n      \"name\": {},\n      \"passed\": {},\n      \"total\": {},\n      \"errors\": {},\n      \"positions\": [\n{}\n      ]\n    }}",
+            json_string(&report.name),
+            report.passed(),
+            report.results.len(),
+            json_list(&report.errors),
+            positions.join(",\n"),
+        )
+    }).collect();
+    format!("{{\n  \"budget\": {},\n  \"suites\": [\n{}\n  ]\n}}\n", budget, suites.join(",\n"))
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut files = Vec::new();
+    let mut budget = Budget::Time(Duration::from_millis(DEFAULT_TIME_MS));
+    let mut json = None;
+    let mut verbose = false;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--nodes" => budget = Budget::Nodes(value()?.parse().map_err(|_| USAGE)?),
+            "--time" => budget = Budget::Time(Duration::from_millis(value()?.parse().map_err(|_| USAGE)?)),
+            "--json" => json = Some(value()?.clone()),
+            "--verbose" => verbose = true,
+            flag if flag.starts_with("--") => return Err(USAGE.to_string()),
+            path => files.push(path.to_string()),
+        }
+    }
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut searcher = Searcher::new(DEFAULT_HASH_MB);
+    let mut reports = Vec::new();
+    for path in &files {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let name = Path::new(path).file_stem().map_or(path.clone(), |s| s.to_string_lossy().into_owned());
+        let report = run_suite(&name, BufReader::new(file), &mut searcher, budget, verbose);
+        for error in &report.errors {
+            eprintln!("{}: skipping {}", path, error);
+        }
+        println!("{}", report);
+        reports.push(report);
+    }
+
+    let passed: usize = reports.iter().map(SuiteReport::passed).sum();
+    let total: usize = reports.iter().map(|r| r.results.len()).sum();
+    if reports.len() > 1 {
+        println!("total: {}/{} passed", passed, total);
+    }
+    if let Some(path) = json {
+        fs::write(&path, to_json(&reports, budget)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUITE: &str = "# a tiny suite
+6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd8#; id \"mate.1\";
+6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - am Kf1; dm 1; id \"mate.2\";
+r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Qe2; id \"wrong\";
+6k1/8/8/8/8/8/8/6K1 w - - id \"nothing\";
+
+not an epd
+";
+
+    #[test]
+    fn test_run_suite() {
+        let mut searcher = Searcher::new(1);
+        let report = run_suite("tiny", SUITE.as_bytes(), &mut searcher, Budget::Nodes(5_000), false);
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.passed(), 2);
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.errors[0], "line 5: no bm, am or dm operation");
+        assert!(report.errors[1].starts_with("line 7: "));
+
+        let first = &report.results[0];
+        assert_eq!(first.id, "mate.1");
+        assert_eq!(first.best, vec!["Rd8#"]);
+        assert_eq!(first.answer.as_deref(), Some("Rd8#"));
+        assert!(report.to_string().starts_with("tiny: 2/3 passed (66.7%), 2 skipped"));
+    }
+
+    #[test]
+    fn test_check() {
+        let epd = parse_epd("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - am Kf1; dm 1;").unwrap();
+        let mut board = epd.board.clone();
+        let mg = MoveGenerator::global();
+        let rd8 = mg.parse_san(&mut board, "Rd8#");
+        let kf1 = mg.parse_san(&mut board, "Kf1");
+        assert_eq!(check(&epd, rd8, MATE - 1), Ok(true));
+        assert_eq!(check(&epd, rd8, 300), Ok(false));
+        assert_eq!(check(&epd, kf1, MATE - 1), Ok(false));
+        assert_eq!(check(&epd, None, 0), Ok(false));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut searcher = Searcher::new(1);
+        let report = run_suite("tiny", SUITE.as_bytes(), &mut searcher, Budget::Nodes(5_000), false);
+        let json = to_json(&[report], Budget::Nodes(5_000));
+        assert!(json.starts_with("{\n  \"budget\": {\"nodes\": 5000},"));
+        assert!(json.contains("{\"id\": \"mate.1\", \"best\": [\"Rd8#\"], \"avoid\": [], \"mate\": null, \"answer\": \"Rd8#\", \"passed\": true,"));
+        assert!(json.contains("\"passed\": 2,\n      \"total\": 3,"));
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
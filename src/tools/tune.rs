@@ -0,0 +1,325 @@
+//! `tune` subcommand
+//!
+//! Texel tuning of the evaluation weights. Every labelled position is reduced
+//! to its evaluation terms once; the scaling constant K of the win
+//! probability sigmoid is fitted to the current weights, and then the mean
+//! squared error between game results and predicted results is minimised
+//! with plain gradient descent or Adam. The result is printed as Rust source
+//! for `params.rs` and can be saved as a parameter file.
+use std::fs::File;
+use std::io::{ BufRead, BufReader };
+use crate::system::{ eval, game_phase, layout, s, try_parse_fen, Board, EvalParams, MoveGenerator, MAX_PHASE };
+
+const USAGE: &str = "usage: rush-rs tune <file>... [--epochs <n>] [--lr <rate>] [--optimizer adam|gd] [--params <file>] [--out <file>] [--limit <n>]";
+const DEFAULT_EPOCHS: usize = 200;
+
+/// Game result from white's point of view, 1.0 for a win.
+pub fn parse_result(text: &str) -> Option<f64> {
+    match text.trim_matches(|c: char| c == '"' || c == '[' || c == ']' || c == ';' || c.is_whitespace()) {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Reads a labelled position. Accepted layouts are `fen | score | result`,
+/// `fen [1.0]`, `fen c9 "1-0";` and a FEN followed by a bare result.
+pub fn parse_line(line: &str) -> Result<(Board, f64), String> {
+    let line = line.trim();
+    let (fen, result) = if line.contains('|') {
+        let fields: Vec<&str> = line.split('|').collect();
+        (fields[0], fields[fields.len() - 1])
+    } else if let Some(open) = line.find('[') {
+        (&line[..open], &line[open..])
+    } else if let Some(c9) = line.find(" c9 ") {
+        (&line[..c9], &line[c9 + 4..])
+    } else {
+        line.rsplit_once(char::is_whitespace).ok_or("expected a FEN and a result")?
+    };
+
+    let result = parse_result(result).ok_or_else(|| format!("invalid result '{}'", result.trim()))?;
+    // EPD-style lines have no move counters
+    let fen = fen.trim();
+    let parsed = if fen.split_whitespace().count() == 4 {
+        try_parse_fen(&format!("{} 0 1", fen))
+    } else {
+        try_parse_fen(fen)
+    };
+    Ok((parsed?.into(), result))
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub features: Vec<(usize, i32)>,
+    // middlegame share of the taper, 0.0 ..= 1.0
+    pub phase: f64,
+    pub result: f64,
+}
+
+impl Entry {
+    pub fn new(board: &Board, result: f64) -> Self {
+        Self {
+            features: eval::features(board).0,
+            phase: game_phase(&board.piece_boards) as f64 / MAX_PHASE as f64,
+            result,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Optimizer {
+    GradientDescent,
+    Adam,
+}
+
+impl Optimizer {
+    fn default_rate(self) -> f64 {
+        match self {
+            // gradients of the error are tiny in centipawn units
+            Optimizer::GradientDescent => 50_000.0,
+            Optimizer::Adam => 1.0,
+        }
+    }
+}
+
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+pub struct Tuner {
+    pub entries: Vec<Entry>,
+    // (middlegame, endgame) per parameter
+    pub weights: Vec<[f64; 2]>,
+}
+
+impl Tuner {
+    pub fn new(entries: Vec<Entry>, params: &EvalParams) -> Self {
+        let weights = params.weights.iter().map(|w| [w.mg as f64, w.eg as f64]).collect();
+        Self { entries, weights }
+    }
+
+    // white's point of view, without the rounding of the engine's evaluation
+    fn evaluate(&self, entry: &Entry) -> f64 {
+        let (mut mg, mut eg) = (0.0, 0.0);
+        for &(index, count) in &entry.features {
+            mg += self.weights[index][0] * count as f64;
+            eg += self.weights[index][1] * count as f64;
+        }
+        mg * entry.phase + eg * (1.0 - entry.phase)
+    }
+
+    pub fn error(&self, k: f64) -> f64 {
+        let total: f64 = self.entries.iter()
+            .map(|entry| (entry.result - sigmoid(k, self.evaluate(entry))).powi(2))
+            .sum();
+        total / self.entries.len().max(1) as f64
+    }
+
+    /// K minimising the error for the current weights, by golden-section search.
+    pub fn fit_k(&self) -> f64 {
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (0.0, 10.0);
+        while high - low > 1e-4 {
+            let a = high - ratio * (high - low);
+            let b = low + ratio * (high - low);
+            if self.error(a) < self.error(b) {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    fn gradient(&self, k: f64) -> Vec<[f64; 2]> {
+        let mut gradient = vec![[0.0; 2]; self.weights.len()];
+        let scale = k * std::f64::consts::LN_10 / 400.0;
+        for entry in &self.entries {
+            let p = sigmoid(k, self.evaluate(entry));
+            // derivative of (result - p)^2 with respect to the evaluation
+            let d = -2.0 * (entry.result - p) * p * (1.0 - p) * scale;
+            for &(index, count) in &entry.features {
+                gradient[index][0] += d * count as f64 * entry.phase;
+                gradient[index][1] += d * count as f64 * (1.0 - entry.phase);
+            }
+        }
+        let n = self.entries.len().max(1) as f64;
+        for g in &mut gradient {
+            g[0] /= n;
+            g[1] /= n;
+        }
+        gradient
+    }
+
+    /// Runs `epochs` full-batch steps and reports the error after each one.
+    pub fn tune(&mut self, k: f64, epochs: usize, optimizer: Optimizer, rate: f64, mut report: impl FnMut(usize, f64)) {
+        const BETA1: f64 = 0.9;
+        const BETA2: f64 = 0.999;
+        const EPSILON: f64 = 1e-8;
+        let mut momentum = vec![[0.0; 2]; self.weights.len()];
+        let mut velocity = vec![[0.0; 2]; self.weights.len()];
+
+        for epoch in 1..=epochs {
+            let gradient = self.gradient(k);
+            for (i, g) in gradient.iter().enumerate() {
+                for phase in 0..2 {
+                    self.weights[i][phase] -= match optimizer {
+                        Optimizer::GradientDescent => rate * g[phase],
+                        Optimizer::Adam => {
+                            momentum[i][phase] = BETA1 * momentum[i][phase] + (1.0 - BETA1) * g[phase];
+                            velocity[i][phase] = BETA2 * velocity[i][phase] + (1.0 - BETA2) * g[phase] * g[phase];
+                            let m = momentum[i][phase] / (1.0 - BETA1.powi(epoch as i32));
+                            let v = velocity[i][phase] / (1.0 - BETA2.powi(epoch as i32));
+                            rate * m / (v.sqrt() + EPSILON)
+                        },
+                    };
+                }
+            }
+            report(epoch, self.error(k));
+        }
+    }
+
+    pub fn params(&self) -> EvalParams {
+        let mut params = EvalParams::default();
+        for (weight, tuned) in params.weights.iter_mut().zip(&self.weights) {
+            *weight = s(tuned[0].round() as i32, tuned[1].round() as i32);
+        }
+        params
+    }
+}
+
+/// Reads labelled positions, skipping positions in check since they are not quiet.
+pub fn read_entries<R: BufRead>(reader: R, limit: usize, entries: &mut Vec<Entry>) -> Result<usize, String> {
+    let mg = MoveGenerator::global();
+    let mut skipped = 0;
+    for (index, line) in reader.lines().enumerate() {
+        if entries.len() >= limit {
+            break;
+        }
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let (board, result) = parse_line(&line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        if mg.in_check(&board, board.side_to_move) {
+            skipped += 1;
+            continue;
+        }
+        entries.push(Entry::new(&board, result));
+    }
+    Ok(skipped)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut files = Vec::new();
+    let mut epochs = DEFAULT_EPOCHS;
+    let mut rate = None;
+    let mut optimizer = Optimizer::Adam;
+    let mut params = EvalParams::default();
+    let mut out = None;
+    let mut limit = usize::MAX;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--epochs" => epochs = value()?.parse().map_err(|_| USAGE)?,
+            "--lr" => rate = Some(value()?.parse().map_err(|_| USAGE)?),
+            "--optimizer" => optimizer = match value()?.as_str() {
+                "adam" => Optimizer::Adam,
+                "gd" => Optimizer::GradientDescent,
+                _ => return Err(USAGE.to_string()),
+            },
+            "--params" => params = EvalParams::load(value()?)?,
+            "--out" => out = Some(value()?.clone()),
+            "--limit" => limit = value()?.parse().map_err(|_| USAGE)?,
+            flag if flag.starts_with("--") => return Err(USAGE.to_string()),
+            path => files.push(path.to_string()),
+        }
+    }
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut entries = Vec::new();
+    for path in &files {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let skipped = read_entries(BufReader::new(file), limit, &mut entries).map_err(|e| format!("{}: {}", path, e))?;
+        if skipped > 0 {
+            eprintln!("{}: skipped {} positions in check", path, skipped);
+        }
+    }
+    if entries.is_empty() {
+        return Err("no positions to tune on".to_string());
+    }
+    eprintln!("{} positions, {} parameters", entries.len(), layout::NUM_PARAMS);
+
+    let mut tuner = Tuner::new(entries, &params);
+    let k = tuner.fit_k();
+    eprintln!("K = {:.4}, error {:.6}", k, tuner.error(k));
+    tuner.tune(k, epochs, optimizer, rate.unwrap_or(optimizer.default_rate()), |epoch, error| {
+        eprintln!("epoch {} error {:.6}", epoch, error);
+    });
+
+    params = tuner.params();
+    if let Some(path) = out {
+        params.save(&path)?;
+    }
+    println!("{}", params.to_rust());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = "# fen | score | result
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 0 | 0.5
+6k1/5ppp/8/8/8/8/3Q1PPP/6K1 w - - 0 1 | 900 | 1.0
+6k1/5ppp/8/8/8/3q4/5PPP/6K1 w - - 0 1 | -900 | 0-1
+r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - [0.5]
+6k1/8/8/8/8/8/5PPP/6K1 b - - c9 \"1-0\";
+6k1/8/8/8/8/8/5PPP/6K1 w - - 1-0
+6k1/5ppp/8/8/8/8/5PPP/5RK1 w - - 0 1 | 500 | 1/2-1/2
+3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1 | -30000 | 1-0
+";
+
+    fn tuner() -> Tuner {
+        let mut entries = Vec::new();
+        // the last position is in check
+        assert_eq!(read_entries(DATA.as_bytes(), usize::MAX, &mut entries), Ok(1));
+        Tuner::new(entries, &EvalParams::default())
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let results: Vec<f64> = DATA.lines().skip(1).map(|line| parse_line(line).unwrap().1).collect();
+        assert_eq!(results, vec![0.5, 1.0, 0.0, 0.5, 1.0, 1.0, 0.5, 1.0]);
+        assert_eq!(parse_line(DATA.lines().nth(5).unwrap()).unwrap().0.side_to_move, crate::system::Color::Black);
+        assert!(parse_line("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1 | 0 | 2-0").is_err());
+        assert!(parse_line("1-0").is_err());
+    }
+
+    #[test]
+    fn test_fit_k() {
+        let tuner = tuner();
+        assert_eq!(tuner.entries.len(), 7);
+        let k = tuner.fit_k();
+        assert!(k > 0.0 && k < 10.0, "{}", k);
+        assert!(tuner.error(k) <= tuner.error(k * 0.5) && tuner.error(k) <= tuner.error(k * 2.0));
+    }
+
+    #[test]
+    fn test_tuning_reduces_error() {
+        for optimizer in [Optimizer::Adam, Optimizer::GradientDescent] {
+            let mut tuner = tuner();
+            let k = tuner.fit_k();
+            let before = tuner.error(k);
+            let mut errors = Vec::new();
+            tuner.tune(k, 20, optimizer, optimizer.default_rate(), |_, error| errors.push(error));
+            assert_eq!(errors.len(), 20);
+            assert!(errors[19] < before, "{:?}: {} -> {}", optimizer, before, errors[19]);
+        }
+    }
+}